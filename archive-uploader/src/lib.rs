@@ -1,10 +1,15 @@
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use anyhow::Result;
 use bytes::Bytes;
-use rusoto_s3::{HeadBucketRequest, PutObjectRequest, S3Client, S3};
+use rusoto_core::RusotoError;
+use rusoto_s3::{
+    DeleteObjectRequest, GetObjectError, GetObjectRequest, HeadBucketRequest, HeadObjectError,
+    HeadObjectRequest, ListObjectsV2Request, PutObjectRequest, S3Client, S3,
+};
 use serde::{Deserialize, Serialize};
+use tokio::io::AsyncReadExt;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
@@ -34,12 +39,39 @@ pub struct ArchiveUploaderConfig {
     /// AWS API access credentials
     #[serde(default)]
     pub credentials: Option<AwsCredentials>,
+
+    /// Whether the bucket is shared between multiple instances.
+    /// Archives are downloaded from the bucket when possible and each archive
+    /// is usually uploaded only by the instance which holds its upload lease.
+    ///
+    /// NOTE: the lease only reduces duplicate uploads, it doesn't guarantee
+    /// mutual exclusion. Archives are stored by their id, so a duplicate upload
+    /// overwrites the object with the same archive.
+    ///
+    /// Lease objects (`{archive_id}.lease`) are deleted after the archive is
+    /// uploaded. Leases of the instances which stopped during the upload are
+    /// left in the bucket, so a lifecycle rule for them is recommended (Default: false)
+    #[serde(default)]
+    pub shared: bool,
+
+    /// Unique name of this instance used in upload leases (Default: generated on start)
+    #[serde(default)]
+    pub instance_id: Option<String>,
+
+    /// Upload lease duration. Another instance will take over the upload
+    /// after it expires (Default: 900)
+    #[serde(default = "default_upload_lease_sec")]
+    pub upload_lease_sec: u64,
 }
 
 fn default_archives_search_interval_sec() -> u64 {
     600
 }
 
+fn default_upload_lease_sec() -> u64 {
+    900
+}
+
 fn default_retry_interval_ms() -> u64 {
     1000
 }
@@ -87,14 +119,150 @@ impl ArchiveUploader {
             })
             .await?;
 
+        let instance_id = config.instance_id.unwrap_or_else(|| {
+            format!(
+                "{:x}-{:x}",
+                std::process::id(),
+                unix_time().as_nanos() as u64
+            )
+        });
+
         Ok(ArchiveUploader(Arc::new(SharedState {
             s3_client,
             bucket: config.bucket,
             archive_key_prefix: config.archive_key_prefix,
             retry_interval: Duration::from_millis(config.retry_interval_ms),
+            shared: config.shared,
+            instance_id,
+            upload_lease: Duration::from_secs(config.upload_lease_sec),
         })))
     }
 
+    /// Whether the bucket is shared between multiple instances
+    pub fn is_shared(&self) -> bool {
+        self.0.shared
+    }
+
+    /// Checks whether the archive was already uploaded
+    pub async fn is_uploaded(&self, archive_id: u32) -> Result<bool> {
+        let request = HeadObjectRequest {
+            bucket: self.0.bucket.clone(),
+            key: self.0.archive_key(archive_id),
+            ..Default::default()
+        };
+
+        match self.0.s3_client.head_object(request).await {
+            Ok(_) => Ok(true),
+            Err(RusotoError::Service(HeadObjectError::NoSuchKey(_))) => Ok(false),
+            Err(RusotoError::Unknown(response)) if response.status.as_u16() == 404 => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Tries to acquire the upload lease of the archive.
+    ///
+    /// NOTE: this is a best-effort lease, not a lock. Conditional writes are not
+    /// available here, so the lease is written and then read back after a short delay.
+    /// Concurrent instances can both see their own lease if the writes are delayed,
+    /// in which case the archive is uploaded twice. This is tolerated, because
+    /// the upload is idempotent by the archive key.
+    pub async fn try_acquire_upload_lease(&self, archive_id: u32) -> Result<bool> {
+        let key = self.0.lease_key(archive_id);
+
+        if let Some(lease) = self.0.load_lease(&key).await? {
+            if lease.owner != self.0.instance_id && lease.expires_at > unix_time().as_secs() {
+                return Ok(false);
+            }
+        }
+
+        let lease = UploadLease {
+            owner: self.0.instance_id.clone(),
+            expires_at: (unix_time() + self.0.upload_lease).as_secs(),
+        };
+        let body = Bytes::from(lease.to_string());
+        let body =
+            rusoto_core::ByteStream::new(futures_util::stream::once(async move { Ok(body) }));
+
+        self.0
+            .s3_client
+            .put_object(PutObjectRequest {
+                bucket: self.0.bucket.clone(),
+                key: key.clone(),
+                body: Some(body),
+                ..Default::default()
+            })
+            .await?;
+
+        tokio::time::sleep(LEASE_SETTLE_DELAY).await;
+
+        Ok(matches!(
+            self.0.load_lease(&key).await?,
+            Some(lease) if lease.owner == self.0.instance_id
+        ))
+    }
+
+    /// Removes the upload lease of the archive
+    pub async fn release_upload_lease(&self, archive_id: u32) -> Result<()> {
+        self.0
+            .s3_client
+            .delete_object(DeleteObjectRequest {
+                bucket: self.0.bucket.clone(),
+                key: self.0.lease_key(archive_id),
+                ..Default::default()
+            })
+            .await?;
+        Ok(())
+    }
+
+    /// Searches for an uploaded archive which contains the specified masterchain block.
+    ///
+    /// Only archives which are followed by another archive are considered complete.
+    /// `max_archive_len` is the max number of masterchain blocks in one archive
+    pub async fn find_archive(&self, mc_seq_no: u32, max_archive_len: u32) -> Result<Option<u32>> {
+        let start_after = mc_seq_no
+            .checked_sub(max_archive_len)
+            .map(|id| self.0.archive_key(id));
+
+        let output = self
+            .0
+            .s3_client
+            .list_objects_v2(ListObjectsV2Request {
+                bucket: self.0.bucket.clone(),
+                prefix: Some(self.0.archive_key_prefix.clone()),
+                start_after,
+                max_keys: Some(LIST_ARCHIVES_LIMIT),
+                ..Default::default()
+            })
+            .await?;
+
+        let mut archive_ids = output
+            .contents
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|object| {
+                object
+                    .key?
+                    .strip_prefix(&self.0.archive_key_prefix)?
+                    .parse::<u32>()
+                    .ok()
+            })
+            .collect::<Vec<_>>();
+        archive_ids.sort_unstable();
+
+        let next_index = archive_ids.partition_point(|&id| id <= mc_seq_no);
+        Ok(
+            match (next_index.checked_sub(1), archive_ids.get(next_index)) {
+                (Some(index), Some(_)) => Some(archive_ids[index]),
+                _ => None,
+            },
+        )
+    }
+
+    /// Downloads the archive data
+    pub async fn download(&self, archive_id: u32) -> Result<Option<Vec<u8>>> {
+        self.0.load_object(self.0.archive_key(archive_id)).await
+    }
+
     /// Prepares a new archive to upload
     pub fn prepare_upload(&self, archive_id: u32, archive_data: Vec<u8>) -> PreparedArchiveUpload {
         let content_md5 = Some(md5::compute(&archive_data)).map(|x| base64::encode(x.as_slice()));
@@ -105,7 +273,7 @@ impl ArchiveUploader {
         PreparedArchiveUpload {
             state: self.0.clone(),
             archive_id,
-            key: format!("{}{archive_id:09}", self.0.archive_key_prefix),
+            key: self.0.archive_key(archive_id),
             content_md5,
             content_length,
             body,
@@ -117,7 +285,7 @@ impl ArchiveUploader {
         let archive = self.prepare_upload(archive_id, archive_data);
         loop {
             match archive.try_upload().await {
-                Ok(()) => return,
+                Ok(()) => break,
                 Err(e) => {
                     tracing::error!(
                         archive_id = archive.archive_id,
@@ -127,6 +295,12 @@ impl ArchiveUploader {
                 }
             }
         }
+
+        if self.0.shared {
+            if let Err(e) = self.release_upload_lease(archive_id).await {
+                tracing::warn!(archive_id, "failed to release upload lease: {e:?}");
+            }
+        }
     }
 }
 
@@ -180,4 +354,94 @@ struct SharedState {
     bucket: String,
     archive_key_prefix: String,
     retry_interval: Duration,
+    shared: bool,
+    instance_id: String,
+    upload_lease: Duration,
+}
+
+impl SharedState {
+    fn archive_key(&self, archive_id: u32) -> String {
+        format!("{}{archive_id:09}", self.archive_key_prefix)
+    }
+
+    fn lease_key(&self, archive_id: u32) -> String {
+        format!("{}{archive_id:09}.lease", self.archive_key_prefix)
+    }
+
+    async fn load_lease(&self, key: &str) -> Result<Option<UploadLease>> {
+        Ok(match self.load_object(key.to_owned()).await? {
+            Some(data) => std::str::from_utf8(&data).ok().and_then(UploadLease::parse),
+            None => None,
+        })
+    }
+
+    async fn load_object(&self, key: String) -> Result<Option<Vec<u8>>> {
+        let request = GetObjectRequest {
+            bucket: self.bucket.clone(),
+            key,
+            ..Default::default()
+        };
+
+        let object = match self.s3_client.get_object(request).await {
+            Ok(object) => object,
+            Err(RusotoError::Service(GetObjectError::NoSuchKey(_))) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut data = Vec::new();
+        if let Some(body) = object.body {
+            body.into_async_read().read_to_end(&mut data).await?;
+        }
+        Ok(Some(data))
+    }
+}
+
+/// Upload lease object content: `{owner}:{expires_at}`
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct UploadLease {
+    owner: String,
+    /// Unix timestamp in seconds
+    expires_at: u64,
+}
+
+impl UploadLease {
+    fn parse(s: &str) -> Option<Self> {
+        let (owner, expires_at) = s.rsplit_once(':')?;
+        Some(Self {
+            owner: owner.to_owned(),
+            expires_at: expires_at.parse().ok()?,
+        })
+    }
+}
+
+impl std::fmt::Display for UploadLease {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.owner, self.expires_at)
+    }
+}
+
+fn unix_time() -> Duration {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+const LEASE_SETTLE_DELAY: Duration = Duration::from_secs(2);
+const LIST_ARCHIVES_LIMIT: i64 = 1000;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn upload_lease_format() {
+        let lease = UploadLease {
+            owner: "node:1".to_owned(),
+            expires_at: 1234,
+        };
+        assert_eq!(UploadLease::parse(&lease.to_string()), Some(lease));
+
+        assert_eq!(UploadLease::parse("node"), None);
+        assert_eq!(UploadLease::parse("node:abc"), None);
+    }
 }
@@ -1,6 +1,7 @@
 use bytesize::ByteSize;
 use std::net::SocketAddrV4;
 use std::path::PathBuf;
+use std::sync::Arc;
 
 use everscale_network::{adnl, dht, overlay, rldp};
 use rand::Rng;
@@ -8,7 +9,8 @@ use serde::{Deserialize, Serialize};
 use sysinfo::SystemExt;
 
 pub use self::node_keys::*;
-use crate::network::NeighboursOptions;
+use crate::engine::{ArchiveUploader, LeaderLock};
+use crate::network::{DhtPublishOptions, NeighboursOptions};
 
 mod node_keys;
 
@@ -20,19 +22,39 @@ pub struct NodeConfig {
 
     pub rocks_db_path: PathBuf,
     pub file_db_path: PathBuf,
+    /// Directory for the partially downloaded files (archives and states).
+    ///
+    /// Files are written into the `ton-indexer-partial` subdirectory, which is
+    /// cleared on startup. Other contents of the directory are not touched,
+    /// so it can be shared with other applications.
+    ///
+    /// Completely downloaded files are moved into the `archives` and `states`
+    /// subdirectories of the `file_db_path` and removed after they are imported.
+    ///
+    /// Default: `{file_db_path}/downloads`
+    pub download_tmp_path: Option<PathBuf>,
 
     pub state_gc_options: Option<StateGcOptions>,
     pub blocks_gc_options: Option<BlocksGcOptions>,
     pub shard_state_cache_options: Option<ShardStateCacheOptions>,
+    /// Free disk space monitoring. Disabled if `None`
+    pub disk_watchdog_options: Option<DiskWatchdogOptions>,
+    /// Pruning of the durable subscribers delivery records
+    pub subscriber_deliveries_options: SubscriberDeliveriesOptions,
+    /// Pruning of the engine events journal
+    pub event_journal_options: EventJournalOptions,
 
     pub db_options: DbOptions,
 
     pub archive_options: Option<ArchiveOptions>,
     pub sync_options: SyncOptions,
+    /// Active/standby mode for multiple instances. Disabled if `None`
+    pub leader_election_options: Option<LeaderElectionOptions>,
 
     pub adnl_options: adnl::NodeOptions,
     pub rldp_options: rldp::NodeOptions,
     pub dht_options: dht::NodeOptions,
+    pub dht_publish_options: DhtPublishOptions,
     pub overlay_shard_options: overlay::OverlayOptions,
     pub neighbours_options: NeighboursOptions,
 }
@@ -44,15 +66,21 @@ impl Default for NodeConfig {
             adnl_keys: Default::default(),
             rocks_db_path: "db/rocksdb".into(),
             file_db_path: "db/file".into(),
+            download_tmp_path: None,
             state_gc_options: None,
             blocks_gc_options: None,
             shard_state_cache_options: Some(Default::default()),
+            disk_watchdog_options: None,
+            subscriber_deliveries_options: Default::default(),
+            event_journal_options: Default::default(),
             archive_options: Some(Default::default()),
             db_options: Default::default(),
             sync_options: Default::default(),
+            leader_election_options: None,
             adnl_options: Default::default(),
             rldp_options: Default::default(),
             dht_options: Default::default(),
+            dht_publish_options: Default::default(),
             overlay_shard_options: Default::default(),
             neighbours_options: Default::default(),
         }
@@ -64,6 +92,32 @@ impl Default for NodeConfig {
 pub struct DbOptions {
     pub rocksdb_lru_capacity: ByteSize,
     pub cells_cache_size: ByteSize,
+    /// Compression of the last level of cells, where the cells
+    /// of old (persistent) states are mostly located.
+    ///
+    /// NOTE: persistent states are stored as cells in RocksDB, files in
+    /// `file_db_path` are temporary memory mapped downloads and are not compressed.
+    ///
+    /// Default: zstd
+    pub cells_bottommost_compression: DbCompression,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DbCompression {
+    None,
+    Lz4,
+    Zstd,
+}
+
+impl DbCompression {
+    pub(crate) fn as_rocksdb_option(&self) -> &'static str {
+        match self {
+            Self::None => "kNoCompression",
+            Self::Lz4 => "kLZ4Compression",
+            Self::Zstd => "kZSTD",
+        }
+    }
 }
 
 impl Default for DbOptions {
@@ -108,6 +162,7 @@ impl Default for DbOptions {
         Self {
             rocksdb_lru_capacity,
             cells_cache_size,
+            cells_bottommost_compression: DbCompression::Zstd,
         }
     }
 }
@@ -116,8 +171,31 @@ impl Default for DbOptions {
 #[serde(deny_unknown_fields)]
 pub struct ArchiveOptions {
     pub gc_interval: ArchivesGcInterval,
+    /// Interval of merging small old archives into larger ones.
+    /// Archives which were not uploaded yet are not merged.
+    /// Default: none (disabled)
+    #[serde(default)]
+    pub repack_interval_sec: Option<u64>,
     #[cfg(feature = "archive-uploader")]
     pub uploader_options: Option<archive_uploader::ArchiveUploaderConfig>,
+    /// Custom archives uploader. Has priority over the built-in S3 uploader
+    #[serde(skip)]
+    pub uploader: Option<Arc<dyn ArchiveUploader>>,
+}
+
+/// Only the leader instance notifies subscribers,
+/// standby instances keep syncing without side effects.
+///
+/// Standby instances don't apply masterchain blocks beyond the offset
+/// committed by the leader, so no blocks are lost after the takeover
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LeaderElectionOptions {
+    /// Lock file shared between instances for the built-in `flock` based lock
+    pub lock_path: Option<PathBuf>,
+    /// Custom leader lock. Has priority over the built-in file lock
+    #[serde(skip)]
+    pub lock: Option<Arc<dyn LeaderLock>>,
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
@@ -145,12 +223,62 @@ pub struct SyncOptions {
     pub old_blocks_policy: OldBlocksPolicy,
     /// Default: 16
     pub parallel_archive_downloads: usize,
+    /// Max number of archives downloaded from the same peer simultaneously.
+    /// Zero means no limit. Default: 2
+    pub max_archive_requests_per_peer: u32,
+    /// Max number of RLDP downloads (blocks and archives) from the same peer
+    /// at which new block downloads are still started. Zero means no limit. Default: 4
+    pub max_block_requests_per_peer: u32,
     /// Default: 1073741824 (1 GB)
     pub save_to_disk_threshold: usize,
+    /// Max memory held by downloaded archives, parsed block maps and cached states.
+    /// Archives are saved to disk, prefetched archives are not downloaded and
+    /// states are not cached when it is reached.
+    ///
+    /// NOTE: parsed block maps are required to make progress, so they are
+    /// accounted even if the budget is exceeded. The actual usage can overshoot
+    /// it by up to `parallel_archive_downloads` parsed archives.
+    ///
+    /// Default: 4294967296 (4 GB)
+    pub max_sync_memory: usize,
     /// Default: 32
     pub max_block_applier_depth: u32,
     /// Ignore archives. Default: false.
     pub force_use_get_next_block: bool,
+    /// Download and apply only masterchain blocks.
+    /// Shard blocks and states are not processed at all. Default: false
+    pub masterchain_only: bool,
+    /// Shards which blocks are stored and delivered to subscribers.
+    /// Other shards are only tracked through the masterchain blocks.
+    ///
+    /// NOTE: when an indexed shard is merged with a non-indexed one
+    /// (or split from it), the merged shard can't be applied from the
+    /// prev blocks. Such shards are skipped until the next persistent state
+    /// and then resumed from it.
+    ///
+    /// Default: empty (all shards)
+    pub indexed_shards: Vec<ShardFilter>,
+    /// Share received block broadcasts with neighbours after the block and
+    /// its proof were validated and stored.
+    ///
+    /// NOTE: the block is not necessarily applied at that moment. Only the
+    /// next masterchain block is applied right away, shard blocks are only
+    /// pre-applied and far ahead blocks are just stored.
+    ///
+    /// Default: false
+    pub rebroadcast_blocks: bool,
+    /// What to do when a subscriber callback (block, full state, blocks edge
+    /// or states GC hooks) fails. Default: halt
+    pub subscriber_error_policy: SubscriberErrorPolicy,
+    /// How thoroughly the stored data is checked on boot. Default: quick
+    pub consistency_check: ConsistencyCheckLevel,
+    /// Number of dedicated threads for shard state computation.
+    /// Default: number of CPUs
+    pub applier_threads: usize,
+    /// Maintain an index of accounts by their code hash.
+    /// When enabled, the index is filled from the latest stored states on boot.
+    /// When disabled, the existing index is removed. Default: false
+    pub index_code_hashes: bool,
 }
 
 impl Default for SyncOptions {
@@ -158,13 +286,63 @@ impl Default for SyncOptions {
         Self {
             old_blocks_policy: Default::default(),
             parallel_archive_downloads: 16,
+            max_archive_requests_per_peer: 2,
+            max_block_requests_per_peer: 4,
             save_to_disk_threshold: 1024 * 1024 * 1024,
+            max_sync_memory: 4 * 1024 * 1024 * 1024,
             max_block_applier_depth: 32,
             force_use_get_next_block: false,
+            masterchain_only: false,
+            indexed_shards: Vec::new(),
+            rebroadcast_blocks: false,
+            subscriber_error_policy: Default::default(),
+            consistency_check: Default::default(),
+            applier_threads: num_cpus::get(),
+            index_code_hashes: false,
         }
     }
 }
 
+impl SyncOptions {
+    /// Whether blocks of the specified shard should be downloaded and applied
+    pub fn is_shard_indexed(&self, shard: &ton_block::ShardIdent) -> bool {
+        if shard.is_masterchain() {
+            return true;
+        }
+        if self.masterchain_only {
+            return false;
+        }
+
+        self.indexed_shards.is_empty()
+            || self
+                .indexed_shards
+                .iter()
+                .any(|filter| filter.contains(shard))
+    }
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ShardFilter {
+    pub workchain: i32,
+    /// Shard prefix with tag (e.g. `0x4000000000000000`).
+    /// Default: none (the whole workchain)
+    #[serde(default)]
+    pub shard_prefix: Option<u64>,
+}
+
+impl ShardFilter {
+    pub fn contains(&self, shard: &ton_block::ShardIdent) -> bool {
+        shard.workchain_id() == self.workchain
+            && match self.shard_prefix {
+                Some(prefix) => {
+                    crate::utils::shard_prefixes_intersect(prefix, shard.shard_prefix_with_tag())
+                }
+                None => true,
+            }
+    }
+}
+
 #[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
 pub enum OldBlocksPolicy {
@@ -178,6 +356,48 @@ impl Default for OldBlocksPolicy {
     }
 }
 
+/// Subscriber error (or panic) handling strategy
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub enum SubscriberErrorPolicy {
+    /// Stop sync and return the error from the engine
+    Halt,
+    /// Retry processing the same block with an exponential backoff.
+    /// Falls back to `halt` after `max_attempts`
+    Retry {
+        min_interval_ms: u64,
+        max_interval_ms: u64,
+        max_attempts: Option<u32>,
+    },
+    /// Record the block id with the subscriber id into the storage and continue
+    Skip,
+}
+
+impl Default for SubscriberErrorPolicy {
+    fn default() -> Self {
+        Self::Halt
+    }
+}
+
+/// Storage consistency check performed on boot
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConsistencyCheckLevel {
+    /// Skip the check. Broken archives are still reported on startup
+    None,
+    /// Check flags of the last applied masterchain block, presence
+    /// of its state root and repair the last archive if it is broken
+    Quick,
+    /// Additionally load the state and compare its hash with the block
+    Full,
+}
+
+impl Default for ConsistencyCheckLevel {
+    fn default() -> Self {
+        Self::Quick
+    }
+}
+
 #[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 #[serde(default, deny_unknown_fields)]
 pub struct StateGcOptions {
@@ -196,6 +416,45 @@ impl Default for StateGcOptions {
     }
 }
 
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SubscriberDeliveriesOptions {
+    /// Number of the latest masterchain blocks which delivery records are kept.
+    /// Blocks older than that are delivered again if replayed.
+    /// Default: 100000
+    pub retention_mc_blocks: u32,
+    /// Default: 3600
+    pub prune_interval_sec: u64,
+}
+
+impl Default for SubscriberDeliveriesOptions {
+    fn default() -> Self {
+        Self {
+            retention_mc_blocks: 100000,
+            prune_interval_sec: 3600,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EventJournalOptions {
+    /// Events older than this are removed on boot and after each GC.
+    /// Default: 2592000 (30 days)
+    pub max_age_sec: Option<u64>,
+    /// Number of the latest events which are kept. Default: 100000
+    pub max_count: Option<u64>,
+}
+
+impl Default for EventJournalOptions {
+    fn default() -> Self {
+        Self {
+            max_age_sec: Some(30 * 24 * 3600),
+            max_count: Some(100000),
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 #[serde(default, deny_unknown_fields)]
 pub struct BlocksGcOptions {
@@ -229,15 +488,52 @@ pub enum BlocksGcKind {
     BeforePreviousPersistentState,
 }
 
+/// Free space thresholds for the disks with `rocks_db_path` and `file_db_path`.
+/// The smallest free space of the two is compared with thresholds
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DiskWatchdogOptions {
+    /// Free space check interval. Default: `10`
+    pub interval_sec: u64,
+    /// Run states, blocks and archives GC below this threshold. Default: `20 GB`
+    pub gc_threshold: ByteSize,
+    /// Pause archive downloads below this threshold. Default: `10 GB`
+    pub pause_downloads_threshold: ByteSize,
+    /// Halt storage writes and stop the engine before RocksDB fails with `ENOSPC`.
+    /// Default: `2 GB`
+    pub halt_threshold: ByteSize,
+}
+
+impl Default for DiskWatchdogOptions {
+    fn default() -> Self {
+        Self {
+            interval_sec: 10,
+            gc_threshold: ByteSize::gb(20),
+            pause_downloads_threshold: ByteSize::gb(10),
+            halt_threshold: ByteSize::gb(2),
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 #[serde(default, deny_unknown_fields)]
 pub struct ShardStateCacheOptions {
     /// LRU cache item duration. Default: `120`
     pub ttl_sec: u64,
+    /// Load states of the latest applied blocks into the cache on startup. Default: `true`
+    pub prewarm: bool,
+    /// Memory accounted for each cached state in `max_sync_memory`.
+    /// States share most of the cells, so this is roughly the size
+    /// of the cells updated by one block. Default: `16777216` (16 MB)
+    pub state_size_estimate: usize,
 }
 
 impl Default for ShardStateCacheOptions {
     fn default() -> Self {
-        Self { ttl_sec: 120 }
+        Self {
+            ttl_sec: 120,
+            prewarm: true,
+            state_size_estimate: 16 * 1024 * 1024,
+        }
     }
 }
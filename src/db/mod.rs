@@ -1,4 +1,5 @@
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
 use anyhow::{Context, Result};
@@ -8,12 +9,15 @@ use weedb::{Caches, WeeDb};
 pub use weedb::Stats as RocksdbStats;
 pub use weedb::{rocksdb, BoundedCfHandle, ColumnFamily, Table, UnboundedCfHandle};
 
-use crate::config::DbOptions;
+use crate::config::{DbCompression, DbOptions};
+
+pub use self::snapshot::DbSnapshot;
 
 pub mod refcount;
 pub mod tables;
 
 mod migrations;
+mod snapshot;
 
 pub struct Db {
     pub archives: Table<tables::Archives>,
@@ -27,8 +31,18 @@ pub struct Db {
     pub prev2: Table<tables::Prev2>,
     pub next1: Table<tables::Next1>,
     pub next2: Table<tables::Next2>,
+    pub engine_events: Table<tables::EngineEvents>,
+    pub config_history: Table<tables::ConfigHistory>,
+    pub validator_sets: Table<tables::ValidatorSets>,
+    pub libraries: Table<tables::Libraries>,
+    pub mc_blocks_by_utime: Table<tables::McBlocksByUtime>,
+    pub mc_shard_blocks: Table<tables::McShardBlocks>,
+    pub code_hashes: Table<tables::CodeHashes>,
+    pub code_hashes_by_address: Table<tables::CodeHashesByAddress>,
+    pub subscriber_deliveries: Table<tables::SubscriberDeliveries>,
 
     compaction_lock: tokio::sync::RwLock<()>,
+    writes_halted: AtomicBool,
     inner: WeeDb,
 }
 
@@ -37,6 +51,7 @@ impl Db {
         tracing::info!(
             rocksdb_lru_capacity = %options.rocksdb_lru_capacity,
             cells_cache_size = %options.cells_cache_size,
+            cells_bottommost_compression = ?options.cells_bottommost_compression,
             "opening DB"
         );
 
@@ -102,11 +117,38 @@ impl Db {
             .with_table::<tables::Next1>()
             .with_table::<tables::Next2>()
             .with_table::<tables::PackageEntries>()
+            .with_table::<tables::EngineEvents>()
+            .with_table::<tables::ConfigHistory>()
+            .with_table::<tables::ValidatorSets>()
+            .with_table::<tables::Libraries>()
+            .with_table::<tables::McBlocksByUtime>()
+            .with_table::<tables::McShardBlocks>()
+            .with_table::<tables::CodeHashes>()
+            .with_table::<tables::CodeHashesByAddress>()
+            .with_table::<tables::SubscriberDeliveries>()
             .build()
             .context("Failed building db")?;
 
         migrations::apply(&inner).context("Failed to apply migrations")?;
 
+        // NOTE: compression options are mutable, so they are applied to the opened table
+        if options.cells_bottommost_compression != DbCompression::Zstd {
+            let cells_cf = inner
+                .raw()
+                .cf_handle(tables::Cells::NAME)
+                .context("Cells table not found")?;
+            inner
+                .raw()
+                .set_options_cf(
+                    &cells_cf,
+                    &[(
+                        "bottommost_compression",
+                        options.cells_bottommost_compression.as_rocksdb_option(),
+                    )],
+                )
+                .context("Failed to set cells compression")?;
+        }
+
         Ok(Arc::new(Self {
             archives: inner.instantiate_table(),
             block_handles: inner.instantiate_table(),
@@ -119,7 +161,17 @@ impl Db {
             prev2: inner.instantiate_table(),
             next1: inner.instantiate_table(),
             next2: inner.instantiate_table(),
+            engine_events: inner.instantiate_table(),
+            config_history: inner.instantiate_table(),
+            validator_sets: inner.instantiate_table(),
+            libraries: inner.instantiate_table(),
+            mc_blocks_by_utime: inner.instantiate_table(),
+            mc_shard_blocks: inner.instantiate_table(),
+            code_hashes: inner.instantiate_table(),
+            code_hashes_by_address: inner.instantiate_table(),
+            subscriber_deliveries: inner.instantiate_table(),
             compaction_lock: tokio::sync::RwLock::default(),
+            writes_halted: AtomicBool::new(false),
             inner,
         }))
     }
@@ -129,6 +181,28 @@ impl Db {
         self.inner.raw()
     }
 
+    /// Makes all storage write paths fail with [`DbError::WritesHalted`]
+    pub fn halt_writes(&self) {
+        self.writes_halted.store(true, Ordering::Release);
+    }
+
+    pub fn resume_writes(&self) {
+        self.writes_halted.store(false, Ordering::Release);
+    }
+
+    /// Fails if writes were halted (e.g. due to exhausted disk space)
+    pub fn check_writes(&self) -> Result<()> {
+        if self.writes_halted.load(Ordering::Acquire) {
+            return Err(DbError::WritesHalted.into());
+        }
+        Ok(())
+    }
+
+    /// Captures a consistent view of the database
+    pub fn snapshot(&self) -> DbSnapshot<'_> {
+        DbSnapshot::new(self)
+    }
+
     pub fn get_memory_usage_stats(&self) -> Result<RocksdbStats> {
         self.inner.get_memory_usage_stats().map_err(From::from)
     }
@@ -225,7 +299,16 @@ impl Db {
                 prev1 => tables::Prev1,
                 prev2 => tables::Prev2,
                 next1 => tables::Next1,
-                next2 => tables::Next2
+                next2 => tables::Next2,
+                engine_events => tables::EngineEvents,
+                config_history => tables::ConfigHistory,
+                validator_sets => tables::ValidatorSets,
+                libraries => tables::Libraries,
+                mc_blocks_by_utime => tables::McBlocksByUtime,
+                mc_shard_blocks => tables::McShardBlocks,
+                code_hashes => tables::CodeHashes,
+                code_hashes_by_address => tables::CodeHashesByAddress,
+                subscriber_deliveries => tables::SubscriberDeliveries
             )
         })?;
 
@@ -245,3 +328,9 @@ impl Drop for Db {
         self.raw().cancel_all_background_work(true)
     }
 }
+
+#[derive(thiserror::Error, Debug)]
+pub enum DbError {
+    #[error("Writes are halted due to low disk space")]
+    WritesHalted,
+}
@@ -0,0 +1,57 @@
+use weedb::{rocksdb, ColumnFamily, Table};
+
+use super::Db;
+
+/// Consistent point-in-time view of the whole database.
+///
+/// All reads through the snapshot ignore writes which happened after its creation
+pub struct DbSnapshot<'a> {
+    db: &'a Db,
+    snapshot: rocksdb::Snapshot<'a>,
+}
+
+impl<'a> DbSnapshot<'a> {
+    pub(super) fn new(db: &'a Db) -> Self {
+        Self {
+            db,
+            snapshot: db.raw().snapshot(),
+        }
+    }
+
+    #[inline]
+    pub fn db(&self) -> &'a Db {
+        self.db
+    }
+
+    pub fn get<T, K>(
+        &self,
+        table: &Table<T>,
+        key: K,
+    ) -> Result<Option<rocksdb::DBPinnableSlice<'_>>, rocksdb::Error>
+    where
+        T: ColumnFamily,
+        K: AsRef<[u8]>,
+    {
+        let read_config = self.read_config(table);
+        self.db
+            .raw()
+            .get_pinned_cf_opt(&table.cf(), key, &read_config)
+    }
+
+    pub fn raw_iterator<T>(&self, table: &Table<T>) -> rocksdb::DBRawIterator<'_>
+    where
+        T: ColumnFamily,
+    {
+        let read_config = self.read_config(table);
+        self.db.raw().raw_iterator_cf_opt(&table.cf(), read_config)
+    }
+
+    fn read_config<T>(&self, table: &Table<T>) -> rocksdb::ReadOptions
+    where
+        T: ColumnFamily,
+    {
+        let mut read_config = table.new_read_config();
+        read_config.set_snapshot(&self.snapshot);
+        read_config
+    }
+}
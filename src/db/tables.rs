@@ -131,6 +131,10 @@ impl ColumnFamily for Cells {
         opts.set_optimize_filters_for_hits(true);
         // option is set for cf
         opts.set_compression_type(DBCompressionType::Lz4);
+        // cells of old (persistent) states are mostly located at the last level
+        // and are rarely read, so a better compression ratio is preferred there.
+        // Can be changed with `DbOptions::cells_bottommost_compression`
+        opts.set_bottommost_compression_type(DBCompressionType::Zstd);
     }
 }
 
@@ -221,6 +225,119 @@ impl ColumnFamily for Next2 {
     }
 }
 
+/// Stores engine events journal
+/// - Key: `u64 (BE)` (event id)
+/// - Value: `EngineEventRecord` (JSON)
+pub struct EngineEvents;
+impl ColumnFamily for EngineEvents {
+    const NAME: &'static str = "engine_events";
+
+    fn options(opts: &mut Options, caches: &Caches) {
+        default_block_based_table_factory(opts, caches);
+        opts.set_compression_type(DBCompressionType::Zstd);
+    }
+}
+
+/// Stores global config params changes
+/// - Key: `u32 (BE)` (param id), `u32 (BE)` (key block seqno)
+/// - Value: param cell BOC, empty if the param was removed
+pub struct ConfigHistory;
+impl ColumnFamily for ConfigHistory {
+    const NAME: &'static str = "config_history";
+
+    fn options(opts: &mut Options, caches: &Caches) {
+        default_block_based_table_factory(opts, caches);
+    }
+}
+
+/// Stores validator set transitions
+/// - Key: `u32 (BE)` (key block seqno)
+/// - Value: `ton_block::ValidatorSet` BOC
+pub struct ValidatorSets;
+impl ColumnFamily for ValidatorSets {
+    const NAME: &'static str = "validator_sets";
+
+    fn options(opts: &mut Options, caches: &Caches) {
+        default_block_based_table_factory(opts, caches);
+        opts.set_compression_type(DBCompressionType::Zstd);
+    }
+}
+
+/// Stores libraries published in the masterchain
+/// - Key: `ton_types::UInt256` (library hash)
+/// - Value: library code BOC
+///
+/// Also contains an indexed dictionary root hash under the `root_hash` key
+pub struct Libraries;
+impl ColumnFamily for Libraries {
+    const NAME: &'static str = "libraries";
+
+    fn options(opts: &mut Options, caches: &Caches) {
+        default_block_based_table_factory(opts, caches);
+        opts.set_compression_type(DBCompressionType::Zstd);
+    }
+}
+
+/// Maps masterchain block generation time to the block
+/// - Key: `gen_utime: u32 (BE), seqno: u32 (BE)`
+/// - Value: `ton_block::BlockIdExt`
+pub struct McBlocksByUtime;
+impl ColumnFamily for McBlocksByUtime {
+    const NAME: &'static str = "mc_blocks_by_utime";
+
+    fn options(opts: &mut Options, caches: &Caches) {
+        default_block_based_table_factory(opts, caches);
+    }
+}
+
+/// Stores top shard blocks of each masterchain block
+/// - Key: `seqno: u32 (BE)`
+/// - Value: `[ton_block::BlockIdExt]`
+pub struct McShardBlocks;
+impl ColumnFamily for McShardBlocks {
+    const NAME: &'static str = "mc_shard_blocks";
+
+    fn options(opts: &mut Options, caches: &Caches) {
+        default_block_based_table_factory(opts, caches);
+    }
+}
+
+/// Index of the accounts by their code hash
+/// - Key: `code_hash: [u8; 32], workchain: i8, account: [u8; 32]`
+/// - Value: empty
+pub struct CodeHashes;
+impl ColumnFamily for CodeHashes {
+    const NAME: &'static str = "code_hashes";
+
+    fn options(opts: &mut Options, caches: &Caches) {
+        default_block_based_table_factory(opts, caches);
+    }
+}
+
+/// Current code hash of the indexed accounts
+/// - Key: `workchain: i8, account: [u8; 32]`
+/// - Value: `code_hash: [u8; 32]`
+pub struct CodeHashesByAddress;
+impl ColumnFamily for CodeHashesByAddress {
+    const NAME: &'static str = "code_hashes_by_address";
+
+    fn options(opts: &mut Options, caches: &Caches) {
+        default_block_based_table_factory(opts, caches);
+    }
+}
+
+/// Blocks which were delivered to the durable subscribers
+/// - Key: `BlockIdShort (16 bytes), root_hash: [u8; 32], subscriber_id: [u8]`
+/// - Value: empty
+pub struct SubscriberDeliveries;
+impl ColumnFamily for SubscriberDeliveries {
+    const NAME: &'static str = "subscriber_deliveries";
+
+    fn options(opts: &mut Options, caches: &Caches) {
+        default_block_based_table_factory(opts, caches);
+    }
+}
+
 fn archive_data_merge(
     _: &[u8],
     current_value: Option<&[u8]>,
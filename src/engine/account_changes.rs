@@ -0,0 +1,323 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use tokio::sync::mpsc;
+use ton_block::HashmapAugType;
+
+use super::Engine;
+use crate::storage::BlockConnection;
+use crate::utils::*;
+
+impl Engine {
+    /// Streams all account changes from the blocks which were committed
+    /// in the masterchain blocks in range `(from_mc_seqno, to_mc_seqno]`.
+    ///
+    /// Changes are ordered by masterchain blocks. The stream ends after the first error
+    pub fn stream_account_changes(
+        self: &Arc<Self>,
+        from_mc_seqno: u32,
+        to_mc_seqno: u32,
+    ) -> mpsc::Receiver<Result<AccountChange>> {
+        const CHANNEL_CAPACITY: usize = 1024;
+
+        let (tx, rx) = mpsc::channel(CHANNEL_CAPACITY);
+
+        let engine = self.clone();
+        tokio::spawn(async move {
+            if let Err(e) = engine
+                .collect_account_changes(from_mc_seqno, to_mc_seqno, &tx)
+                .await
+            {
+                tx.send(Err(e)).await.ok();
+            }
+        });
+
+        rx
+    }
+
+    async fn collect_account_changes(
+        &self,
+        from_mc_seqno: u32,
+        to_mc_seqno: u32,
+        tx: &mpsc::Sender<Result<AccountChange>>,
+    ) -> Result<()> {
+        let block_handle_storage = self.storage.block_handle_storage();
+        let block_connection_storage = self.storage.block_connection_storage();
+
+        let mut mc_block_id = self.find_mc_block_id(from_mc_seqno)?;
+
+        while mc_block_id.seq_no < to_mc_seqno {
+            mc_block_id =
+                block_connection_storage.load_connection(&mc_block_id, BlockConnection::Next1)?;
+            let mc_seq_no = mc_block_id.seq_no;
+
+            let mc_block = self.load_block_for_changes(&mc_block_id).await?;
+
+            // Collect all shard blocks which were committed in this masterchain block
+            let mut walk = CommittedBlocksWalk::new(
+                mc_seq_no,
+                mc_block.shard_blocks()?.into_values().collect(),
+            );
+            let mut blocks = vec![mc_block];
+            while let Some(block_id) = walk.next(|block_id| {
+                Ok(block_handle_storage
+                    .load_handle(block_id)?
+                    .map(|handle| handle.masterchain_ref_seqno()))
+            })? {
+                let block = self.load_block_for_changes(&block_id).await?;
+                let (prev1, prev2) = block.construct_prev_id()?;
+                walk.add_prev(prev1, prev2);
+                blocks.push(block);
+            }
+
+            for block in blocks {
+                for change in extract_account_changes(mc_seq_no, block.id(), block.block())? {
+                    if tx.send(Ok(change)).await.is_err() {
+                        // Receiver was dropped
+                        return Ok(());
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Resolves the applied masterchain block by its seqno.
+    ///
+    /// NOTE: blocks are resolved through their handles, so the state of the
+    /// block is not required. Blocks removed by the blocks GC are not found
+    fn find_mc_block_id(&self, mc_seq_no: u32) -> Result<ton_block::BlockIdExt> {
+        let shard = ton_block::ShardIdent::masterchain();
+        if let Some(block_id) = self
+            .storage
+            .block_handle_storage()
+            .find_applied_block_id(&shard, mc_seq_no)?
+        {
+            return Ok(block_id);
+        }
+
+        // Fallback for the blocks without package entries (e.g. zerostate)
+        self.storage
+            .shard_state_storage()
+            .find_block_id(shard, mc_seq_no)?
+            .ok_or_else(|| AccountChangesError::MasterchainBlockNotFound.into())
+    }
+
+    async fn load_block_for_changes(&self, block_id: &ton_block::BlockIdExt) -> Result<BlockStuff> {
+        let handle = self
+            .storage
+            .block_handle_storage()
+            .load_handle(block_id)?
+            .ok_or(AccountChangesError::BlockNotFound)?;
+        self.storage.block_storage().load_block_data(&handle).await
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AccountChange {
+    /// Masterchain block in which the change was committed
+    pub mc_seq_no: u32,
+    pub block_id: ton_block::BlockIdExt,
+    pub account: ton_types::UInt256,
+    /// Account state hash before the block
+    pub old_hash: ton_types::UInt256,
+    /// Account state hash after the block
+    pub new_hash: ton_types::UInt256,
+    pub transaction_count: usize,
+}
+
+/// Walks back from the top shard blocks of the masterchain block
+/// through the blocks which were committed in it
+struct CommittedBlocksWalk {
+    mc_seq_no: u32,
+    pending: Vec<ton_block::BlockIdExt>,
+    visited: FastHashSet<ton_block::BlockIdExt>,
+}
+
+impl CommittedBlocksWalk {
+    fn new(mc_seq_no: u32, top_blocks: Vec<ton_block::BlockIdExt>) -> Self {
+        Self {
+            mc_seq_no,
+            pending: top_blocks,
+            visited: Default::default(),
+        }
+    }
+
+    /// Returns the next block committed in the masterchain block.
+    ///
+    /// `masterchain_ref_seqno` returns the masterchain seqno in which
+    /// the block was committed, or `None` if the block is unknown
+    fn next<F>(&mut self, mut masterchain_ref_seqno: F) -> Result<Option<ton_block::BlockIdExt>>
+    where
+        F: FnMut(&ton_block::BlockIdExt) -> Result<Option<u32>>,
+    {
+        while let Some(block_id) = self.pending.pop() {
+            if !self.visited.insert(block_id.clone()) {
+                continue;
+            }
+
+            match masterchain_ref_seqno(&block_id)? {
+                Some(mc_seq_no) if mc_seq_no == self.mc_seq_no => return Ok(Some(block_id)),
+                // Block was committed in one of the previous masterchain blocks
+                _ => continue,
+            }
+        }
+        Ok(None)
+    }
+
+    fn add_prev(&mut self, prev1: ton_block::BlockIdExt, prev2: Option<ton_block::BlockIdExt>) {
+        self.pending.push(prev1);
+        self.pending.extend(prev2);
+    }
+}
+
+fn extract_account_changes(
+    mc_seq_no: u32,
+    block_id: &ton_block::BlockIdExt,
+    block: &ton_block::Block,
+) -> Result<Vec<AccountChange>> {
+    let mut result = Vec::new();
+    block
+        .read_extra()?
+        .read_account_blocks()?
+        .iterate_objects(|account_block| {
+            let state_update = account_block.read_state_update()?;
+            result.push(AccountChange {
+                mc_seq_no,
+                block_id: block_id.clone(),
+                account: account_block.account_id().clone().get_next_hash()?,
+                old_hash: state_update.old_hash,
+                new_hash: state_update.new_hash,
+                transaction_count: account_block.transaction_count()?,
+            });
+            Ok(true)
+        })?;
+    Ok(result)
+}
+
+#[derive(thiserror::Error, Debug)]
+enum AccountChangesError {
+    #[error("Masterchain block not found")]
+    MasterchainBlockNotFound,
+    #[error("Block not found")]
+    BlockNotFound,
+}
+
+#[cfg(test)]
+mod tests {
+    use ton_block::Serializable;
+
+    use super::*;
+
+    fn block_id(shard_prefix: u64, seq_no: u32) -> ton_block::BlockIdExt {
+        ton_block::BlockIdExt {
+            shard_id: ton_block::ShardIdent::with_tagged_prefix(0, shard_prefix).unwrap(),
+            seq_no,
+            root_hash: ton_types::UInt256::from([seq_no as u8; 32]),
+            file_hash: ton_types::UInt256::from([seq_no as u8; 32]),
+        }
+    }
+
+    #[test]
+    fn committed_blocks_walk() {
+        const MC_SEQ_NO: u32 = 10;
+
+        const LEFT: u64 = 0x4000_0000_0000_0000;
+        const RIGHT: u64 = 0xc000_0000_0000_0000;
+
+        // left: 1 (prev mc block) <- 2 <- 3 (top)
+        // right: 4 (unknown) <- 5 <- 6 (top, also references left 2)
+        let mut graph = FastHashMap::default();
+        graph.insert(block_id(LEFT, 1), (MC_SEQ_NO - 1, None));
+        graph.insert(
+            block_id(LEFT, 2),
+            (MC_SEQ_NO, Some((block_id(LEFT, 1), None))),
+        );
+        graph.insert(
+            block_id(LEFT, 3),
+            (MC_SEQ_NO, Some((block_id(LEFT, 2), None))),
+        );
+        graph.insert(
+            block_id(RIGHT, 5),
+            (MC_SEQ_NO, Some((block_id(RIGHT, 4), None))),
+        );
+        graph.insert(
+            block_id(RIGHT, 6),
+            (
+                MC_SEQ_NO,
+                Some((block_id(RIGHT, 5), Some(block_id(LEFT, 2)))),
+            ),
+        );
+
+        let mut walk =
+            CommittedBlocksWalk::new(MC_SEQ_NO, vec![block_id(LEFT, 3), block_id(RIGHT, 6)]);
+
+        let mut committed = Vec::new();
+        while let Some(id) = walk
+            .next(|id| Ok(graph.get(id).map(|(mc_seq_no, _)| *mc_seq_no)))
+            .unwrap()
+        {
+            if let Some((prev1, prev2)) = graph[&id].1.clone() {
+                walk.add_prev(prev1, prev2);
+            }
+            committed.push(id);
+        }
+
+        committed.sort_by_key(|id| (id.shard_id.shard_prefix_with_tag(), id.seq_no));
+        assert_eq!(
+            committed,
+            [
+                block_id(LEFT, 2),
+                block_id(LEFT, 3),
+                block_id(RIGHT, 5),
+                block_id(RIGHT, 6)
+            ]
+        );
+    }
+
+    #[test]
+    fn account_changes_extraction() {
+        let account = ton_types::UInt256::from([0x11; 32]);
+        let old_hash = ton_types::UInt256::from([0x22; 32]);
+        let new_hash = ton_types::UInt256::from([0x33; 32]);
+
+        let mut transaction = ton_block::Transaction::with_address_and_status(
+            account.clone().into(),
+            ton_block::AccountStatus::AccStateActive,
+        );
+        transaction
+            .write_state_update(&ton_block::HashUpdate::with_hashes(
+                old_hash.clone(),
+                new_hash.clone(),
+            ))
+            .unwrap();
+        let transaction_cell = transaction.serialize().unwrap();
+
+        let mut account_blocks = ton_block::ShardAccountBlocks::default();
+        account_blocks
+            .add_serialized_transaction(&transaction, &transaction_cell)
+            .unwrap();
+
+        let mut extra = ton_block::BlockExtra::default();
+        extra.write_account_blocks(&account_blocks).unwrap();
+
+        let mut block = ton_block::Block::default();
+        block.write_extra(&extra).unwrap();
+
+        let id = block_id(0x8000_0000_0000_0000, 1);
+        let changes = extract_account_changes(10, &id, &block).unwrap();
+        assert_eq!(changes.len(), 1);
+
+        let change = &changes[0];
+        assert_eq!(change.mc_seq_no, 10);
+        assert_eq!(change.block_id, id);
+        assert_eq!(change.account, account);
+        assert_eq!(change.old_hash, old_hash);
+        assert_eq!(change.new_hash, new_hash);
+        assert_eq!(change.transaction_count, 1);
+
+        let empty = ton_block::Block::default();
+        assert!(extract_account_changes(10, &id, &empty).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,206 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+
+use super::Engine;
+use crate::storage::StorageSnapshot;
+use crate::utils::*;
+
+impl Engine {
+    /// Loads states of multiple accounts at once.
+    ///
+    /// Accounts are grouped by shards so that each shard state is loaded only once.
+    /// Shard blocks are resolved from the specified masterchain block or from the
+    /// last applied one. The result has the same order as `addresses`
+    pub async fn get_account_states(
+        &self,
+        addresses: &[ton_block::MsgAddressInt],
+        mc_block_id: Option<&ton_block::BlockIdExt>,
+    ) -> Result<Vec<AccountState>> {
+        self.get_account_states_at(&self.snapshot(), addresses, mc_block_id)
+    }
+
+    /// Same as [`Engine::get_account_states`], but resolves all blocks and states
+    /// through the specified snapshot
+    pub fn get_account_states_at(
+        &self,
+        snapshot: &StorageSnapshot<'_>,
+        addresses: &[ton_block::MsgAddressInt],
+        mc_block_id: Option<&ton_block::BlockIdExt>,
+    ) -> Result<Vec<AccountState>> {
+        let mc_block_id = match mc_block_id {
+            Some(mc_block_id) => mc_block_id.clone(),
+            None => snapshot.load_last_applied_mc_block_id()?,
+        };
+        let mc_state = self.load_state_at(snapshot, &mc_block_id)?;
+        let shard_blocks = collect_shard_blocks(&mc_state)?;
+
+        // Group address indices by shard blocks
+        let mut groups = FastHashMap::<usize, Vec<usize>>::default();
+        for (i, address) in addresses.iter().enumerate() {
+            let shard_index = find_shard_index(&shard_blocks, address)?;
+            groups.entry(shard_index).or_default().push(i);
+        }
+
+        let mut result: Vec<Option<AccountState>> = addresses.iter().map(|_| None).collect();
+        for (shard_index, indices) in groups {
+            let block_id = &shard_blocks[shard_index];
+            let state = if block_id == &mc_block_id {
+                mc_state.clone()
+            } else {
+                self.load_state_at(snapshot, block_id)?
+            };
+
+            let accounts = state.state().read_accounts()?;
+            for i in indices {
+                result[i] = Some(AccountState {
+                    block_id: block_id.clone(),
+                    account: accounts.account(&addresses[i].address())?,
+                });
+            }
+        }
+
+        Ok(result.into_iter().flatten().collect())
+    }
+
+    /// Loads config, elector and minter accounts from the masterchain state
+    /// of the specified key block or of the last applied masterchain block.
+    ///
+    /// NOTE: only key blocks with stored states can be used
+    pub async fn get_special_accounts(
+        &self,
+        key_block_seqno: Option<u32>,
+    ) -> Result<SpecialAccounts> {
+        self.get_special_accounts_at(&self.snapshot(), key_block_seqno)
+    }
+
+    /// Same as [`Engine::get_special_accounts`], but resolves the block and its state
+    /// through the specified snapshot
+    pub fn get_special_accounts_at(
+        &self,
+        snapshot: &StorageSnapshot<'_>,
+        key_block_seqno: Option<u32>,
+    ) -> Result<SpecialAccounts> {
+        let mc_block_id = match key_block_seqno {
+            Some(seq_no) => snapshot.load_key_block_id(seq_no)?,
+            None => snapshot.load_last_applied_mc_block_id()?,
+        };
+        let mc_state = self.load_state_at(snapshot, &mc_block_id)?;
+
+        let config = mc_state.config_params()?;
+        let config_address = config.config_addr.clone();
+        let elector_address = config.elector_address()?;
+        // Config account is used as minter if `ConfigParam2` is absent
+        let minter_address = config
+            .minter_address()
+            .unwrap_or_else(|_| config_address.clone());
+
+        let accounts = mc_state.state().read_accounts()?;
+        let load_account = |address: ton_types::UInt256| -> Result<SpecialAccount> {
+            let account = accounts.account(&address.clone().into())?;
+            Ok(SpecialAccount { address, account })
+        };
+
+        Ok(SpecialAccounts {
+            config: load_account(config_address)?,
+            elector: load_account(elector_address)?,
+            minter: load_account(minter_address)?,
+            mc_block_id,
+        })
+    }
+}
+
+impl Engine {
+    /// Loads the state visible in the snapshot, reusing the states cache
+    fn load_state_at(
+        &self,
+        snapshot: &StorageSnapshot<'_>,
+        block_id: &ton_block::BlockIdExt,
+    ) -> Result<Arc<ShardStateStuff>> {
+        if let Some(state) = self.shard_states_cache.get(block_id) {
+            return Ok(state);
+        }
+        snapshot.load_state(block_id)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AccountState {
+    /// Block with the state from which the account was loaded
+    pub block_id: ton_block::BlockIdExt,
+    /// Account state, `None` if the account doesn't exist
+    pub account: Option<ton_block::ShardAccount>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SpecialAccounts {
+    /// Masterchain block with the state from which the accounts were loaded
+    pub mc_block_id: ton_block::BlockIdExt,
+    pub config: SpecialAccount,
+    pub elector: SpecialAccount,
+    pub minter: SpecialAccount,
+}
+
+#[derive(Debug, Clone)]
+pub struct SpecialAccount {
+    /// Account id in the masterchain
+    pub address: ton_types::UInt256,
+    /// Account state, `None` if the account doesn't exist
+    pub account: Option<ton_block::ShardAccount>,
+}
+
+/// Returns ids of the masterchain block and all its top shard blocks
+pub(super) fn collect_shard_blocks(
+    mc_state: &ShardStateStuff,
+) -> Result<Vec<ton_block::BlockIdExt>> {
+    let mut result = vec![mc_state.block_id().clone()];
+    mc_state.shards()?.iterate_shards(|ident, descr| {
+        result.push(ton_block::BlockIdExt {
+            shard_id: ident,
+            seq_no: descr.seq_no,
+            root_hash: descr.root_hash,
+            file_hash: descr.file_hash,
+        });
+        Ok(true)
+    })?;
+    Ok(result)
+}
+
+/// Finds the shard block which contains the account
+pub(super) fn find_shard_index(
+    shard_blocks: &[ton_block::BlockIdExt],
+    address: &ton_block::MsgAddressInt,
+) -> Result<usize> {
+    // Anycast addresses are resolved into different accounts depending on the sender
+    if matches!(
+        address,
+        ton_block::MsgAddressInt::AddrStd(ton_block::MsgAddrStd {
+            anycast: Some(_),
+            ..
+        }) | ton_block::MsgAddressInt::AddrVar(ton_block::MsgAddrVar {
+            anycast: Some(_),
+            ..
+        })
+    ) {
+        return Err(AccountsError::AnycastNotSupported.into());
+    }
+
+    let workchain = address.workchain_id();
+    let account_prefix = address.address().get_next_u64()?;
+
+    shard_blocks
+        .iter()
+        .position(|block_id| {
+            block_id.shard_id.workchain_id() == workchain
+                && shard_contains_account(block_id.shard_id.shard_prefix_with_tag(), account_prefix)
+        })
+        .ok_or_else(|| AccountsError::ShardNotFound.into())
+}
+
+#[derive(thiserror::Error, Debug)]
+enum AccountsError {
+    #[error("Shard for the account not found")]
+    ShardNotFound,
+    #[error("Anycast addresses are not supported")]
+    AnycastNotSupported,
+}
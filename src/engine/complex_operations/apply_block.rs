@@ -5,12 +5,14 @@
 /// - slightly changed application of blocks
 ///
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use anyhow::Result;
 use futures_util::future::{BoxFuture, FutureExt};
 
+use crate::db::rocksdb;
 use crate::engine::Engine;
-use crate::storage::{BlockConnection, BlockHandle};
+use crate::storage::{BlockConnection, BlockHandle, PendingConnections};
 use crate::utils::*;
 
 pub fn apply_block<'a>(
@@ -31,35 +33,64 @@ pub fn apply_block<'a>(
         }
 
         let (prev1_id, prev2_id) = block.construct_prev_id()?;
+        if !handle.id().shard_id.is_masterchain() {
+            ensure_prev_blocks_indexed(engine, handle, &prev1_id, &prev2_id)?;
+        }
         ensure_prev_blocks_downloaded(engine, &prev1_id, &prev2_id, mc_seq_no, pre_apply, depth)
             .await?;
 
+        let stages = &engine.metrics.block_apply_stages;
+
         let shard_state = if handle.meta().has_state() {
             engine.load_state(handle.id()).await?
         } else {
-            compute_and_store_shard_state(engine, handle, block, &prev1_id, &prev2_id).await?
+            let started_at = Instant::now();
+            let state =
+                compute_and_store_shard_state(engine, handle, block, &prev1_id, &prev2_id).await?;
+            stages.state_update.observe_since(started_at);
+            state
         };
 
         if !pre_apply {
-            update_block_connections(engine, handle, &prev1_id, &prev2_id)?;
-            engine
-                .notify_subscribers_with_block(handle, block, &shard_state)
-                .await?;
-
-            if block.id().is_masterchain() {
-                engine.store_last_applied_mc_block_id(block.id())?;
-
-                // TODO: update shard blocks
+            // NOTE: connections, indices, the last applied block and the applied flag
+            // are written in a single batch. The shard state is stored separately,
+            // because it is content addressed and also exists for pre-applied blocks.
+            //
+            // Connections are visible to subscribers while the batch is pending,
+            // so that they can traverse the blocks
+            let started_at = Instant::now();
+            let mut batch = rocksdb::WriteBatch::default();
+            let mut connections = PendingConnections::default();
+            update_block_connections(
+                engine,
+                &mut batch,
+                &mut connections,
+                handle,
+                &prev1_id,
+                &prev2_id,
+            )?;
+            let connections_time = started_at.elapsed();
 
-                engine.set_applied(handle, mc_seq_no).await?;
+            let connection_storage = engine.storage.block_connection_storage();
+            let result =
+                apply_block_batched(engine, handle, block, &shard_state, mc_seq_no, batch).await;
+            match result {
+                Ok(indexing_time) => {
+                    connection_storage.commit_pending(connections);
+                    stages.indexing.observe(connections_time + indexing_time);
+                }
+                Err(e) => {
+                    connection_storage.rollback_pending(connections);
+                    return Err(e);
+                }
+            }
 
+            if block.id().is_masterchain() {
                 let id = handle.id().clone();
                 engine
                     .next_block_applying_operations
                     .do_or_wait(&prev1_id, None, async move { Ok(id) })
                     .await?;
-            } else {
-                engine.set_applied(handle, mc_seq_no).await?;
             }
         }
 
@@ -68,6 +99,109 @@ pub fn apply_block<'a>(
     .boxed()
 }
 
+/// Notifies subscribers and writes the batch with the applied flag.
+///
+/// Returns the time spent on indexing
+async fn apply_block_batched(
+    engine: &Arc<Engine>,
+    handle: &Arc<BlockHandle>,
+    block: &BlockStuff,
+    shard_state: &ShardStateStuff,
+    mc_seq_no: u32,
+    mut batch: rocksdb::WriteBatch,
+) -> Result<Duration> {
+    engine
+        .notify_subscribers_with_block(handle, block, shard_state)
+        .await?;
+
+    let started_at = Instant::now();
+
+    if engine.sync_options.index_code_hashes {
+        engine
+            .storage
+            .code_hashes()
+            .update_batched(&mut batch, block, shard_state)?;
+    }
+
+    if block.id().is_masterchain() {
+        if handle.is_key_block() {
+            let config = shard_state.config_params()?;
+            engine.storage.config_history().store_config_batched(
+                &mut batch,
+                block.id().seq_no,
+                config,
+            )?;
+            engine
+                .storage
+                .validator_sets()
+                .store_validator_set_batched(&mut batch, block.id().seq_no, config)?;
+        }
+
+        engine
+            .storage
+            .libraries()
+            .update_batched(&mut batch, shard_state.state().libraries())?;
+
+        let mut shard_blocks = block.shard_blocks()?.into_values().collect::<Vec<_>>();
+        shard_blocks.sort_unstable_by_key(|id| {
+            (
+                id.shard_id.workchain_id(),
+                id.shard_id.shard_prefix_with_tag(),
+            )
+        });
+        engine.storage.block_index().index_mc_block_batched(
+            &mut batch,
+            block.id(),
+            handle.meta().gen_utime(),
+            &shard_blocks,
+        );
+
+        engine.store_last_applied_mc_block_id_batched(&mut batch, block.id());
+
+        // TODO: update shard blocks
+
+        engine.set_applied_batched(handle, mc_seq_no, batch).await?;
+        engine.on_last_applied_mc_block_id_written(block.id());
+    } else {
+        engine.set_applied_batched(handle, mc_seq_no, batch).await?;
+    }
+
+    Ok(started_at.elapsed())
+}
+
+/// Blocks of the non-indexed shards are never applied, so the prev blocks
+/// across the merge or split boundary can't be reached recursively.
+/// Detaches the shard until the next persistent state in that case
+fn ensure_prev_blocks_indexed(
+    engine: &Arc<Engine>,
+    handle: &Arc<BlockHandle>,
+    prev1_id: &ton_block::BlockIdExt,
+    prev2_id: &Option<ton_block::BlockIdExt>,
+) -> Result<()> {
+    let handles = engine.storage.block_handle_storage();
+
+    for prev_id in std::iter::once(prev1_id).chain(prev2_id) {
+        if engine.is_shard_tracked(&prev_id.shard_id)
+            || matches!(
+                handles.load_handle(prev_id)?,
+                Some(handle) if handle.meta().is_applied()
+            )
+        {
+            continue;
+        }
+
+        tracing::warn!(
+            block_id = %handle.id().display(),
+            prev_block_id = %prev_id.display(),
+            "prev block belongs to the non-indexed shard"
+        );
+        engine.detach_shard(&handle.id().shard_id)?;
+        return Err(ApplyBlockError::NonIndexedPrevBlock.into());
+    }
+
+    Ok(())
+}
+
 async fn ensure_prev_blocks_downloaded(
     engine: &Arc<Engine>,
     prev1_id: &ton_block::BlockIdExt,
@@ -100,6 +234,8 @@ async fn ensure_prev_blocks_downloaded(
 
 fn update_block_connections(
     engine: &Arc<Engine>,
+    batch: &mut rocksdb::WriteBatch,
+    pending: &mut PendingConnections,
     handle: &Arc<BlockHandle>,
     prev1_id: &ton_block::BlockIdExt,
     prev2_id: &Option<ton_block::BlockIdExt>,
@@ -117,21 +253,45 @@ fn update_block_connections(
                 .load_handle(prev2_id)?
                 .ok_or(ApplyBlockError::Prev2BlockHandleNotFound)?;
 
-            conn.store_connection(&prev1_handle, BlockConnection::Next1, handle.id())?;
-            conn.store_connection(&prev2_handle, BlockConnection::Next1, handle.id())?;
-            conn.store_connection(handle, BlockConnection::Prev1, prev1_id)?;
-            conn.store_connection(handle, BlockConnection::Prev2, prev2_id)?;
+            conn.store_connection_batched(
+                batch,
+                pending,
+                &prev1_handle,
+                BlockConnection::Next1,
+                handle.id(),
+            );
+            conn.store_connection_batched(
+                batch,
+                pending,
+                &prev2_handle,
+                BlockConnection::Next1,
+                handle.id(),
+            );
+            conn.store_connection_batched(batch, pending, handle, BlockConnection::Prev1, prev1_id);
+            conn.store_connection_batched(batch, pending, handle, BlockConnection::Prev2, prev2_id);
         }
         None => {
             let prev1_shard = prev1_handle.id().shard_id;
             let shard = handle.id().shard_id;
 
             if prev1_shard != shard && prev1_shard.split()?.1 == shard {
-                conn.store_connection(&prev1_handle, BlockConnection::Next2, handle.id())?;
+                conn.store_connection_batched(
+                    batch,
+                    pending,
+                    &prev1_handle,
+                    BlockConnection::Next2,
+                    handle.id(),
+                );
             } else {
-                conn.store_connection(&prev1_handle, BlockConnection::Next1, handle.id())?;
+                conn.store_connection_batched(
+                    batch,
+                    pending,
+                    &prev1_handle,
+                    BlockConnection::Next1,
+                    handle.id(),
+                );
             }
-            conn.store_connection(handle, BlockConnection::Prev1, prev1_id)?;
+            conn.store_connection_batched(batch, pending, handle, BlockConnection::Prev1, prev1_id);
         }
     }
 
@@ -184,18 +344,20 @@ async fn compute_and_store_shard_state(
         .min_ref_mc_state()
         .clone();
 
-    let shard_state = tokio::task::spawn_blocking({
-        let block_id = block.id().clone();
-        move || -> Result<Arc<ShardStateStuff>> {
-            let shard_state_root = merkle_update.apply_for(&prev_shard_state_root)?;
-            Ok(Arc::new(ShardStateStuff::new(
-                block_id,
-                shard_state_root,
-                &min_ref_mc_state,
-            )?))
-        }
-    })
-    .await??;
+    let shard_state = engine
+        .applier_pool
+        .run({
+            let block_id = block.id().clone();
+            move || -> Result<Arc<ShardStateStuff>> {
+                let shard_state_root = merkle_update.apply_for(&prev_shard_state_root)?;
+                Ok(Arc::new(ShardStateStuff::new(
+                    block_id,
+                    shard_state_root,
+                    &min_ref_mc_state,
+                )?))
+            }
+        })
+        .await??;
 
     engine.store_state(handle, &shard_state).await?;
     Ok(shard_state)
@@ -211,4 +373,6 @@ enum ApplyBlockError {
     Prev2BlockHandleNotFound,
     #[error("Invalid masterchain block sequence")]
     InvalidMasterchainBlockSequence,
+    #[error("Prev block belongs to the non-indexed shard")]
+    NonIndexedPrevBlock,
 }
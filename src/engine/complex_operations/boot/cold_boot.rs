@@ -534,7 +534,11 @@ async fn download_start_blocks_and_states(
     );
 
     // Download and save blocks and states from other shards
-    for (_, block_id) in init_mc_block.shard_blocks()? {
+    for (shard, block_id) in init_mc_block.shard_blocks()? {
+        if !engine.sync_options.is_shard_indexed(&shard) {
+            continue;
+        }
+
         if block_id.seq_no == 0 {
             engine.download_zero_state(&block_id).await?;
         } else {
@@ -552,7 +556,7 @@ async fn download_start_blocks_and_states(
     Ok(top_blocks)
 }
 
-async fn download_block_with_state(
+pub(crate) async fn download_block_with_state(
     engine: &Arc<Engine>,
     full_state_id: FullStateId,
 ) -> Result<(Arc<BlockHandle>, BlockStuff)> {
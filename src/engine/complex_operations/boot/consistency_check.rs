@@ -0,0 +1,243 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+
+use crate::config::ConsistencyCheckLevel;
+use crate::engine::{delivery_mc_seqno, Engine};
+use crate::storage::{BlockConnection, BlockHandle, EngineEvent};
+use crate::utils::*;
+
+/// Validates the last applied masterchain block, its state and the last archive.
+///
+/// Rolls back to the closest consistent masterchain block if needed.
+/// Returns the id of the consistent masterchain block
+pub async fn check_consistency(
+    engine: &Arc<Engine>,
+    last_mc_block_id: ton_block::BlockIdExt,
+) -> Result<ton_block::BlockIdExt> {
+    let level = engine.sync_options.consistency_check;
+    if level == ConsistencyCheckLevel::None {
+        return Ok(last_mc_block_id);
+    }
+
+    tracing::info!(?level, "starting consistency check");
+
+    // NOTE: scans all package entries if the archive is broken
+    let storage = engine.storage.clone();
+    tokio::task::spawn_blocking(move || storage.block_storage().repair_last_archive()).await??;
+
+    let block_handle_storage = engine.storage.block_handle_storage();
+    let block_connection_storage = engine.storage.block_connection_storage();
+
+    let mut block_id = last_mc_block_id.clone();
+    let mut rolled_back = Vec::new();
+    let mut depth = 0;
+    loop {
+        let handle = block_handle_storage.load_handle(&block_id)?;
+        let result = match &handle {
+            Some(handle) => check_block(engine, handle, level).await,
+            None => Err(ConsistencyCheckError::BlockHandleNotFound.into()),
+        };
+
+        let e = match result {
+            Ok(()) => break,
+            Err(e) => e,
+        };
+
+        tracing::warn!(
+            block_id = %block_id.display(),
+            "inconsistent masterchain block: {e:?}"
+        );
+
+        if block_id.seq_no == 0 || depth >= MAX_ROLLBACK_DEPTH {
+            return Err(ConsistencyCheckError::ConsistentBlockNotFound.into());
+        }
+
+        // Force block to be applied again
+        if let Some(handle) = handle {
+            handle.meta().clear_applied_and_state();
+            block_handle_storage.store_handle(&handle)?;
+        }
+        rolled_back.push(block_id.clone());
+
+        block_id = block_connection_storage.load_connection(&block_id, BlockConnection::Prev1)?;
+        depth += 1;
+    }
+
+    if block_id != last_mc_block_id {
+        tracing::warn!(
+            from = %last_mc_block_id.display(),
+            to = %block_id.display(),
+            "rolled back to the last consistent masterchain block"
+        );
+
+        rollback_applied_blocks(engine, rolled_back, &block_id).await?;
+
+        engine.store_last_applied_mc_block_id(&block_id)?;
+        if let Ok(shards_client_mc_block_id) = engine.load_shards_client_mc_block_id() {
+            if shards_client_mc_block_id.seq_no > block_id.seq_no {
+                engine.store_shards_client_mc_block_id(&block_id)?;
+            }
+        }
+
+        engine.record_event(EngineEvent::RolledBack {
+            from_mc_block_id: last_mc_block_id.to_string(),
+            to_mc_block_id: block_id.to_string(),
+        });
+    }
+
+    tracing::info!("consistency check finished");
+    Ok(block_id)
+}
+
+/// Resets everything which must be written again when the rolled back blocks are applied.
+///
+/// - shard blocks applied by the rolled back masterchain blocks are marked as not applied;
+/// - masterchain block indices after the consistent block are removed;
+/// - delivery records of the rolled back blocks are removed, so they are delivered again.
+///
+/// NOTE: other batched writes are idempotent and are overwritten on the next application:
+/// config history and validator sets are keyed by the key block seqno, libraries are
+/// replaced with the libraries from the state, code hashes are updated from the account
+/// states, and connections point to the same blocks because the chain is the same
+async fn rollback_applied_blocks(
+    engine: &Arc<Engine>,
+    mut rolled_back: Vec<ton_block::BlockIdExt>,
+    consistent_mc_block_id: &ton_block::BlockIdExt,
+) -> Result<()> {
+    let block_handle_storage = engine.storage.block_handle_storage();
+    let block_connection_storage = engine.storage.block_connection_storage();
+    let block_storage = engine.storage.block_storage();
+    let block_index = engine.storage.block_index();
+
+    let durable_ids = engine
+        .subscribers
+        .iter()
+        .filter_map(|subscriber| subscriber.durable_id())
+        .collect::<Vec<_>>();
+
+    let consistent_seq_no = consistent_mc_block_id.seq_no;
+
+    // Collect top shard blocks of the rolled back masterchain blocks
+    let mut stack = Vec::new();
+    for mc_block_id in &rolled_back {
+        stack.extend(
+            block_index
+                .load_shard_blocks(mc_block_id.seq_no)?
+                .unwrap_or_default(),
+        );
+    }
+
+    // Walk shard blocks back until the blocks of the consistent masterchain block
+    let mut visited = FastHashSet::default();
+    while let Some(block_id) = stack.pop() {
+        if !visited.insert(block_id.clone()) {
+            continue;
+        }
+
+        let handle = match block_handle_storage.load_handle(&block_id)? {
+            Some(handle) => handle,
+            None => continue,
+        };
+        if !handle.meta().is_applied() || handle.masterchain_ref_seqno() <= consistent_seq_no {
+            continue;
+        }
+
+        if handle.meta().has_prev1() {
+            stack
+                .push(block_connection_storage.load_connection(&block_id, BlockConnection::Prev1)?);
+        }
+        if handle.meta().has_prev2() {
+            stack
+                .push(block_connection_storage.load_connection(&block_id, BlockConnection::Prev2)?);
+        }
+
+        handle.meta().clear_is_applied();
+        handle.meta().clear_masterchain_ref_seqno();
+        block_handle_storage.store_handle(&handle)?;
+        rolled_back.push(block_id);
+    }
+
+    if !durable_ids.is_empty() {
+        let deliveries = engine.storage.subscriber_deliveries();
+        for block_id in &rolled_back {
+            let handle = match block_handle_storage.load_handle(block_id)? {
+                Some(handle) if handle.meta().has_data() => handle,
+                _ => continue,
+            };
+            let block = block_storage.load_block_data(&handle).await?;
+            let mc_seq_no = delivery_mc_seqno(block_id, block.block())?;
+            for durable_id in &durable_ids {
+                deliveries.remove_delivered(durable_id, mc_seq_no, block_id)?;
+            }
+        }
+    }
+
+    if let Some(handle) = block_handle_storage.load_handle(consistent_mc_block_id)? {
+        block_index.remove_mc_blocks_after(consistent_seq_no, handle.meta().gen_utime())?;
+    }
+
+    tracing::info!(
+        rolled_back_blocks = rolled_back.len(),
+        "reset applied blocks after rollback"
+    );
+    Ok(())
+}
+
+async fn check_block(
+    engine: &Engine,
+    handle: &BlockHandle,
+    level: ConsistencyCheckLevel,
+) -> Result<()> {
+    let block_id = handle.id();
+
+    if !handle.meta().is_applied() {
+        return Err(ConsistencyCheckError::BlockNotApplied.into());
+    }
+    if !handle.meta().has_state() {
+        return Err(ConsistencyCheckError::StateNotFound.into());
+    }
+
+    let state_block_id = engine
+        .storage
+        .shard_state_storage()
+        .find_block_id(block_id.shard_id, block_id.seq_no)?;
+    if state_block_id.as_ref() != Some(block_id) {
+        return Err(ConsistencyCheckError::StateRootMismatch.into());
+    }
+
+    if level == ConsistencyCheckLevel::Full {
+        let state = engine.load_state(block_id).await?;
+        if handle.meta().has_data() {
+            let block = engine
+                .storage
+                .block_storage()
+                .load_block_data(handle)
+                .await?;
+            let state_update = block.block().read_state_update()?;
+            if state.root_cell().repr_hash() != state_update.new_hash {
+                return Err(ConsistencyCheckError::StateHashMismatch.into());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+const MAX_ROLLBACK_DEPTH: u32 = 1000;
+
+#[derive(Debug, thiserror::Error)]
+enum ConsistencyCheckError {
+    #[error("Block handle not found")]
+    BlockHandleNotFound,
+    #[error("Block is not applied")]
+    BlockNotApplied,
+    #[error("Block state not found")]
+    StateNotFound,
+    #[error("Stored state root belongs to another block")]
+    StateRootMismatch,
+    #[error("Stored state hash mismatch")]
+    StateHashMismatch,
+    #[error("Consistent masterchain block not found")]
+    ConsistentBlockNotFound,
+}
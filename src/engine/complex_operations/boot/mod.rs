@@ -5,10 +5,13 @@ use anyhow::Result;
 use crate::engine::Engine;
 use crate::utils::*;
 
+pub(crate) use self::cold_boot::download_block_with_state;
 use self::cold_boot::*;
+use self::consistency_check::*;
 use self::warm_boot::*;
 
 mod cold_boot;
+mod consistency_check;
 mod warm_boot;
 
 /// Ensures that all shard states are downloaded.
@@ -18,7 +21,10 @@ pub async fn boot(engine: &Arc<Engine>) -> Result<()> {
     tracing::info!("starting boot");
 
     let last_key_block_id = match engine.load_last_applied_mc_block_id() {
-        Ok(block_id) => warm_boot(engine, block_id).await?,
+        Ok(block_id) => {
+            let block_id = check_consistency(engine, block_id).await?;
+            warm_boot(engine, block_id).await?
+        }
         Err(e) => {
             tracing::warn!("failed to load last masterchain block id: {e}. node is not synced yet");
             let last_mc_block_id = cold_boot(engine).await?;
@@ -5,10 +5,12 @@
 /// - simplified block walking
 ///
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::{anyhow, Result};
 use tokio::sync::{OwnedSemaphorePermit, Semaphore};
 
+use super::download_block_with_state;
 use crate::engine::Engine;
 use crate::proto;
 use crate::storage::{BlockConnection, BlockHandle};
@@ -147,8 +149,16 @@ async fn load_shard_blocks(
     let block_handle_storage = engine.storage.block_handle_storage();
 
     let mc_seq_no = masterchain_block.id().seq_no;
+
+    // Detached shards are resumed in background from their own persistent state
+    engine.resume_detached_shards(masterchain_block)?;
+
     let mut tasks = Vec::new();
-    for (_, shard_block_id) in masterchain_block.shard_blocks()? {
+    for (shard, shard_block_id) in masterchain_block.shard_blocks()? {
+        if !engine.is_shard_tracked(&shard) {
+            continue;
+        }
+
         if matches!(
             block_handle_storage.load_handle(&shard_block_id)?,
             Some(handle) if handle.meta().is_applied()
@@ -162,6 +172,14 @@ async fn load_shard_blocks(
                 .download_and_apply_block(&shard_block_id, mc_seq_no, false, 0)
                 .await
             {
+                if engine.is_shard_detached(&shard) {
+                    tracing::warn!(
+                        block_id = %shard_block_id.display(),
+                        "shard detached until the next persistent state: {e:?}"
+                    );
+                    break;
+                }
+
                 tracing::error!(
                     block_id = %shard_block_id.display(),
                     "failed to apply shard block: {e:?}"
@@ -184,10 +202,19 @@ async fn load_shard_blocks(
     Ok(())
 }
 
+/// Validates and applies block from the broadcast.
+///
+/// Returns `true` if the block was new and its data and proof were stored.
+/// Shard blocks are only pre-applied, masterchain blocks are applied only
+/// if they are next to the last applied one
 pub async fn process_block_broadcast(
     engine: &Arc<Engine>,
     mut broadcast: proto::BlockBroadcast,
-) -> Result<()> {
+) -> Result<bool> {
+    if !engine.is_shard_tracked(&broadcast.id.shard_id) {
+        return Ok(false);
+    }
+
     let block_handle_storage = engine.storage.block_handle_storage();
     let block_storage = engine.storage.block_storage();
 
@@ -195,7 +222,7 @@ pub async fn process_block_broadcast(
         block_handle_storage.load_handle(&broadcast.id)?,
         Some(handle) if handle.meta().has_data()
     ) {
-        return Ok(());
+        return Ok(false);
     }
 
     let proof = BlockProofStuff::deserialize(
@@ -208,7 +235,7 @@ pub async fn process_block_broadcast(
 
     let last_applied_mc_block_id = engine.load_last_applied_mc_block_id()?;
     if virt_block_info.prev_key_block_seqno() > last_applied_mc_block_id.seq_no {
-        return Ok(());
+        return Ok(false);
     }
 
     let last_mc_state = engine.load_state(&last_applied_mc_block_id).await?;
@@ -229,7 +256,7 @@ pub async fn process_block_broadcast(
     {
         result if result.updated => result.handle,
         // Skipped apply for block broadcast because the block is already being processed
-        _ => return Ok(()),
+        _ => return Ok(false),
     };
 
     if !handle.meta().has_proof() {
@@ -242,7 +269,7 @@ pub async fn process_block_broadcast(
         {
             result if result.updated => result.handle,
             // Skipped apply for block broadcast because the block is already being processed
-            _ => return Ok(()),
+            _ => return Ok(false),
         };
     }
 
@@ -267,7 +294,7 @@ pub async fn process_block_broadcast(
         }
     }
 
-    Ok(())
+    Ok(true)
 }
 
 fn validate_broadcast(
@@ -345,3 +372,144 @@ enum ShardClientError {
     #[error("Invalid block extra")]
     InvalidBlockExtra,
 }
+
+impl Engine {
+    /// Whether blocks of the specified shard are applied
+    pub(crate) fn is_shard_tracked(&self, shard: &ton_block::ShardIdent) -> bool {
+        self.sync_options.is_shard_indexed(shard) && !self.is_shard_detached(shard)
+    }
+
+    /// Whether the shard intersects with some detached shard
+    pub(crate) fn is_shard_detached(&self, shard: &ton_block::ShardIdent) -> bool {
+        self.detached_shards
+            .lock()
+            .iter()
+            .any(|detached| detached.intersect_with(shard))
+    }
+
+    /// Stops applying blocks of the shard until the next persistent state
+    pub(crate) fn detach_shard(&self, shard: &ton_block::ShardIdent) -> Result<()> {
+        if self.detached_shards.lock().insert(*shard) {
+            self.storage.node_state().store_detached_shard(shard)?;
+        }
+        Ok(())
+    }
+
+    fn attach_shard(&self, shard: &ton_block::ShardIdent) -> Result<()> {
+        self.storage.node_state().remove_detached_shard(shard)?;
+        self.detached_shards.lock().remove(shard);
+        Ok(())
+    }
+
+    /// Replaces detached shards which no longer exist in the shard config
+    /// with the current shards they intersect with, and starts resuming them
+    /// if the masterchain block is the current persistent state.
+    ///
+    /// NOTE: resuming runs in background and doesn't block the masterchain block processing
+    pub(crate) fn resume_detached_shards(self: &Arc<Self>, mc_block: &BlockStuff) -> Result<()> {
+        if self.detached_shards.lock().is_empty() {
+            return Ok(());
+        }
+
+        let shard_blocks = mc_block.shard_blocks()?;
+        let shards = shard_blocks.keys().copied().collect::<Vec<_>>();
+        self.update_detached_shards(&shards)?;
+
+        let is_persistent = matches!(
+            self.storage.runtime_storage().persistent_state_keeper().current(),
+            Some(handle) if handle.id() == mc_block.id()
+        );
+        if !is_persistent {
+            return Ok(());
+        }
+
+        for (shard, block_id) in shard_blocks {
+            if !self.sync_options.is_shard_indexed(&shard) || !self.is_shard_detached(&shard) {
+                continue;
+            }
+
+            let engine = self.clone();
+            let full_state_id = FullStateId {
+                mc_block_id: mc_block.id().clone(),
+                block_id,
+            };
+            tokio::spawn(async move {
+                engine.resume_detached_shard(full_state_id).await;
+            });
+        }
+
+        Ok(())
+    }
+
+    fn update_detached_shards(&self, shards: &[ton_block::ShardIdent]) -> Result<()> {
+        let node_state = self.storage.node_state();
+
+        let mut detached = self.detached_shards.lock();
+        let outdated = detached
+            .iter()
+            .filter(|shard| !shards.contains(shard))
+            .copied()
+            .collect::<Vec<_>>();
+
+        for shard in outdated {
+            node_state.remove_detached_shard(&shard)?;
+            detached.remove(&shard);
+
+            // Shard was split or merged, so its successors stay detached
+            for new_shard in shards
+                .iter()
+                .filter(|new_shard| new_shard.intersect_with(&shard))
+            {
+                if detached.insert(*new_shard) {
+                    node_state.store_detached_shard(new_shard)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Downloads the shard state from the persistent state and attaches the shard.
+    ///
+    /// Retries with a backoff until the newer persistent state is available
+    async fn resume_detached_shard(self: Arc<Self>, full_state_id: FullStateId) {
+        const MIN_INTERVAL: Duration = Duration::from_secs(1);
+        const MAX_INTERVAL: Duration = Duration::from_secs(60);
+
+        let shard = full_state_id.block_id.shard_id;
+        let keeper = self.storage.runtime_storage().persistent_state_keeper();
+
+        let mut interval = MIN_INTERVAL;
+        while self.is_working() {
+            // The newer persistent state starts its own task
+            if !matches!(keeper.current(), Some(handle) if handle.id() == &full_state_id.mc_block_id)
+            {
+                break;
+            }
+
+            let e = match download_block_with_state(&self, full_state_id.clone()).await {
+                // Accounts changed while the shard was detached are taken from the state
+                Ok(_) => match self
+                    .backfill_code_hash_index(&full_state_id.block_id)
+                    .await
+                    .and_then(|_| self.attach_shard(&shard))
+                {
+                    Ok(()) => {
+                        tracing::info!(shard = %shard, "resumed detached shard");
+                        break;
+                    }
+                    Err(e) => e,
+                },
+                Err(e) => e,
+            };
+
+            tracing::error!(
+                block_id = %full_state_id.block_id.display(),
+                retry_in_sec = interval.as_secs(),
+                "failed to resume detached shard: {e:?}"
+            );
+            tokio::time::sleep(interval).await;
+            interval = std::cmp::min(interval * 2, MAX_INTERVAL);
+        }
+    }
+}
@@ -8,6 +8,7 @@ use anyhow::{Context, Result};
 use parking_lot::Mutex;
 
 use super::block_maps::*;
+use crate::utils::{MemoryBudget, MemoryReservation};
 
 #[derive(Clone)]
 pub struct ArchiveWritersPool {
@@ -15,19 +16,27 @@ pub struct ArchiveWritersPool {
 }
 
 impl ArchiveWritersPool {
-    pub fn new(base_path: impl AsRef<Path>, save_to_disk_threshold: usize) -> Self {
+    pub fn new(
+        base_path: impl AsRef<Path>,
+        completed_path: impl AsRef<Path>,
+        save_to_disk_threshold: usize,
+        memory_budget: MemoryBudget,
+    ) -> Self {
         Self {
             state: Arc::new(ArchiveWritersPoolState {
                 save_to_disk_threshold,
+                memory_budget,
                 acquired_memory: Default::default(),
                 temp_file_index: Default::default(),
                 base_path: base_path.as_ref().to_path_buf(),
+                completed_path: completed_path.as_ref().to_path_buf(),
             }),
         }
     }
 
     pub fn acquire(&self) -> ArchiveWriter {
         ArchiveWriter {
+            memory: self.state.memory_budget.reservation(),
             pool_state: self.state.clone(),
             state: ArchiveWriterState::InMemory(Vec::new()),
         }
@@ -36,11 +45,15 @@ impl ArchiveWritersPool {
 
 struct ArchiveWritersPoolState {
     save_to_disk_threshold: usize,
+    /// Shared budget of the in-flight sync data
+    memory_budget: MemoryBudget,
     // NOTE: `AtomicUsize` is not used here because there is a complex
     // InMemory-to-File transition
     acquired_memory: Mutex<usize>,
     temp_file_index: AtomicUsize,
     base_path: PathBuf,
+    /// Directory in the file DB for the completely downloaded archives
+    completed_path: PathBuf,
 }
 
 impl ArchiveWritersPoolState {
@@ -63,27 +76,57 @@ impl ArchiveWritersPoolState {
 
 pub struct ArchiveWriter {
     pool_state: Arc<ArchiveWritersPoolState>,
+    /// Memory of the in-memory buffer in the shared budget
+    memory: MemoryReservation,
     state: ArchiveWriterState,
 }
 
 impl ArchiveWriter {
     pub fn parse_block_maps(&self) -> Result<Arc<BlockMaps>> {
+        // NOTE: parsed block maps are required to make progress,
+        // so they are accounted even if the budget is exceeded
+        let budget = &self.pool_state.memory_budget;
+
         match &self.state {
-            ArchiveWriterState::InMemory(buffer) => BlockMaps::new(buffer),
+            ArchiveWriterState::InMemory(buffer) => {
+                BlockMaps::new(buffer, budget.force_acquire(buffer.len()))
+            }
             ArchiveWriterState::File { file, .. } => {
                 let mapped_file =
                     FileWriterView::new(file).context("Failed to map temp archive file")?;
 
-                BlockMaps::new(mapped_file.as_slice())
+                let data = mapped_file.as_slice();
+                BlockMaps::new(data, budget.force_acquire(data.len()))
             }
         }
     }
 
+    /// Moves the completely downloaded archive file into the file DB.
+    ///
+    /// NOTE: in-memory archives are left as is
+    pub fn complete(&mut self) -> std::io::Result<()> {
+        if let ArchiveWriterState::File { path, file } = &mut self.state {
+            file.flush()?;
+            file.sync_all()?;
+
+            let name = match path.file_name().and_then(|name| name.to_str()) {
+                Some(name) => name.to_owned(),
+                None => return Ok(()),
+            };
+            *path =
+                crate::storage::move_completed_file(path, &self.pool_state.completed_path, &name)?;
+            *file = std::fs::OpenOptions::new().read(true).open(&*path)?;
+        }
+        Ok(())
+    }
+
     fn acquire_memory(&mut self, additional: usize) -> std::io::Result<()> {
         if let ArchiveWriterState::InMemory(buffer) = &self.state {
             let move_to_file = {
                 let mut acquired_memory = self.pool_state.acquired_memory.lock();
-                if *acquired_memory + additional > self.pool_state.save_to_disk_threshold {
+                if *acquired_memory + additional > self.pool_state.save_to_disk_threshold
+                    || !self.memory.try_grow(additional)
+                {
                     *acquired_memory -= buffer.len();
                     true
                 } else {
@@ -96,6 +139,7 @@ impl ArchiveWriter {
                 let (path, mut file) = self.pool_state.acquire_file()?;
                 file.write_all(buffer)?;
                 self.state = ArchiveWriterState::File { path, file };
+                self.memory.release();
             }
         }
 
@@ -1,8 +1,10 @@
 use std::collections::binary_heap::PeekMut;
 use std::collections::BinaryHeap;
+use std::io::Write;
 use std::ops::{Bound, Deref, DerefMut, RangeBounds};
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::{Context, Result};
 use broxus_util::now;
@@ -13,7 +15,10 @@ use tokio_util::sync::CancellationToken;
 use super::archive_writers_pool::*;
 use super::block_maps::*;
 use crate::engine::{ArchiveDownloadStatus, Engine};
-use crate::network::Neighbour;
+use crate::network::{Neighbour, NeighbourRequestSlot};
+use crate::proto;
+use crate::storage::EngineEvent;
+use crate::utils::FastHashSet;
 
 pub struct ArchivesStream {
     ctx: Arc<DownloaderContext>,
@@ -64,12 +69,15 @@ impl ArchivesStream {
             ctx: Arc::new(DownloaderContext {
                 engine: engine.clone(),
                 writers_pool: ArchiveWritersPool::new(
-                    engine.storage.file_db_path(),
+                    engine.storage.downloads_dir(),
+                    engine.storage.archives_dir(),
                     engine.sync_options.save_to_disk_threshold,
+                    engine.sync_memory.clone(),
                 ),
                 new_archive_notification: Default::default(),
                 cancellation_token: Default::default(),
                 good_peers: Default::default(),
+                rejected_stored_archives: Default::default(),
             }),
             pending_archives: Default::default(),
             prefetch_enabled,
@@ -80,7 +88,7 @@ impl ArchivesStream {
         };
 
         // Start with only the first archive
-        stream.start_downloading(stream.next_mc_seq_no);
+        stream.start_downloading(stream.next_mc_seq_no, false);
 
         stream
     }
@@ -96,7 +104,7 @@ impl ArchivesStream {
         let (block_maps, neighbour) = loop {
             // Force fill gap
             if has_gap {
-                self.start_downloading(next_index);
+                self.start_downloading(next_index, false);
                 has_gap = false;
                 continue;
             }
@@ -144,6 +152,9 @@ impl ArchivesStream {
                             }
                             None => {
                                 tracing::error!(target: "sync", next_index, "retrying invalid archive");
+                                self.ctx.engine.record_event(EngineEvent::InvalidArchive {
+                                    mc_seqno: next_index,
+                                });
                                 continue;
                             }
                         }
@@ -178,7 +189,7 @@ impl ArchivesStream {
             && self.pending_archives.len() < self.ctx.engine.sync_options.parallel_archive_downloads
             && !matches!(self.to, Some(to) if self.max_mc_seq_no + 2 * STEP > to)
         {
-            self.start_downloading(self.max_mc_seq_no + STEP);
+            self.start_downloading(self.max_mc_seq_no + STEP, true);
         }
 
         ReceivedBlockMaps {
@@ -190,7 +201,12 @@ impl ArchivesStream {
         }
     }
 
-    fn start_downloading(&mut self, mc_block_seq_no: u32) {
+    /// Starts downloading the archive in background.
+    ///
+    /// Prefetched archives are downloaded only when the sync memory budget
+    /// is not exhausted. The required archive is downloaded anyway, otherwise
+    /// the memory held by the prefetched ones would never be released
+    fn start_downloading(&mut self, mc_block_seq_no: u32, prefetch: bool) {
         let block_maps = Arc::new(Mutex::new(None));
 
         // Add pending archive
@@ -205,9 +221,11 @@ impl ArchivesStream {
 
         // Spawn downloader
         tokio::spawn(async move {
-            if let Some((writer, neighbour)) = download_archive(&ctx, mc_block_seq_no).await {
+            if let Some((writer, neighbour)) =
+                download_archive(&ctx, mc_block_seq_no, prefetch).await
+            {
                 *block_maps.lock() = Some(BlockMapsData {
-                    neighbour: Some(neighbour),
+                    neighbour,
                     writer: Some(writer),
                     loaded: None,
                 });
@@ -229,6 +247,30 @@ struct DownloaderContext {
     new_archive_notification: Notify,
     cancellation_token: CancellationToken,
     good_peers: GoodPeers,
+    /// Archives from the shared storage which were not accepted
+    rejected_stored_archives: Mutex<FastHashSet<u32>>,
+}
+
+impl DownloaderContext {
+    /// Selects a neighbour which has not reached the limit of active archive downloads
+    fn try_acquire_neighbour(&self) -> Option<NeighbourRequestSlot> {
+        let limit = self.engine.sync_options.max_archive_requests_per_peer;
+
+        if let Some(slot) = self
+            .good_peers
+            .get()
+            .and_then(|neighbour| neighbour.try_acquire_request_slot(limit))
+        {
+            return Some(slot);
+        }
+
+        let neighbours = self.engine.masterchain_client.0.neighbours();
+        (0..NEIGHBOUR_SELECTION_ATTEMPTS).find_map(|_| {
+            neighbours
+                .choose_neighbour_with_capabilities(proto::Capabilities::FULL_PROTOCOL)
+                .and_then(|neighbour| neighbour.try_acquire_request_slot(limit))
+        })
+    }
 }
 
 #[derive(Default)]
@@ -375,41 +417,136 @@ impl Drop for ReceivedBlockMaps<'_> {
     fn drop(&mut self) {
         if !self.accepted {
             // Remove peer from good peers
-            if let Some(neighbour) = &self.neighbour {
-                self.stream.ctx.good_peers.remove(neighbour);
+            match &self.neighbour {
+                Some(neighbour) => self.stream.ctx.good_peers.remove(neighbour),
+                // Don't use the shared storage for this archive anymore
+                None => {
+                    self.stream
+                        .ctx
+                        .rejected_stored_archives
+                        .lock()
+                        .insert(self.index);
+                }
             }
 
             tracing::info!(target: "sync", index = self.index, "archive not accepted");
-            self.stream.start_downloading(self.index);
+            self.stream.start_downloading(self.index, false);
         }
     }
 }
 
+/// Moves the downloaded archive into the file DB.
+///
+/// NOTE: the archive is still usable from the downloads dir if it can't be moved
+async fn complete_archive(writer: ArchiveWriter) -> ArchiveWriter {
+    let result = tokio::task::spawn_blocking(move || {
+        let mut writer = writer;
+        let result = writer.complete();
+        (writer, result)
+    })
+    .await;
+
+    match result {
+        Ok((writer, Ok(()))) => writer,
+        Ok((writer, Err(e))) => {
+            tracing::warn!(target: "sync", "failed to move archive into the file DB: {e:?}");
+            writer
+        }
+        Err(e) => std::panic::resume_unwind(e.into_panic()),
+    }
+}
+
 async fn download_archive(
     ctx: &DownloaderContext,
     mc_seq_no: u32,
-) -> Option<(ArchiveWriter, Arc<Neighbour>)> {
+    prefetch: bool,
+) -> Option<(ArchiveWriter, Option<Arc<Neighbour>>)> {
     tokio::pin!(
         let signal = ctx.cancellation_token.cancelled();
     );
 
-    tracing::info!(target: "sync", mc_seq_no, "downloading archive");
+    // Wait until the disk watchdog allows to write archives
+    tokio::select! {
+        _ = ctx.engine.wait_for_disk_space() => {},
+        _ = (&mut signal) => return None,
+    }
+
+    // Wait until the parsed archives release the sync memory
+    if prefetch {
+        let budget = &ctx.engine.sync_memory;
+        while budget.used() >= budget.capacity() {
+            tracing::debug!(target: "sync", mc_seq_no, "sync memory exhausted, postponing prefetch");
+            tokio::select! {
+                _ = tokio::time::sleep(SYNC_MEMORY_CHECK_INTERVAL) => {},
+                _ = (&mut signal) => return None,
+            }
+        }
+    }
+
+    tracing::debug!(target: "sync", mc_seq_no, "downloading archive");
+
+    let metrics = &ctx.engine.metrics.archive_downloads;
+    let _in_flight = InFlightGuard::new(&metrics.in_flight);
+    let on_downloaded = |len: usize| {
+        metrics.downloaded.fetch_add(1, Ordering::Release);
+        metrics
+            .downloaded_bytes
+            .fetch_add(len as u64, Ordering::Release);
+    };
+
+    // Try the archives storage shared with other instances first
+    if !ctx.rejected_stored_archives.lock().contains(&mc_seq_no) {
+        let result = tokio::select! {
+            result = ctx.engine.download_archive_from_storage(mc_seq_no) => result,
+            _ = (&mut signal) => return None,
+        };
+
+        if let Some(data) = result {
+            let mut writer = ctx.writers_pool.acquire();
+            match writer.write_all(&data) {
+                Ok(()) => {
+                    on_downloaded(data.len());
+                    tracing::debug!(
+                        target: "sync",
+                        mc_seq_no,
+                        bytes_len = data.len(),
+                        "downloaded archive from the storage",
+                    );
+                    return Some((complete_archive(writer).await, None));
+                }
+                Err(e) => {
+                    tracing::warn!(target: "sync", mc_seq_no, "failed to write archive: {e:?}")
+                }
+            }
+        }
+    }
 
     loop {
-        let mut writer = ctx.writers_pool.acquire();
+        let slot = match ctx.try_acquire_neighbour() {
+            Some(slot) => slot,
+            None => {
+                // All selected peers are busy
+                tokio::select! {
+                    _ = tokio::time::sleep(NO_FREE_NEIGHBOURS_DELAY) => continue,
+                    _ = (&mut signal) => return None,
+                }
+            }
+        };
+        let neighbour = slot.neighbour();
 
-        let good_peer = ctx.good_peers.get();
+        let mut writer = ctx.writers_pool.acquire();
 
         let start = std::time::Instant::now();
         let result = tokio::select! {
-            result = ctx.engine.download_archive(mc_seq_no, good_peer.as_ref(), &mut writer) => result,
+            result = ctx.engine.download_archive(mc_seq_no, Some(neighbour), &mut writer) => result,
             _ = (&mut signal) => return None,
         };
 
         match result {
             Ok(ArchiveDownloadStatus::Downloaded { neighbour, len }) => {
                 ctx.good_peers.add(&neighbour);
-                tracing::info!(
+                on_downloaded(len);
+                tracing::debug!(
                     target: "sync",
                     mc_seq_no,
                     bytes_len = len,
@@ -417,24 +554,36 @@ async fn download_archive(
                     elapsed_ms = start.elapsed().as_millis(),
                     "downloaded archive",
                 );
-                break Some((writer, neighbour));
+                break Some((complete_archive(writer).await, Some(neighbour)));
             }
             Ok(ArchiveDownloadStatus::NotFound) => {
-                if let Some(neighbour) = &good_peer {
-                    ctx.good_peers.remove(neighbour);
-                }
+                ctx.good_peers.remove(neighbour);
                 tracing::trace!(target: "sync", mc_seq_no, "no archive found");
             }
             Err(e) => {
-                if let Some(neighbour) = &good_peer {
-                    ctx.good_peers.remove(neighbour);
-                }
+                ctx.good_peers.remove(neighbour);
                 tracing::warn!(target: "sync", mc_seq_no, "failed to download archive: {e:?}")
             }
         }
     }
 }
 
+/// Counts archives which are being downloaded
+struct InFlightGuard<'a>(&'a AtomicUsize);
+
+impl<'a> InFlightGuard<'a> {
+    fn new(counter: &'a AtomicUsize) -> Self {
+        counter.fetch_add(1, Ordering::Release);
+        Self(counter)
+    }
+}
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Release);
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
 enum ArchivesStreamError {
     #[error("Empty block maps data")]
@@ -442,3 +591,6 @@ enum ArchivesStreamError {
 }
 
 const ARCHIVE_EXISTENCE_THRESHOLD: u32 = 1800;
+const NEIGHBOUR_SELECTION_ATTEMPTS: usize = 8;
+const NO_FREE_NEIGHBOURS_DELAY: Duration = Duration::from_millis(100);
+const SYNC_MEMORY_CHECK_INTERVAL: Duration = Duration::from_secs(1);
@@ -8,17 +8,20 @@ use crate::utils::*;
 pub struct BlockMaps {
     pub mc_block_ids: BTreeMap<u32, ton_block::BlockIdExt>,
     pub blocks: BTreeMap<ton_block::BlockIdExt, BlockMapsEntry>,
+    /// Memory of the parsed data in the sync memory budget
+    _memory: MemoryReservation,
 }
 
 impl BlockMaps {
     pub const MAX_MC_BLOCK_COUNT: usize = 100;
 
-    pub fn new(data: &[u8]) -> Result<Arc<Self>> {
+    pub fn new(data: &[u8], memory: MemoryReservation) -> Result<Arc<Self>> {
         let mut reader = ArchivePackageViewReader::new(data)?;
 
         let mut maps = BlockMaps {
             mc_block_ids: Default::default(),
             blocks: Default::default(),
+            _memory: memory,
         };
 
         while let Some(entry) = reader.read_next()? {
@@ -71,7 +74,7 @@ impl BlockMaps {
 
         let (left, right) = match (self.lowest_mc_id(), self.highest_mc_id()) {
             (Some(left), Some(right)) => {
-                tracing::info!(
+                tracing::debug!(
                     target: "sync",
                     index,
                     left_seq_no = left.seq_no,
@@ -5,6 +5,7 @@ use anyhow::Result;
 
 use super::archives_stream::*;
 use super::block_maps::*;
+use super::progress::*;
 use crate::engine::Engine;
 use crate::utils::*;
 
@@ -24,11 +25,15 @@ pub async fn historical_sync(engine: &Arc<Engine>, from_seqno: u32) -> Result<()
     let mut ctx = HistoricalSyncContext::new(engine, from, to);
 
     let mut archives = ArchivesStream::new(engine, from..=to, None);
+    let mut progress = SyncProgress::new(engine, from, Some(to));
     loop {
         let archive = archives.recv().await;
         match ctx.handle(archive.clone()).await {
             Ok(ControlFlow::Break(())) => break,
             Ok(_) => {
+                if let Some(id) = archive.highest_mc_id() {
+                    progress.update(id.seq_no, None);
+                }
                 archive.accept(ctx.last_archive_edge.clone());
             }
             Err(e) => {
@@ -73,7 +78,7 @@ impl<'a> HistoricalSyncContext<'a> {
         let mut block_edge = self.last_archive_edge.clone();
 
         self.process_blocks(&maps, &mut block_edge).await?;
-        tracing::info!(
+        tracing::debug!(
             target: "sync",
             lowest_id = %lowest_id.display(),
             highest_id = %highest_id.display(),
@@ -130,7 +135,11 @@ impl<'a> HistoricalSyncContext<'a> {
 
             splits.clear();
             let mut tasks = Vec::with_capacity(shard_blocks.len());
-            for (_, id) in shard_blocks {
+            for (shard, id) in shard_blocks {
+                if !self.engine.sync_options.is_shard_indexed(&shard) {
+                    continue;
+                }
+
                 fn should_process(
                     maps: &BlockMaps,
                     edge: &Option<BlockMapsEdge>,
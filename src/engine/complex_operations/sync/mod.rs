@@ -10,11 +10,13 @@ use crate::utils::*;
 use self::archives_stream::*;
 use self::block_maps::*;
 pub use self::historical_sync::*;
+use self::progress::*;
 
 mod archive_writers_pool;
 mod archives_stream;
 mod block_maps;
 mod historical_sync;
+mod progress;
 
 pub async fn sync(engine: &Arc<Engine>) -> Result<()> {
     tracing::info!(target: "sync", "started normal sync");
@@ -27,6 +29,7 @@ pub async fn sync(engine: &Arc<Engine>) -> Result<()> {
     );
 
     let mut archives = ArchivesStream::new(engine, last_mc_block_id.seq_no + 1.., None);
+    let mut progress = SyncProgress::new(engine, last_mc_block_id.seq_no, None);
 
     let mut last_gen_utime = 0;
     loop {
@@ -52,6 +55,10 @@ pub async fn sync(engine: &Arc<Engine>) -> Result<()> {
         }
 
         last_mc_block_id = engine.last_applied_block()?;
+        progress.update(
+            last_mc_block_id.seq_no,
+            (last_gen_utime > 0).then(|| last_gen_utime),
+        );
         archive.accept_with_time(last_gen_utime, None); // TODO
     }
 
@@ -75,10 +82,14 @@ async fn import_package_with_apply(
     let import_start = std::time::Instant::now();
 
     import_mc_blocks_with_apply(engine, &maps, last_mc_block_id, last_gen_utime).await?;
-    import_shard_blocks_with_apply(engine, &maps).await?;
+    if engine.sync_options.masterchain_only {
+        skip_shard_blocks(engine, &maps).await?;
+    } else {
+        import_shard_blocks_with_apply(engine, &maps).await?;
+    }
 
     let elapsed_ms = import_start.elapsed().as_millis();
-    tracing::info!(
+    tracing::debug!(
         target: "sync",
         block_id = %last_mc_block_id.display(),
         elapsed_ms,
@@ -146,7 +157,7 @@ async fn import_mc_blocks_with_apply(
         humantime::format_duration(std::time::Duration::from_secs(diff as u64))
     };
 
-    tracing::info!(
+    tracing::debug!(
         target: "sync",
         last_mc_block_id = %last_mc_block_id.display(),
         %block_time_diff,
@@ -160,7 +171,7 @@ async fn import_shard_blocks_with_apply(engine: &Arc<Engine>, maps: &Arc<BlockMa
 
     // Save all shardchain blocks
     for (id, entry) in &maps.blocks {
-        if !id.shard_id.is_masterchain() {
+        if !id.shard_id.is_masterchain() && engine.sync_options.is_shard_indexed(&id.shard_id) {
             let (block, block_proof) = entry.get_data()?;
             engine.save_block(block, block_proof, 0).await?;
         }
@@ -185,9 +196,16 @@ async fn import_shard_blocks_with_apply(engine: &Arc<Engine>, maps: &Arc<BlockMa
             .await?;
         let shard_blocks = masterchain_block.shard_blocks()?;
 
+        // Detached shards are resumed in background from their own persistent state
+        engine.resume_detached_shards(&masterchain_block)?;
+
         // Start applying blocks for each shard
         let mut tasks = Vec::with_capacity(shard_blocks.len());
-        for (_, id) in shard_blocks {
+        for (shard, id) in shard_blocks {
+            if !engine.is_shard_tracked(&shard) {
+                continue;
+            }
+
             let engine = engine.clone();
             let maps = maps.clone();
             tasks.push(tokio::spawn(async move {
@@ -232,7 +250,7 @@ async fn import_shard_blocks_with_apply(engine: &Arc<Engine>, maps: &Arc<BlockMa
                 //  if too many shardchain blocks are missing
 
                 // Apply shardchain blocks recursively
-                match block {
+                let result = match block {
                     Some(block) => {
                         engine
                             .apply_block_ext(&handle, block.as_ref(), mc_seq_no, false, 0)
@@ -244,6 +262,19 @@ async fn import_shard_blocks_with_apply(engine: &Arc<Engine>, maps: &Arc<BlockMa
                             .download_and_apply_block(handle.id(), mc_seq_no, false, 0)
                             .await
                     }
+                };
+
+                match result {
+                    // Detached shards are resumed from the next persistent state
+                    Err(e) if engine.is_shard_detached(&shard) => {
+                        tracing::warn!(
+                            target: "sync",
+                            block_id = %id.display(),
+                            "shard detached until the next persistent state: {e:?}"
+                        );
+                        Ok(())
+                    }
+                    result => result,
                 }
             }));
         }
@@ -255,12 +286,25 @@ async fn import_shard_blocks_with_apply(engine: &Arc<Engine>, maps: &Arc<BlockMa
             .unwrap_or(Ok(()))?;
 
         engine.store_shards_client_mc_block_id(mc_block_id)?;
+        engine.commit_delivered_mc_block(mc_seq_no).await?;
         last_applied_mc_block_id = mc_block_id.clone();
     }
 
     Ok(())
 }
 
+/// Moves shards client to the last masterchain block in archive without applying shard blocks
+async fn skip_shard_blocks(engine: &Arc<Engine>, maps: &BlockMaps) -> Result<()> {
+    let last_applied_mc_block_id = engine.load_shards_client_mc_block_id()?;
+    if let Some(mc_block_id) = maps.mc_block_ids.values().next_back() {
+        if mc_block_id.seq_no > last_applied_mc_block_id.seq_no {
+            engine.store_shards_client_mc_block_id(mc_block_id)?;
+            engine.commit_delivered_mc_block(mc_block_id.seq_no).await?;
+        }
+    }
+    Ok(())
+}
+
 impl Engine {
     fn last_applied_block(&self) -> Result<ton_block::BlockIdExt> {
         let mc_block_id = self.load_last_applied_mc_block_id()?;
@@ -0,0 +1,96 @@
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+use crate::engine::Engine;
+
+/// Periodically reports the sync progress instead of logging each archive
+pub struct SyncProgress<'a> {
+    engine: &'a Engine,
+    started_at: Instant,
+    last_report_at: Instant,
+    from_seqno: u32,
+    to_seqno: Option<u32>,
+    start_gen_utime: Option<u32>,
+}
+
+impl<'a> SyncProgress<'a> {
+    /// `to_seqno` is used to estimate the remaining time. Otherwise,
+    /// the sync is considered complete when the node reaches the current time
+    pub fn new(engine: &'a Engine, from_seqno: u32, to_seqno: Option<u32>) -> Self {
+        let now = Instant::now();
+        Self {
+            engine,
+            started_at: now,
+            last_report_at: now,
+            from_seqno,
+            to_seqno,
+            start_gen_utime: None,
+        }
+    }
+
+    /// Updates progress after the archive was processed
+    pub fn update(&mut self, mc_seq_no: u32, gen_utime: Option<u32>) {
+        if let Some(gen_utime) = gen_utime {
+            self.start_gen_utime.get_or_insert(gen_utime);
+        }
+
+        let now = Instant::now();
+        if now.duration_since(self.last_report_at) < REPORT_INTERVAL {
+            return;
+        }
+        self.last_report_at = now;
+
+        let elapsed = now.duration_since(self.started_at).as_secs_f64();
+        let blocks_per_sec = mc_seq_no.saturating_sub(self.from_seqno) as f64 / elapsed;
+
+        let eta = match (self.to_seqno, self.start_gen_utime, gen_utime) {
+            (Some(to_seqno), _, _) => {
+                estimate_eta(to_seqno.saturating_sub(mc_seq_no) as f64, blocks_per_sec)
+            }
+            (None, Some(start_gen_utime), Some(gen_utime)) => {
+                // Seconds of the chain time processed in one second
+                let chain_speed = gen_utime.saturating_sub(start_gen_utime) as f64 / elapsed;
+                let lag = broxus_util::now().saturating_sub(gen_utime) as f64;
+                estimate_eta(lag, chain_speed - 1.0)
+            }
+            _ => None,
+        };
+        let eta = match eta {
+            Some(eta) => humantime::format_duration(eta).to_string(),
+            None => "unknown".to_owned(),
+        };
+
+        let metrics = &self.engine.metrics.archive_downloads;
+        tracing::info!(
+            target: "sync",
+            mc_seq_no,
+            blocks_per_sec = %format_args!("{blocks_per_sec:.1}"),
+            %eta,
+            archives_in_flight = metrics.in_flight.load(Ordering::Acquire),
+            archives_downloaded = metrics.downloaded.load(Ordering::Acquire),
+            downloaded = %bytesize::ByteSize(metrics.downloaded_bytes.load(Ordering::Acquire)),
+            "sync progress"
+        );
+    }
+}
+
+/// Computes the remaining time with the specified progress rate
+fn estimate_eta(remaining: f64, rate: f64) -> Option<Duration> {
+    let eta = remaining / rate;
+    (rate > 0.0 && eta.is_finite()).then(|| Duration::from_secs(eta as u64))
+}
+
+const REPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn eta() {
+        assert_eq!(estimate_eta(100.0, 10.0), Some(Duration::from_secs(10)));
+        assert_eq!(estimate_eta(0.0, 10.0), Some(Duration::ZERO));
+        assert_eq!(estimate_eta(100.0, 0.0), None);
+        assert_eq!(estimate_eta(100.0, -1.0), None);
+    }
+}
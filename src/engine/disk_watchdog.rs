@@ -0,0 +1,261 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use tokio::sync::Notify;
+
+use super::Engine;
+use crate::config::DiskWatchdogOptions;
+use crate::storage::EngineEvent;
+
+pub(super) struct DiskWatchdog {
+    options: DiskWatchdogOptions,
+    rocks_db_path: PathBuf,
+    file_db_path: PathBuf,
+    level_changed: Notify,
+}
+
+impl DiskWatchdog {
+    pub fn new(
+        options: DiskWatchdogOptions,
+        rocks_db_path: PathBuf,
+        file_db_path: PathBuf,
+    ) -> Self {
+        Self {
+            options,
+            rocks_db_path,
+            file_db_path,
+            level_changed: Notify::new(),
+        }
+    }
+}
+
+/// Free disk space
+#[derive(Debug, Default)]
+pub struct DiskSpaceMetrics {
+    /// Available bytes on the disk with `rocks_db_path`
+    pub rocks_db_available: AtomicU64,
+    /// Available bytes on the disk with `file_db_path`
+    pub file_db_available: AtomicU64,
+    /// Current disk space level
+    pub level: DiskSpaceLevelCell,
+}
+
+#[derive(Debug, Default)]
+pub struct DiskSpaceLevelCell(AtomicU8);
+
+impl DiskSpaceLevelCell {
+    pub fn load(&self) -> DiskSpaceLevel {
+        match self.0.load(Ordering::Acquire) {
+            0 => DiskSpaceLevel::Ok,
+            1 => DiskSpaceLevel::Low,
+            2 => DiskSpaceLevel::Critical,
+            _ => DiskSpaceLevel::Exhausted,
+        }
+    }
+
+    fn swap(&self, level: DiskSpaceLevel) -> DiskSpaceLevel {
+        let prev = self.load();
+        self.0.store(level as u8, Ordering::Release);
+        prev
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub enum DiskSpaceLevel {
+    /// Enough free space
+    Ok = 0,
+    /// Below `gc_threshold`, states, blocks and archives GC are triggered
+    Low = 1,
+    /// Below `pause_downloads_threshold`, archive downloads are paused
+    Critical = 2,
+    /// Below `halt_threshold`, storage writes are halted and the engine is stopped
+    Exhausted = 3,
+}
+
+impl DiskSpaceLevel {
+    fn from_available(options: &DiskWatchdogOptions, available: u64) -> Self {
+        if available < options.halt_threshold.as_u64() {
+            Self::Exhausted
+        } else if available < options.pause_downloads_threshold.as_u64() {
+            Self::Critical
+        } else if available < options.gc_threshold.as_u64() {
+            Self::Low
+        } else {
+            Self::Ok
+        }
+    }
+}
+
+impl Engine {
+    pub(super) fn start_disk_watchdog(self: &Arc<Self>) {
+        let interval = match &self.disk_watchdog {
+            Some(watchdog) => Duration::from_secs(watchdog.options.interval_sec),
+            None => return,
+        };
+
+        let engine = Arc::downgrade(self);
+        tokio::spawn(async move {
+            loop {
+                let engine = match engine.upgrade() {
+                    Some(engine) => engine,
+                    None => return,
+                };
+
+                match engine.check_disk_space() {
+                    Ok(DiskSpaceLevel::Exhausted) => return,
+                    Ok(_) => {}
+                    Err(e) => tracing::error!("failed to check disk space: {e:?}"),
+                }
+
+                drop(engine);
+                tokio::time::sleep(interval).await;
+            }
+        });
+    }
+
+    /// Waits until there is enough free space to download archives
+    pub(crate) async fn wait_for_disk_space(&self) {
+        let watchdog = match &self.disk_watchdog {
+            Some(watchdog) => watchdog,
+            None => return,
+        };
+
+        let mut logged = false;
+        loop {
+            tokio::pin!(let level_changed = watchdog.level_changed.notified(););
+
+            if self.metrics.disk_space.level.load() < DiskSpaceLevel::Critical {
+                return;
+            }
+
+            if !logged {
+                tracing::warn!("archive downloads are paused due to low disk space");
+                logged = true;
+            }
+            level_changed.await;
+        }
+    }
+
+    fn check_disk_space(self: &Arc<Self>) -> Result<DiskSpaceLevel> {
+        let watchdog = match &self.disk_watchdog {
+            Some(watchdog) => watchdog,
+            None => return Ok(DiskSpaceLevel::Ok),
+        };
+
+        let metrics = &self.metrics.disk_space;
+
+        let rocks_db_available = available_space(&watchdog.rocks_db_path)?;
+        let file_db_available = available_space(&watchdog.file_db_path)?;
+        metrics
+            .rocks_db_available
+            .store(rocks_db_available, Ordering::Release);
+        metrics
+            .file_db_available
+            .store(file_db_available, Ordering::Release);
+
+        let available = std::cmp::min(rocks_db_available, file_db_available);
+        let level = DiskSpaceLevel::from_available(&watchdog.options, available);
+
+        let prev_level = metrics.level.swap(level);
+        if level == prev_level {
+            return Ok(level);
+        }
+        watchdog.level_changed.notify_waiters();
+
+        if prev_level == DiskSpaceLevel::Exhausted {
+            self.db.resume_writes();
+        }
+
+        if level == DiskSpaceLevel::Ok {
+            tracing::info!(available, "disk space recovered");
+            return Ok(level);
+        }
+
+        tracing::warn!(available, ?level, "low disk space");
+
+        if level > prev_level && prev_level == DiskSpaceLevel::Ok {
+            // Try to free some space.
+            // NOTE: compaction is not triggered here because it needs extra space
+            self.trigger_gc();
+        }
+
+        if level == DiskSpaceLevel::Exhausted {
+            tracing::error!(available, "disk space exhausted, halting writes");
+            self.record_event(EngineEvent::DiskSpaceExhausted {
+                available_bytes: available,
+            });
+            self.db.halt_writes();
+            self.shutdown();
+        }
+
+        Ok(level)
+    }
+
+    /// Runs all enabled GCs without waiting for their schedule
+    fn trigger_gc(self: &Arc<Self>) {
+        self.states_gc_trigger.notify_one();
+        self.archives_gc_trigger.notify_one();
+
+        if matches!(&self.blocks_gc_state, Some(state) if state.enabled.load(Ordering::Acquire)) {
+            let engine = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = engine.run_blocks_gc().await {
+                    tracing::error!("failed to GC blocks: {e:?}");
+                }
+            });
+        }
+    }
+}
+
+/// Returns the number of bytes available to unprivileged users
+/// on the filesystem containing the path
+fn available_space(path: &Path) -> Result<u64> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes())?;
+
+    let mut stat = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: path is a valid nul-terminated string and `stat` is a valid pointer
+    let res = unsafe { libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) };
+    if res != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+
+    // SAFETY: `statvfs` succeeded, so `stat` is initialized
+    let stat = unsafe { stat.assume_init() };
+    #[allow(clippy::unnecessary_cast)]
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use bytesize::ByteSize;
+
+    use super::*;
+
+    #[test]
+    fn disk_space_levels() {
+        let options = DiskWatchdogOptions {
+            interval_sec: 10,
+            gc_threshold: ByteSize::gb(20),
+            pause_downloads_threshold: ByteSize::gb(10),
+            halt_threshold: ByteSize::gb(2),
+        };
+
+        let level = |available| DiskSpaceLevel::from_available(&options, available);
+        assert_eq!(level(ByteSize::gb(100).as_u64()), DiskSpaceLevel::Ok);
+        assert_eq!(level(ByteSize::gb(20).as_u64()), DiskSpaceLevel::Ok);
+        assert_eq!(level(ByteSize::gb(15).as_u64()), DiskSpaceLevel::Low);
+        assert_eq!(level(ByteSize::gb(5).as_u64()), DiskSpaceLevel::Critical);
+        assert_eq!(level(0), DiskSpaceLevel::Exhausted);
+    }
+
+    #[test]
+    fn available_space_of_current_dir() {
+        assert!(available_space(Path::new(".")).is_ok());
+        assert!(available_space(Path::new("/definitely/not/exists")).is_err());
+    }
+}
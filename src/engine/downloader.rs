@@ -56,7 +56,10 @@ impl Downloader for BlockDownloader {
             return Ok(Some(full_block));
         }
 
-        context.client.download_block_full(context.block_id).await
+        context
+            .client
+            .download_block_full(context.block_id, context.max_requests_per_peer)
+            .await
     }
 }
 
@@ -120,7 +123,7 @@ impl Downloader for NextBlockDownloader {
 
         context
             .client
-            .download_next_block_full(context.block_id)
+            .download_next_block_full(context.block_id, context.max_requests_per_peer)
             .await
     }
 }
@@ -157,6 +160,8 @@ pub struct DownloadContext<'a, T> {
 
     pub client: &'a NodeRpcClient,
     pub storage: &'a Storage,
+    /// Limit of the active RLDP requests to the same neighbour
+    pub max_requests_per_peer: u32,
 
     pub downloader: Arc<dyn Downloader<Item = T>>,
     pub explicit_neighbour: Option<&'a Arc<Neighbour>>,
@@ -0,0 +1,308 @@
+use std::ffi::OsString;
+use std::fs::File;
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use parking_lot::Mutex;
+
+use super::Engine;
+use crate::config::LeaderElectionOptions;
+use crate::storage::EngineEvent;
+
+/// Leadership coordination between multiple instances.
+///
+/// Only the leader notifies subscribers, standby instances
+/// keep syncing without side effects.
+///
+/// The leader commits the last fully delivered masterchain block into the lock backend,
+/// and standby instances don't apply masterchain blocks beyond this offset. So after
+/// the takeover the new leader continues delivering from the offset of the previous one.
+/// Blocks after the offset can be delivered twice (at-least-once)
+#[async_trait::async_trait]
+pub trait LeaderLock: Send + Sync {
+    /// Interval of leadership checks
+    fn check_interval(&self) -> Duration {
+        Duration::from_secs(5)
+    }
+
+    /// Acquires or renews the leadership.
+    ///
+    /// Returns `true` if this instance is the leader
+    async fn try_acquire(&self) -> Result<bool>;
+
+    /// Loads the last masterchain block seqno committed by the leader.
+    ///
+    /// Returns `None` if nothing was committed yet
+    async fn last_delivered(&self) -> Result<Option<u32>>;
+
+    /// Stores the masterchain block seqno up to which all blocks
+    /// (including shard blocks) were delivered to subscribers.
+    ///
+    /// NOTE: called only by the leader
+    async fn commit(&self, mc_seqno: u32) -> Result<()>;
+}
+
+impl std::fmt::Debug for dyn LeaderLock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LeaderLock")
+            .field("check_interval", &self.check_interval())
+            .finish()
+    }
+}
+
+/// Selects leader lock from options.
+///
+/// User provided lock has priority over the built-in one
+pub fn create_leader_lock(options: &LeaderElectionOptions) -> Option<Arc<dyn LeaderLock>> {
+    if let Some(lock) = &options.lock {
+        return Some(lock.clone());
+    }
+
+    let path = options.lock_path.clone()?;
+    Some(Arc::new(FileLeaderLock::new(path)))
+}
+
+/// Exclusive `flock` on the file.
+///
+/// The lock is released by the OS when the process exits, so the standby
+/// instance takes over even if the leader crashed.
+///
+/// The delivery offset is stored next to the lock file (`{lock_path}.offset`).
+///
+/// NOTE: `flock` is not reliable on some network filesystems
+pub struct FileLeaderLock {
+    path: PathBuf,
+    offset_path: PathBuf,
+    file: Mutex<Option<File>>,
+}
+
+impl FileLeaderLock {
+    pub fn new(path: PathBuf) -> Self {
+        let offset_path = with_suffix(&path, ".offset");
+        Self {
+            path,
+            offset_path,
+            file: Default::default(),
+        }
+    }
+
+    fn try_lock(&self) -> Result<bool> {
+        use std::os::unix::io::AsRawFd;
+
+        let mut file = self.file.lock();
+        if file.is_some() {
+            // Lock is held until the file is closed
+            return Ok(true);
+        }
+
+        let new_file = std::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(false)
+            .open(&self.path)
+            .context("Failed to open leader lock file")?;
+
+        // SAFETY: fd is valid while the file is alive
+        let res = unsafe { libc::flock(new_file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) };
+        if res == 0 {
+            *file = Some(new_file);
+            return Ok(true);
+        }
+
+        let e = std::io::Error::last_os_error();
+        if e.kind() == std::io::ErrorKind::WouldBlock {
+            Ok(false)
+        } else {
+            Err(e).context("Failed to lock leader lock file")
+        }
+    }
+
+    fn read_offset(&self) -> Result<Option<u32>> {
+        match std::fs::read(&self.offset_path) {
+            Ok(data) if data.len() >= 4 => {
+                Ok(Some(u32::from_le_bytes(data[..4].try_into().unwrap())))
+            }
+            Ok(_) => Ok(None),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).context("Failed to read leader offset"),
+        }
+    }
+
+    fn write_offset(&self, mc_seqno: u32) -> Result<()> {
+        // Write the whole file and replace the old one so that
+        // standby instances never see a partially written offset
+        let temp_path = with_suffix(&self.offset_path, ".tmp");
+        std::fs::write(&temp_path, mc_seqno.to_le_bytes())
+            .context("Failed to write leader offset")?;
+        std::fs::rename(&temp_path, &self.offset_path).context("Failed to replace leader offset")
+    }
+}
+
+#[async_trait::async_trait]
+impl LeaderLock for FileLeaderLock {
+    async fn try_acquire(&self) -> Result<bool> {
+        self.try_lock()
+    }
+
+    async fn last_delivered(&self) -> Result<Option<u32>> {
+        self.read_offset()
+    }
+
+    async fn commit(&self, mc_seqno: u32) -> Result<()> {
+        self.write_offset(mc_seqno)
+    }
+}
+
+fn with_suffix(path: &std::path::Path, suffix: &str) -> PathBuf {
+    let mut path = OsString::from(path);
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+impl Engine {
+    /// Whether this instance notifies subscribers
+    pub fn is_leader(&self) -> bool {
+        self.is_leader.load(Ordering::Acquire)
+    }
+
+    /// The last masterchain block seqno which was delivered to subscribers.
+    ///
+    /// Uses the offset committed into the leader lock if it is configured
+    pub async fn last_delivered_mc_seqno(&self) -> Result<Option<u32>> {
+        match &self.leader_lock {
+            Some(lock) => lock.last_delivered().await,
+            None => self.storage.node_state().load_last_delivered_mc_seqno(),
+        }
+    }
+
+    /// Commits the masterchain block after all its shard blocks were delivered
+    pub(super) async fn commit_delivered_mc_block(&self, mc_seq_no: u32) -> Result<()> {
+        match &self.leader_lock {
+            Some(lock) if self.is_leader() => lock
+                .commit(mc_seq_no)
+                .await
+                .context("Failed to commit delivered masterchain block"),
+            _ => Ok(()),
+        }
+    }
+
+    /// Holds the masterchain block application on the standby instance
+    /// until the leader delivers this block.
+    ///
+    /// This way the standby never skips blocks which were not delivered by the leader
+    pub(super) async fn wait_for_leader_offset(&self, mc_seq_no: u32) -> Result<()> {
+        let lock = match &self.leader_lock {
+            Some(lock) => lock,
+            None => return Ok(()),
+        };
+
+        while !self.is_leader() && self.is_working() {
+            if matches!(lock.last_delivered().await?, Some(seqno) if seqno >= mc_seq_no) {
+                break;
+            }
+            tokio::time::sleep(lock.check_interval()).await;
+        }
+        Ok(())
+    }
+
+    /// Waits for the initial leadership check and starts periodic checks
+    pub(super) async fn start_leader_election(self: &Arc<Self>) -> Result<()> {
+        let lock = match &self.leader_lock {
+            Some(lock) => lock.clone(),
+            None => return Ok(()),
+        };
+
+        self.update_leadership(lock.try_acquire().await?).await;
+
+        let interval = lock.check_interval();
+        let engine = Arc::downgrade(self);
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+
+                let engine = match engine.upgrade() {
+                    Some(engine) => engine,
+                    None => return,
+                };
+
+                match lock.try_acquire().await {
+                    Ok(is_leader) => engine.update_leadership(is_leader).await,
+                    Err(e) => {
+                        // Step down if the leadership can't be confirmed
+                        tracing::error!("failed to check leadership: {e:?}");
+                        engine.update_leadership(false).await;
+                    }
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    async fn update_leadership(&self, is_leader: bool) {
+        if self.is_leader.swap(is_leader, Ordering::AcqRel) == is_leader {
+            return;
+        }
+
+        if is_leader {
+            let last_applied = self
+                .load_last_applied_mc_block_id()
+                .map(|id| id.seq_no)
+                .ok();
+            let last_delivered = self.last_delivered_mc_seqno().await.ok().flatten();
+            tracing::info!(
+                ?last_applied,
+                ?last_delivered,
+                "acquired leadership, subscribers are notified"
+            );
+        } else {
+            tracing::warn!("lost leadership, subscribers are not notified");
+        }
+        self.record_event(EngineEvent::LeadershipChanged { is_leader });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn file_lock_is_exclusive() {
+        let path = std::env::temp_dir().join(format!("ton-indexer-leader-{}", std::process::id()));
+
+        let first = FileLeaderLock::new(path.clone());
+        let second = FileLeaderLock::new(path.clone());
+
+        assert!(first.try_acquire().await.unwrap());
+        // Renewal succeeds
+        assert!(first.try_acquire().await.unwrap());
+        assert!(!second.try_acquire().await.unwrap());
+
+        // Lock is released when the file is closed
+        drop(first);
+        assert!(second.try_acquire().await.unwrap());
+
+        std::fs::remove_file(path).ok();
+    }
+
+    #[tokio::test]
+    async fn file_lock_offset_is_shared() {
+        let path =
+            std::env::temp_dir().join(format!("ton-indexer-leader-offset-{}", std::process::id()));
+
+        let leader = FileLeaderLock::new(path.clone());
+        let standby = FileLeaderLock::new(path.clone());
+        assert_eq!(standby.last_delivered().await.unwrap(), None);
+
+        leader.commit(123).await.unwrap();
+        assert_eq!(standby.last_delivered().await.unwrap(), Some(123));
+        leader.commit(124).await.unwrap();
+        assert_eq!(standby.last_delivered().await.unwrap(), Some(124));
+
+        std::fs::remove_file(&leader.offset_path).ok();
+        std::fs::remove_file(path).ok();
+    }
+}
@@ -6,7 +6,7 @@
 /// - slightly changed application of blocks
 ///
 use std::io::Write;
-use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU32, AtomicU64, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU32, AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -24,13 +24,29 @@ use crate::network::*;
 use crate::storage::*;
 use crate::utils::*;
 
+pub use self::account_changes::AccountChange;
+use self::accounts::collect_shard_blocks;
+pub use self::accounts::{AccountState, SpecialAccount, SpecialAccounts};
 use self::complex_operations::*;
+use self::disk_watchdog::DiskWatchdog;
+pub use self::disk_watchdog::{DiskSpaceLevel, DiskSpaceLevelCell, DiskSpaceMetrics};
 use self::downloader::*;
+pub use self::leader_lock::{FileLeaderLock, LeaderLock};
 pub use self::node_rpc::*;
+#[cfg(feature = "tvm")]
+pub use self::tvm::GetMethodOutput;
+pub use self::uploader::{ArchiveUploadLease, ArchiveUploader};
 
+mod account_changes;
+mod accounts;
 pub mod complex_operations;
+mod disk_watchdog;
 mod downloader;
+mod leader_lock;
 mod node_rpc;
+#[cfg(feature = "tvm")]
+mod tvm;
+mod uploader;
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum EngineStatus {
@@ -43,6 +59,9 @@ pub struct Engine {
     db: Arc<Db>,
     storage: Arc<Storage>,
     states_gc_options: Option<StateGcOptions>,
+    /// Triggers states GC before the scheduled time
+    states_gc_trigger: Arc<Notify>,
+    archives_gc_trigger: Arc<Notify>,
     blocks_gc_state: Option<BlocksGcState>,
     subscribers: Vec<Arc<dyn Subscriber>>,
     network: Arc<NodeNetwork>,
@@ -56,13 +75,26 @@ pub struct Engine {
     hard_forks: FastHashSet<ton_block::BlockIdExt>,
 
     archive_options: Option<ArchiveOptions>,
+    archive_uploader: Option<Arc<dyn ArchiveUploader>>,
     sync_options: SyncOptions,
+    /// Indexed shards which are skipped until the next persistent state
+    detached_shards: parking_lot::Mutex<FastHashSet<ton_block::ShardIdent>>,
+    leader_lock: Option<Arc<dyn LeaderLock>>,
+    /// Whether this instance notifies subscribers
+    is_leader: AtomicBool,
 
     shard_states_operations: ShardStatesOperationsPool,
     block_applying_operations: BlockApplyingOperationsPool,
     next_block_applying_operations: NextBlockApplyingOperationsPool,
     download_block_operations: DownloadBlockOperationsPool,
+    applied_blocks_operations: AppliedBlocksOperationsPool,
     shard_states_cache: ShardStateCache,
+    /// Memory held by archives, block maps and cached states
+    sync_memory: MemoryBudget,
+    applier_pool: WorkerPool,
+    disk_watchdog: Option<DiskWatchdog>,
+    subscriber_deliveries_options: SubscriberDeliveriesOptions,
+    event_journal_options: EventJournalOptions,
 
     metrics: Arc<EngineMetrics>,
 }
@@ -72,6 +104,17 @@ type BlockApplyingOperationsPool = OperationsPool<ton_block::BlockIdExt, ()>;
 type NextBlockApplyingOperationsPool = OperationsPool<ton_block::BlockIdExt, ton_block::BlockIdExt>;
 type DownloadBlockOperationsPool =
     OperationsPool<ton_block::BlockIdExt, (BlockStuffAug, BlockProofStuffAug)>;
+type AppliedBlocksOperationsPool = OperationsPool<AppliedBlockKey, ton_block::BlockIdExt>;
+
+/// Short block id used to wait for the block application by seqno
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+struct AppliedBlockKey(BlockIdShort);
+
+impl std::fmt::Display for AppliedBlockKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Display::fmt(&self.0.display(), f)
+    }
+}
 
 struct BlocksGcState {
     ty: BlocksGcKind,
@@ -92,11 +135,19 @@ impl Engine {
         subscribers: Vec<Arc<dyn Subscriber>>,
     ) -> Result<Arc<Self>> {
         let old_blocks_policy = config.sync_options.old_blocks_policy;
+        let disk_watchdog = config.disk_watchdog_options.map(|options| {
+            DiskWatchdog::new(
+                options,
+                config.rocks_db_path.clone(),
+                config.file_db_path.clone(),
+            )
+        });
         let db = Db::open(config.rocks_db_path, config.db_options)?;
         let cells_storage_size_bytes = config.db_options.cells_cache_size;
         let storage = Storage::new(
             db.clone(),
             config.file_db_path,
+            config.download_tmp_path,
             cells_storage_size_bytes.as_u64(),
         )
         .await
@@ -120,6 +171,7 @@ impl Engine {
         );
 
         let hard_forks = global_config.hard_forks.clone().into_iter().collect();
+        let detached_shards = storage.node_state().load_detached_shards()?;
 
         let network = NodeNetwork::new(
             config.ip_address,
@@ -127,6 +179,7 @@ impl Engine {
             config.adnl_options,
             config.rldp_options,
             config.dht_options,
+            config.dht_publish_options,
             config.neighbours_options,
             config.overlay_shard_options,
             global_config,
@@ -153,11 +206,28 @@ impl Engine {
 
         tracing::info!("network started");
 
+        let applier_pool = WorkerPool::new("applier", config.sync_options.applier_threads)
+            .context("Failed to create applier pool")?;
+
+        let sync_memory = MemoryBudget::new(config.sync_options.max_sync_memory);
+
+        let archive_uploader = match &config.archive_options {
+            Some(options) => uploader::create_archive_uploader(options).await?,
+            None => None,
+        };
+
+        let leader_lock = config
+            .leader_election_options
+            .as_ref()
+            .and_then(leader_lock::create_leader_lock);
+
         Ok(Arc::new(Self {
             is_working: AtomicBool::new(true),
             db,
             storage,
             states_gc_options: config.state_gc_options,
+            states_gc_trigger: Arc::new(Notify::new()),
+            archives_gc_trigger: Arc::new(Notify::new()),
             blocks_gc_state: config.blocks_gc_options.map(|options| BlocksGcState {
                 ty: options.kind,
                 max_blocks_per_batch: options.max_blocks_per_batch,
@@ -172,12 +242,25 @@ impl Engine {
             init_mc_block_id,
             hard_forks,
             archive_options: config.archive_options,
+            archive_uploader,
             sync_options: config.sync_options,
+            detached_shards: parking_lot::Mutex::new(detached_shards.into_iter().collect()),
+            is_leader: AtomicBool::new(leader_lock.is_none()),
+            leader_lock,
             shard_states_operations: OperationsPool::new("shard_states_operations"),
             block_applying_operations: OperationsPool::new("block_applying_operations"),
             next_block_applying_operations: OperationsPool::new("next_block_applying_operations"),
             download_block_operations: OperationsPool::new("download_block_operations"),
-            shard_states_cache: ShardStateCache::new(config.shard_state_cache_options),
+            applied_blocks_operations: OperationsPool::new("applied_blocks_operations"),
+            shard_states_cache: ShardStateCache::new(
+                config.shard_state_cache_options,
+                sync_memory.clone(),
+            ),
+            sync_memory,
+            applier_pool,
+            disk_watchdog,
+            subscriber_deliveries_options: config.subscriber_deliveries_options,
+            event_journal_options: config.event_journal_options,
             metrics: Arc::new(Default::default()),
         }))
     }
@@ -191,14 +274,32 @@ impl Engine {
         self.network
             .add_subscriber(ton_block::BASE_WORKCHAIN_ID, service);
 
+        // Start monitoring free disk space
+        self.start_disk_watchdog();
+
+        // Check leadership before any subscriber is notified
+        self.start_leader_election().await?;
+
         // Boot
         boot(self).await?;
+        if let Err(e) = self.prewarm_shard_states_cache().await {
+            tracing::warn!("failed to prewarm shard states cache: {e:?}");
+        }
+        self.prepare_code_hash_index().await?;
+        if let Err(e) = self.prune_engine_events().await {
+            tracing::warn!("failed to prune engine events: {e:?}");
+        }
+        self.record_event(EngineEvent::Booted {
+            last_mc_block_id: self.load_last_applied_mc_block_id()?.to_string(),
+        });
         self.notify_subscribers_with_status(EngineStatus::Booted)
             .await;
 
         // Start listening broadcasts
         self.listen_broadcasts(&self.masterchain_client);
-        self.listen_broadcasts(&self.basechain_client);
+        if !self.sync_options.masterchain_only {
+            self.listen_broadcasts(&self.basechain_client);
+        }
 
         // Start archives gc
         self.start_archives_gc().await?;
@@ -207,11 +308,27 @@ impl Engine {
         match self.old_blocks_policy {
             OldBlocksPolicy::Ignore => { /* do nothing */ }
             OldBlocksPolicy::Sync { from_seqno } => {
+                self.record_event(EngineEvent::SyncStarted {
+                    historical: true,
+                    from_mc_seqno: from_seqno,
+                });
                 historical_sync(self, from_seqno).await?;
+                self.record_event(EngineEvent::SyncFinished {
+                    historical: true,
+                    last_mc_block_id: self.load_last_applied_mc_block_id()?.to_string(),
+                });
             }
         }
         if !self.sync_options.force_use_get_next_block && !self.is_synced()? {
+            self.record_event(EngineEvent::SyncStarted {
+                historical: false,
+                from_mc_seqno: self.load_last_applied_mc_block_id()?.seq_no,
+            });
             sync(self).await?;
+            self.record_event(EngineEvent::SyncFinished {
+                historical: false,
+                last_mc_block_id: self.load_last_applied_mc_block_id()?.to_string(),
+            });
         }
         tracing::info!("node synced");
 
@@ -221,6 +338,7 @@ impl Engine {
         self.prepare_blocks_gc().await?;
         self.start_walking_blocks()?;
         self.start_states_gc();
+        self.start_subscriber_deliveries_gc();
 
         // Engine started
         Ok(())
@@ -234,6 +352,46 @@ impl Engine {
         self.db.trigger_compaction().await;
     }
 
+    /// Loads states of the latest applied masterchain block and the shard blocks
+    /// referenced by the shards client into the cache
+    async fn prewarm_shard_states_cache(&self) -> Result<()> {
+        if !self.shard_states_cache.prewarm() {
+            return Ok(());
+        }
+
+        let block_handle_storage = self.storage.block_handle_storage();
+        let block_storage = self.storage.block_storage();
+
+        let shards_client_mc_block_id = self.load_shards_client_mc_block_id()?;
+
+        let mut block_ids = vec![self.load_last_applied_mc_block_id()?];
+        if let Some(handle) = block_handle_storage.load_handle(&shards_client_mc_block_id)? {
+            if handle.meta().has_data() {
+                let block = block_storage.load_block_data(&handle).await?;
+                block_ids.extend(block.shard_blocks()?.into_values());
+            }
+        }
+        block_ids.push(shards_client_mc_block_id);
+
+        let mut count = 0;
+        for block_id in block_ids {
+            if self.shard_states_cache.get(&block_id).is_some() {
+                continue;
+            }
+
+            match block_handle_storage.load_handle(&block_id)? {
+                Some(handle) if handle.meta().has_state() => {
+                    self.load_state(&block_id).await?;
+                    count += 1;
+                }
+                _ => continue,
+            }
+        }
+
+        tracing::info!(count, "prewarmed shard states cache");
+        Ok(())
+    }
+
     async fn prepare_blocks_gc(self: &Arc<Self>) -> Result<()> {
         let blocks_gc_state = match &self.blocks_gc_state {
             Some(state) => state,
@@ -241,16 +399,76 @@ impl Engine {
         };
 
         blocks_gc_state.enabled.store(true, Ordering::Release);
+        self.run_blocks_gc().await
+    }
+
+    /// Removes blocks which are older than the last key block
+    async fn run_blocks_gc(&self) -> Result<()> {
+        let blocks_gc_state = match &self.blocks_gc_state {
+            Some(state) => state,
+            None => return Ok(()),
+        };
 
         let handle = self.storage.block_handle_storage().find_last_key_block()?;
-        self.storage
+        let result = self
+            .storage
             .block_storage()
             .remove_outdated_blocks(
                 handle.id(),
                 blocks_gc_state.max_blocks_per_batch,
                 blocks_gc_state.ty,
             )
-            .await
+            .await;
+
+        self.record_event(EngineEvent::BlocksGc {
+            success: result.is_ok(),
+        });
+        self.on_gc_finished().await;
+        result
+    }
+
+    fn start_archives_repack(
+        self: &Arc<Self>,
+        interval: Duration,
+        lower_bound: Option<Arc<ArchivesLowerBound>>,
+    ) {
+        let engine = Arc::downgrade(self);
+
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+
+                let engine = match engine.upgrade() {
+                    Some(engine) => engine,
+                    None => return,
+                };
+
+                // Repack only old archives which are not filled anymore
+                let mut until_id = match engine.load_shards_client_mc_block_id() {
+                    Ok(block_id) => block_id.seq_no.saturating_sub(2 * ARCHIVE_PACKAGE_SIZE),
+                    Err(e) => {
+                        tracing::error!("failed to load last shards client block: {e:?}");
+                        continue;
+                    }
+                };
+
+                // Merging a not yet uploaded archive into the uploaded one
+                // would hide its blocks from the uploader
+                if let Some(lower_bound) = &lower_bound {
+                    until_id =
+                        std::cmp::min(until_id, lower_bound.archive_id.load(Ordering::Acquire));
+                }
+
+                if let Err(e) = engine
+                    .storage
+                    .block_storage()
+                    .repack_archives(until_id)
+                    .await
+                {
+                    tracing::error!("failed to repack archives: {e:?}");
+                }
+            }
+        });
     }
 
     async fn start_archives_gc(self: &Arc<Self>) -> Result<()> {
@@ -259,16 +477,9 @@ impl Engine {
             None => return Ok(()),
         };
 
-        struct LowerBound {
-            archive_id: AtomicU32,
-            changed: Notify,
-        }
+        let mut lower_bound = None::<Arc<ArchivesLowerBound>>;
 
-        #[allow(unused_mut)]
-        let mut lower_bound = None::<Arc<LowerBound>>;
-
-        #[cfg(feature = "archive-uploader")]
-        if let Some(options) = options.uploader_options.clone() {
+        if let Some(uploader) = self.archive_uploader.clone() {
             async fn get_latest_mc_block_seq_no(engine: &Engine) -> Result<u32> {
                 let block_handle_storage = engine.storage.block_handle_storage();
                 let block_storage = engine.storage.block_storage();
@@ -282,16 +493,31 @@ impl Engine {
                 Ok(info.min_ref_mc_seqno())
             }
 
-            let interval = Duration::from_secs(options.archives_search_interval_sec);
-            let uploader = archive_uploader::ArchiveUploader::new(options)
-                .await
-                .context("Failed to create archive uploader")?;
+            /// Waits until the archive is either uploaded by another instance
+            /// or this instance is allowed to upload it.
+            ///
+            /// Returns `true` if the archive must be uploaded by this instance
+            async fn acquire_upload_lease(uploader: &dyn ArchiveUploader, archive_id: u32) -> bool {
+                loop {
+                    match uploader.acquire_upload_lease(archive_id).await {
+                        Ok(ArchiveUploadLease::Acquired) => return true,
+                        Ok(ArchiveUploadLease::Uploaded) => return false,
+                        Ok(ArchiveUploadLease::Busy) => {}
+                        Err(e) => {
+                            tracing::error!(archive_id, "failed to acquire upload lease: {e:?}");
+                        }
+                    }
+                    tokio::time::sleep(Duration::from_secs(10)).await;
+                }
+            }
+
+            let interval = uploader.archives_search_interval();
 
             let mut last_uploaded_archive =
                 self.storage.node_state().load_last_uploaded_archive()?;
 
             let lower_bound = lower_bound
-                .insert(Arc::new(LowerBound {
+                .insert(Arc::new(ArchivesLowerBound {
                     archive_id: AtomicU32::new(last_uploaded_archive.unwrap_or_default()),
                     changed: Notify::new(),
                 }))
@@ -340,19 +566,26 @@ impl Engine {
                         let data_len = archive_data.len();
 
                         let now = std::time::Instant::now();
-                        uploader.upload(archive_id, archive_data).await;
+                        let upload = acquire_upload_lease(uploader.as_ref(), archive_id).await;
+                        if upload {
+                            uploader.upload(archive_id, archive_data).await;
+                        }
 
                         if let Err(e) = node_state.store_last_uploaded_archive(archive_id) {
                             tracing::error!("failed to store last uploaded archive: {e:?}");
                         }
                         last_uploaded_archive = Some(archive_id);
 
-                        tracing::info!(
-                            archive_id,
-                            data_len,
-                            duration = now.elapsed().as_secs_f64(),
-                            "uploaded archive",
-                        );
+                        if upload {
+                            tracing::info!(
+                                archive_id,
+                                data_len,
+                                duration = now.elapsed().as_secs_f64(),
+                                "uploaded archive",
+                            );
+                        } else {
+                            tracing::info!(archive_id, "archive was uploaded by another instance");
+                        }
                     }
 
                     tokio::time::sleep(interval).await;
@@ -360,6 +593,10 @@ impl Engine {
             });
         }
 
+        if let Some(interval_sec) = options.repack_interval_sec {
+            self.start_archives_repack(Duration::from_secs(interval_sec), lower_bound.clone());
+        }
+
         match options.gc_interval {
             ArchivesGcInterval::Manual => Ok(()),
             ArchivesGcInterval::PersistentStates { offset_sec } => {
@@ -383,8 +620,11 @@ impl Engine {
                         };
 
                         if let Some(interval) = untile_time.checked_sub(now() as u64) {
+                            // GC can be triggered earlier by the disk watchdog
+                            let trigger = engine.archives_gc_trigger.clone();
                             tokio::select!(
                                 _ = tokio::time::sleep(Duration::from_secs(interval)) => {},
+                                _ = trigger.notified() => tracing::info!("archives GC triggered"),
                                 _ = &mut new_state_found => continue,
                             );
                         }
@@ -407,14 +647,19 @@ impl Engine {
                             }
                         }
 
-                        if let Err(e) = engine
+                        let result = engine
                             .storage
                             .block_storage()
                             .remove_outdated_archives(until_id)
-                            .await
-                        {
+                            .await;
+                        if let Err(e) = &result {
                             tracing::error!("failed to remove outdated archives: {e:?}");
                         }
+                        engine.record_event(EngineEvent::ArchivesGc {
+                            until_id,
+                            success: result.is_ok(),
+                        });
+                        engine.on_gc_finished().await;
 
                         new_state_found.await;
                     }
@@ -479,7 +724,19 @@ impl Engine {
                 gc_at += options.interval_sec;
                 // Check if there is some time left before the GC
                 if let Some(interval) = gc_at.checked_sub(broxus_util::now_sec_u64()) {
-                    tokio::time::sleep(Duration::from_secs(interval)).await;
+                    let engine = match engine.upgrade() {
+                        Some(engine) => engine,
+                        None => return,
+                    };
+
+                    // GC can be triggered earlier by the disk watchdog
+                    let trigger = engine.states_gc_trigger.clone();
+                    drop(engine);
+
+                    tokio::select! {
+                        _ = tokio::time::sleep(Duration::from_secs(interval)) => {},
+                        _ = trigger.notified() => tracing::info!("states GC triggered"),
+                    }
                 }
 
                 let engine = match engine.upgrade() {
@@ -495,8 +752,10 @@ impl Engine {
                     }
                 };
 
-                for subscriber in &engine.subscribers {
-                    subscriber.on_before_states_gc(&block_id).await;
+                // NOTE: states are not removed until all subscribers are ready
+                if let Err(e) = engine.notify_subscribers_before_states_gc(&block_id).await {
+                    tracing::error!("states GC postponed: {e:?}");
+                    continue;
                 }
 
                 let shard_state_storage = engine.storage.shard_state_storage();
@@ -513,9 +772,107 @@ impl Engine {
                         None
                     }
                 };
+                engine.record_event(EngineEvent::StatesGc {
+                    shards_client_mc_seqno: block_id.seq_no,
+                    success: top_blocks.is_some(),
+                });
+                engine.on_gc_finished().await;
+
+                if let Err(e) = engine
+                    .notify_subscribers_after_states_gc(&block_id, &top_blocks)
+                    .await
+                {
+                    tracing::error!("subscriber failed after states GC: {e:?}");
+                }
+            }
+        });
+    }
+
+    async fn notify_subscribers_before_states_gc(
+        &self,
+        block_id: &ton_block::BlockIdExt,
+    ) -> Result<()> {
+        for subscriber in &self.subscribers {
+            self.run_subscriber_callback(
+                subscriber,
+                block_id,
+                "on_before_states_gc",
+                || async move {
+                    subscriber.on_before_states_gc(block_id).await;
+                    Ok::<_, anyhow::Error>(())
+                },
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    async fn notify_subscribers_after_states_gc(
+        &self,
+        block_id: &ton_block::BlockIdExt,
+        top_blocks: &Option<TopBlocks>,
+    ) -> Result<()> {
+        for subscriber in &self.subscribers {
+            self.run_subscriber_callback(
+                subscriber,
+                block_id,
+                "on_after_states_gc",
+                || async move {
+                    subscriber.on_after_states_gc(block_id, top_blocks).await;
+                    Ok::<_, anyhow::Error>(())
+                },
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    fn start_subscriber_deliveries_gc(self: &Arc<Self>) {
+        if !self.subscribers.iter().any(|s| s.durable_id().is_some()) {
+            return;
+        }
+
+        let options = self.subscriber_deliveries_options;
+        let interval = Duration::from_secs(options.prune_interval_sec);
+        let engine = Arc::downgrade(self);
+
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+
+                let engine = match engine.upgrade() {
+                    Some(engine) => engine,
+                    None => return,
+                };
+
+                let mc_seq_no = match engine.load_shards_client_mc_block_id() {
+                    Ok(block_id) => block_id.seq_no,
+                    Err(e) => {
+                        tracing::error!("failed to load last shards client block: {e:?}");
+                        continue;
+                    }
+                };
+                let until_mc_seqno = mc_seq_no.saturating_sub(options.retention_mc_blocks);
+
+                let storage = engine.storage.clone();
+                drop(engine);
 
-                for subscriber in &engine.subscribers {
-                    subscriber.on_after_states_gc(&block_id, &top_blocks).await;
+                match tokio::task::spawn_blocking(move || {
+                    storage
+                        .subscriber_deliveries()
+                        .remove_outdated(until_mc_seqno)
+                })
+                .await
+                {
+                    Ok(Ok(())) => {
+                        tracing::info!(until_mc_seqno, "removed outdated subscriber deliveries")
+                    }
+                    Ok(Err(e)) => {
+                        tracing::error!("failed to remove outdated subscriber deliveries: {e:?}")
+                    }
+                    Err(e) => {
+                        tracing::error!("subscriber deliveries GC panicked: {e:?}")
+                    }
                 }
             }
         });
@@ -546,10 +903,12 @@ impl Engine {
     pub fn internal_metrics(&self) -> InternalEngineMetrics {
         InternalEngineMetrics {
             shard_states_cache_len: self.shard_states_cache.len(),
+            sync_memory_used: self.sync_memory.used(),
             shard_states_operations_len: self.shard_states_operations.len(),
             block_applying_operations_len: self.block_applying_operations.len(),
             next_block_applying_operations_len: self.next_block_applying_operations.len(),
             download_block_operations_len: self.download_block_operations.len(),
+            applied_blocks_operations_len: self.applied_blocks_operations.len(),
             cells_cache_stats: self.storage.cells_cache_stats(),
         }
     }
@@ -598,7 +957,7 @@ impl Engine {
 
         tokio::spawn(async move {
             loop {
-                let block = match client.wait_broadcast().await {
+                let (block, data) = match client.wait_broadcast().await {
                     Ok(block) => block,
                     Err(_) => continue,
                 };
@@ -610,15 +969,28 @@ impl Engine {
                     .fetch_add(1, Ordering::Relaxed);
 
                 let engine = engine.clone();
+                let client = client.clone();
                 tokio::spawn(async move {
-                    if let Err(e) = process_block_broadcast(&engine, block).await {
-                        engine
-                            .metrics
-                            .block_broadcasts
-                            .invalid
-                            .fetch_add(1, Ordering::Relaxed);
-
-                        tracing::error!("failed to process block broadcast: {e:?}");
+                    match process_block_broadcast(&engine, block).await {
+                        // Share only new blocks with valid proofs (not necessarily applied)
+                        Ok(true) if engine.sync_options.rebroadcast_blocks => {
+                            client.broadcast_block(data);
+                            engine
+                                .metrics
+                                .block_broadcasts
+                                .rebroadcasted
+                                .fetch_add(1, Ordering::Relaxed);
+                        }
+                        Ok(_) => {}
+                        Err(e) => {
+                            engine
+                                .metrics
+                                .block_broadcasts
+                                .invalid
+                                .fetch_add(1, Ordering::Relaxed);
+
+                            tracing::error!("failed to process block broadcast: {e:?}");
+                        }
                     }
                 });
             }
@@ -767,6 +1139,22 @@ impl Engine {
             .await
     }
 
+    /// Downloads an archive with the specified masterchain block from
+    /// the archives storage shared with other instances
+    async fn download_archive_from_storage(&self, mc_block_seq_no: u32) -> Option<Vec<u8>> {
+        let uploader = self.archive_uploader.as_ref()?;
+        match uploader.download(mc_block_seq_no).await {
+            Ok(data) => data,
+            Err(e) => {
+                tracing::warn!(
+                    mc_block_seq_no,
+                    "failed to download archive from the storage: {e:?}"
+                );
+                None
+            }
+        }
+    }
+
     pub async fn load_last_key_block(&self) -> Result<BlockStuff> {
         let handle = self
             .storage
@@ -847,6 +1235,142 @@ impl Engine {
         }
     }
 
+    /// Returns the id of the applied block which follows the specified one.
+    ///
+    /// NOTE: after the shard split this is the left block, see [`Engine::get_next_block_id_after_split`]
+    pub fn get_next_block_id(
+        &self,
+        block_id: &ton_block::BlockIdExt,
+    ) -> Result<Option<ton_block::BlockIdExt>> {
+        self.load_next_block_id(block_id, BlockConnection::Next1)
+    }
+
+    /// Returns the id of the right block after the shard split
+    pub fn get_next_block_id_after_split(
+        &self,
+        block_id: &ton_block::BlockIdExt,
+    ) -> Result<Option<ton_block::BlockIdExt>> {
+        self.load_next_block_id(block_id, BlockConnection::Next2)
+    }
+
+    fn load_next_block_id(
+        &self,
+        block_id: &ton_block::BlockIdExt,
+        direction: BlockConnection,
+    ) -> Result<Option<ton_block::BlockIdExt>> {
+        let handle = match self.storage.block_handle_storage().load_handle(block_id)? {
+            Some(handle) => handle,
+            None => return Ok(None),
+        };
+
+        let has_next = match direction {
+            BlockConnection::Next1 => handle.meta().has_next1(),
+            BlockConnection::Next2 => handle.meta().has_next2(),
+            _ => false,
+        };
+        if !has_next {
+            return Ok(None);
+        }
+
+        let next_id = self
+            .storage
+            .block_connection_storage()
+            .load_connection(block_id, direction)?;
+
+        // Connections are stored before the next block is marked as applied
+        let is_applied = matches!(
+            self.storage.block_handle_storage().load_handle(&next_id)?,
+            Some(handle) if handle.meta().is_applied()
+        );
+        Ok(is_applied.then(|| next_id))
+    }
+
+    /// Waits until the block with the specified seqno is applied in the specified shard.
+    ///
+    /// Returns an error if the shard is not indexed or detached, if the block was applied
+    /// but is already removed by GC, if the shard was split or merged before reaching
+    /// the seqno, or if the `timeout` has elapsed
+    pub async fn wait_for_block(
+        &self,
+        shard: ton_block::ShardIdent,
+        seqno: u32,
+        timeout: Option<Duration>,
+    ) -> Result<ton_block::BlockIdExt> {
+        if !self.sync_options.is_shard_indexed(&shard) {
+            return Err(EngineError::ShardNotIndexed.into());
+        }
+
+        let block_handle_storage = self.storage.block_handle_storage();
+
+        let key = AppliedBlockKey((shard, seqno));
+        let wait = async {
+            loop {
+                // Blocks of the detached shards are not applied until it is resumed
+                if self.is_shard_detached(&shard) {
+                    return Err(EngineError::ShardDetached.into());
+                }
+
+                if let Some(block_id) = block_handle_storage.find_applied_block_id(&shard, seqno)? {
+                    return Ok(block_id);
+                }
+
+                // Applied blocks which are not found were already removed
+                if self.is_block_passed(&shard, seqno)? {
+                    return match block_handle_storage.find_applied_block_id(&shard, seqno)? {
+                        Some(block_id) => Ok(block_id),
+                        None => Err(EngineError::BlockNotFound.into()),
+                    };
+                }
+
+                if let Some(block_id) = self
+                    .applied_blocks_operations
+                    .wait(&key, None, || {
+                        Ok(block_handle_storage
+                            .find_applied_block_id(&shard, seqno)?
+                            .is_some())
+                    })
+                    .await?
+                {
+                    return Ok(block_id);
+                }
+            }
+        };
+
+        match timeout {
+            Some(timeout) => tokio::time::timeout(timeout, wait)
+                .await
+                .map_err(|_| EngineError::WaitForBlockTimeout)?,
+            None => wait.await,
+        }
+    }
+
+    /// Whether the shard client has already processed the block with the specified seqno.
+    ///
+    /// NOTE: top blocks are matched by intersection, because the shard could be split
+    /// or merged. Seqno continues after the split or merge, so the block with the
+    /// same seqno will never appear in the old shard
+    fn is_block_passed(&self, shard: &ton_block::ShardIdent, seqno: u32) -> Result<bool> {
+        if shard.is_masterchain() {
+            return Ok(self.load_last_applied_mc_block_id()?.seq_no >= seqno);
+        }
+
+        let mc_block_id = self.load_shards_client_mc_block_id()?;
+        Ok(self
+            .storage
+            .block_index()
+            .load_shard_blocks(mc_block_id.seq_no)?
+            .unwrap_or_default()
+            .iter()
+            .any(|block_id| {
+                block_id.shard_id.workchain_id() == shard.workchain_id()
+                    && shard_prefixes_intersect(
+                        block_id.shard_id.shard_prefix_with_tag(),
+                        shard.shard_prefix_with_tag(),
+                    )
+                    && block_id.seq_no >= seqno
+            }))
+    }
+
     pub async fn wait_state(
         self: &Arc<Self>,
         block_id: &ton_block::BlockIdExt,
@@ -954,25 +1478,69 @@ impl Engine {
     }
 
     async fn set_applied(&self, handle: &Arc<BlockHandle>, mc_seq_no: u32) -> Result<bool> {
+        self.set_applied_batched(handle, mc_seq_no, Default::default())
+            .await
+    }
+
+    /// Marks block as applied and writes it together with the `batch`,
+    /// so that the block is either fully applied or not applied at all
+    async fn set_applied_batched(
+        &self,
+        handle: &Arc<BlockHandle>,
+        mc_seq_no: u32,
+        mut batch: rocksdb::WriteBatch,
+    ) -> Result<bool> {
         if handle.meta().is_applied() {
+            if !batch.is_empty() {
+                self.db.raw().write(batch)?;
+            }
             return Ok(false);
         }
 
         let block_handle_storage = self.storage.block_handle_storage();
         let block_storage = self.storage.block_storage();
 
-        block_handle_storage.assign_mc_ref_seq_no(handle, mc_seq_no)?;
+        // NOTE: in-memory flags are rolled back if the batch was not written,
+        // so that the block will be applied again
+        let ref_seqno_set = handle.set_masterchain_ref_seqno(mc_seq_no)?;
+        let rollback = |applied: bool| {
+            if applied {
+                handle.meta().clear_is_applied();
+            }
+            if ref_seqno_set {
+                handle.meta().clear_masterchain_ref_seqno();
+            }
+        };
 
         if self.archive_options.is_some() {
-            block_storage.move_into_archive(handle).await?;
+            if let Err(e) = block_storage.move_into_archive(handle).await {
+                rollback(false);
+                return Err(e);
+            }
         }
 
-        let applied = block_handle_storage.store_block_applied(handle)?;
+        let applied = handle.meta().set_is_applied();
+        block_handle_storage.store_handle_batched(&mut batch, handle);
+        if let Err(e) = self.db.raw().write(batch) {
+            rollback(applied);
+            return Err(e.into());
+        }
 
         if handle.id().shard_id.is_masterchain() {
             self.on_masterchain_block(handle).await?;
         }
 
+        if applied {
+            let block_id = handle.id();
+            self.applied_blocks_operations
+                .do_or_wait(
+                    &AppliedBlockKey((block_id.shard_id, block_id.seq_no)),
+                    None,
+                    futures_util::future::ok(block_id.clone()),
+                )
+                .await?;
+        }
+
         Ok(applied)
     }
 
@@ -1011,13 +1579,245 @@ impl Engine {
             block,
         };
 
+        if !self.is_leader() {
+            return Ok(());
+        }
+
         for subscriber in &self.subscribers {
-            subscriber.process_blocks_edge(ctx).await?;
+            self.run_subscriber_callback(subscriber, ctx.id(), "process_blocks_edge", || {
+                subscriber.process_blocks_edge(ctx)
+            })
+            .await?;
         }
 
+        // All blocks up to this masterchain block are delivered
+        self.commit_delivered_mc_block(block.id().seq_no).await
+    }
+
+    /// Captures a consistent read-only view of the storage.
+    ///
+    /// Can be held for the duration of a long read session. Read APIs with the `_at`
+    /// suffix (e.g. [`Engine::get_account_states_at`]) resolve everything through it
+    pub fn snapshot(&self) -> StorageSnapshot<'_> {
+        StorageSnapshot::new(&self.db, &self.storage)
+    }
+
+    /// Loads typed flags of the block handle
+    pub fn load_block_handle_flags(
+        &self,
+        block_id: &ton_block::BlockIdExt,
+    ) -> Result<Option<BlockHandleFlags>> {
+        Ok(self
+            .storage
+            .block_handle_storage()
+            .load_handle(block_id)?
+            .map(|handle| handle.meta().flags()))
+    }
+
+    /// Loads typed flags of all stored blocks in the shard within the seqno range
+    pub fn load_block_handle_flags_range(
+        &self,
+        shard: &ton_block::ShardIdent,
+        seqno_range: std::ops::RangeInclusive<u32>,
+    ) -> Result<Vec<BlockHandleFlagsEntry>> {
+        self.storage
+            .block_handle_storage()
+            .load_handle_flags_range(shard, seqno_range)
+    }
+
+    /// Loads all known changes of the global config param.
+    ///
+    /// NOTE: changes are only tracked for the key blocks applied by this node
+    pub fn get_config_history(&self, param_id: u32) -> Result<Vec<ConfigParamChange>> {
+        self.storage.config_history().load_history(param_id)
+    }
+
+    /// Finds validator set which was active at the specified time or block.
+    ///
+    /// NOTE: transitions are only tracked for the key blocks applied by this node
+    pub fn get_validator_set(&self, query: ValidatorSetQuery) -> Result<Option<ValidatorSetEntry>> {
+        self.storage.validator_sets().load_validator_set(query)
+    }
+
+    /// Loads library code published in the masterchain
+    pub fn load_library(&self, hash: &ton_types::UInt256) -> Result<Option<ton_types::Cell>> {
+        self.storage.libraries().load_library(hash)
+    }
+
+    /// Finds the last masterchain block generated at or before the specified time.
+    ///
+    /// Returns `None` if the time is before the oldest retained masterchain block
+    /// (removed by the blocks GC or applied before the block index was introduced)
+    pub fn resolve_block_at_time(&self, utime: u32) -> Result<Option<ton_block::BlockIdExt>> {
+        self.storage.block_index().find_mc_block_at_time(utime)
+    }
+
+    /// Returns top shard blocks of the applied masterchain block.
+    /// Returns `None` if the block was removed by the blocks GC
+    pub fn get_shard_blocks(&self, mc_seq_no: u32) -> Result<Option<Vec<ton_block::BlockIdExt>>> {
+        self.storage.block_index().load_shard_blocks(mc_seq_no)
+    }
+
+    /// Loads at most `limit` addresses of the accounts with the specified code hash,
+    /// starting after the `after` address (exclusive)
+    ///
+    /// NOTE: requires `index_code_hashes` sync option. Use
+    /// [`Engine::find_accounts_by_code_hash_at`] to paginate over the same index version
+    pub fn find_accounts_by_code_hash(
+        &self,
+        code_hash: &ton_types::UInt256,
+        after: Option<&ton_block::MsgAddressInt>,
+        limit: usize,
+    ) -> Result<Vec<ton_block::MsgAddressInt>> {
+        if !self.sync_options.index_code_hashes {
+            return Err(EngineError::CodeHashIndexDisabled.into());
+        }
+        self.storage
+            .code_hashes()
+            .find_accounts(code_hash, after, limit)
+    }
+
+    /// Returns the masterchain seqno since which the code hash index contains all accounts
+    pub fn code_hash_index_valid_since(&self) -> Result<Option<u32>> {
+        if !self.sync_options.index_code_hashes {
+            return Err(EngineError::CodeHashIndexDisabled.into());
+        }
+        self.storage.code_hashes().load_valid_since()
+    }
+
+    /// Removes the code hash index when it is disabled, or fills it from the
+    /// stored states when it was enabled after the blocks were applied
+    async fn prepare_code_hash_index(&self) -> Result<()> {
+        let valid_since = self.storage.code_hashes().load_valid_since()?;
+
+        if !self.sync_options.index_code_hashes {
+            // NOTE: the index is not updated while disabled, so it can't be reused later
+            if valid_since.is_some() {
+                tracing::info!("removing disabled code hash index");
+                self.storage.code_hashes().clear()?;
+            }
+            return Ok(());
+        } else if valid_since.is_some() {
+            return Ok(());
+        }
+
+        // Top shard blocks of the shards client block are applied and have states.
+        // Masterchain accounts are taken from the last applied block
+        let shards_client_mc_block_id =
+            self.storage.node_state().load_shards_client_mc_block_id()?;
+        let mut block_ids = vec![self.load_last_applied_mc_block_id()?];
+        if !self.sync_options.masterchain_only {
+            let shards_client_mc_state = self.load_state(&shards_client_mc_block_id).await?;
+            block_ids.extend(
+                collect_shard_blocks(&shards_client_mc_state)?
+                    .into_iter()
+                    .skip(1)
+                    .filter(|block_id| self.is_shard_tracked(&block_id.shard_id)),
+            );
+        }
+
+        tracing::info!(%shards_client_mc_block_id, "backfilling code hash index");
+        self.storage.code_hashes().clear()?;
+        for block_id in &block_ids {
+            self.backfill_code_hash_index(block_id).await?;
+        }
+        self.storage
+            .code_hashes()
+            .store_valid_since(shards_client_mc_block_id.seq_no)?;
+        tracing::info!("backfilled code hash index");
+
         Ok(())
     }
 
+    /// Adds all accounts of the stored state to the code hash index (if enabled)
+    pub(crate) async fn backfill_code_hash_index(
+        &self,
+        block_id: &ton_block::BlockIdExt,
+    ) -> Result<()> {
+        if !self.sync_options.index_code_hashes {
+            return Ok(());
+        }
+
+        let state = self.load_state(block_id).await?;
+        let storage = self.storage.clone();
+        tokio::task::spawn_blocking(move || storage.code_hashes().backfill(&state)).await?
+    }
+
+    /// Same as [`Engine::find_accounts_by_code_hash`], but reads the index through the snapshot
+    pub fn find_accounts_by_code_hash_at(
+        &self,
+        snapshot: &StorageSnapshot<'_>,
+        code_hash: &ton_types::UInt256,
+        after: Option<&ton_block::MsgAddressInt>,
+        limit: usize,
+    ) -> Result<Vec<ton_block::MsgAddressInt>> {
+        if !self.sync_options.index_code_hashes {
+            return Err(EngineError::CodeHashIndexDisabled.into());
+        }
+        snapshot.find_accounts_by_code_hash(code_hash, after, limit)
+    }
+
+    /// Loads at most `limit` journal events starting from the `from_id` (inclusive)
+    pub fn load_engine_events(&self, from_id: u64, limit: usize) -> Result<Vec<EngineEventRecord>> {
+        self.storage.event_journal().load_events(from_id, limit)
+    }
+
+    fn record_event(&self, event: EngineEvent) {
+        if let Err(e) = self.storage.event_journal().append(event) {
+            tracing::error!("failed to record engine event: {e:?}");
+        }
+    }
+
+    /// Exports the retention manifest and prunes the journal after some data was removed by GC
+    async fn on_gc_finished(&self) {
+        if let Err(e) = self.export_retention_manifest().await {
+            tracing::error!("failed to export retention manifest: {e:?}");
+        }
+        if let Err(e) = self.prune_engine_events().await {
+            tracing::error!("failed to prune engine events: {e:?}");
+        }
+    }
+
+    async fn prune_engine_events(&self) -> Result<()> {
+        let options = self.event_journal_options;
+        let min_timestamp = options
+            .max_age_sec
+            .map(|max_age| broxus_util::now_sec_u64().saturating_sub(max_age) as u32);
+
+        let storage = self.storage.clone();
+        tokio::task::spawn_blocking(move || {
+            storage
+                .event_journal()
+                .prune(min_timestamp, options.max_count)
+        })
+        .await?
+    }
+
+    /// Describes which blocks, states and archives are currently retained locally
+    pub fn retention_manifest(&self) -> Result<RetentionManifest> {
+        RetentionManifest::build(&self.db, &self.storage)
+    }
+
+    /// Writes the retention manifest as JSON into `{file_db_path}/retention_manifest.json`.
+    ///
+    /// NOTE: the manifest is also exported after each GC
+    pub async fn export_retention_manifest(&self) -> Result<()> {
+        self.db.check_writes()?;
+
+        let db = self.db.clone();
+        let storage = self.storage.clone();
+        tokio::task::spawn_blocking(move || {
+            let manifest = RetentionManifest::build(&db, &storage)?;
+
+            // NOTE: readers never see a partially written manifest
+            let path = storage.downloads_dir().join(RETENTION_MANIFEST_FILE);
+            std::fs::write(&path, serde_json::to_vec_pretty(&manifest)?)?;
+            storage.move_into_file_db(&path, RETENTION_MANIFEST_FILE)?;
+            Ok(())
+        })
+        .await?
+    }
+
     pub fn load_last_applied_mc_block_id(&self) -> Result<ton_block::BlockIdExt> {
         self.storage.node_state().load_last_mc_block_id()
     }
@@ -1030,6 +1830,29 @@ impl Engine {
         Ok(())
     }
 
+    /// Adds last applied mc block id to the batch.
+    ///
+    /// NOTE: [`Engine::on_last_applied_mc_block_id_written`] must be called
+    /// after the batch is written
+    fn store_last_applied_mc_block_id_batched(
+        &self,
+        batch: &mut rocksdb::WriteBatch,
+        block_id: &ton_block::BlockIdExt,
+    ) {
+        self.storage
+            .node_state()
+            .store_last_mc_block_id_batched(batch, block_id);
+    }
+
+    fn on_last_applied_mc_block_id_written(&self, block_id: &ton_block::BlockIdExt) {
+        self.storage
+            .node_state()
+            .on_last_mc_block_id_written(block_id);
+        self.metrics
+            .last_mc_block_seqno
+            .store(block_id.seq_no, Ordering::Release);
+    }
+
     pub fn load_shards_client_mc_block_id(&self) -> Result<ton_block::BlockIdExt> {
         self.storage.node_state().load_shards_client_mc_block_id()
     }
@@ -1169,6 +1992,12 @@ impl Engine {
         pre_apply: bool,
         depth: u32,
     ) -> Result<()> {
+        self.db.check_writes()?;
+
+        if handle.id().is_masterchain() && !pre_apply && !handle.meta().is_applied() {
+            self.wait_for_leader_offset(handle.id().seq_no).await?;
+        }
+
         while !(pre_apply && handle.meta().has_data() || handle.meta().is_applied()) {
             self.block_applying_operations
                 .do_or_wait(
@@ -1208,8 +2037,10 @@ impl Engine {
             shard_state: Some(shard_state),
             block_data: None,
             block_proof_data: None,
+            proof_payload: None,
         };
 
+        let started_at = std::time::Instant::now();
         if handle.id().shard().is_masterchain() {
             self.metrics
                 .mc_time_diff
@@ -1217,20 +2048,34 @@ impl Engine {
             self.metrics
                 .last_mc_utime
                 .store(meta.gen_utime(), Ordering::Release);
-
-            for subscriber in &self.subscribers {
-                subscriber.process_block(ctx).await?;
-            }
         } else {
             self.metrics
                 .shard_client_time_diff
                 .store(time_diff, Ordering::Release);
+        }
 
-            for subscriber in &self.subscribers {
-                subscriber.process_block(ctx).await?;
-            }
+        // Standby instance keeps syncing without side effects
+        if !self.is_leader() {
+            return Ok(());
         }
 
+        let proof_payload = self.load_block_proof_payload(handle, block, None).await?;
+        for subscriber in &self.subscribers {
+            let ctx = ProcessBlockContext {
+                proof_payload: proof_payload
+                    .as_ref()
+                    .filter(|_| subscriber.include_block_proofs()),
+                ..ctx
+            };
+            self.notify_subscriber_with_block(subscriber, ctx).await?;
+        }
+        self.on_block_delivered(handle)?;
+
+        self.metrics
+            .block_apply_stages
+            .subscribers
+            .observe_since(started_at);
+
         Ok(())
     }
 
@@ -1251,29 +2096,261 @@ impl Engine {
             shard_state: None,
             block_data: Some(block_data),
             block_proof_data: Some(block_proof_data),
+            proof_payload: None,
         };
 
-        if handle.id().shard().is_masterchain() {
-            for subscriber in &self.subscribers {
-                subscriber.process_block(ctx).await?;
-            }
-        } else {
-            for subscriber in &self.subscribers {
-                subscriber.process_block(ctx).await?;
+        // Standby instance keeps syncing without side effects
+        if !self.is_leader() {
+            return Ok(());
+        }
+
+        let started_at = std::time::Instant::now();
+        let proof_payload = self
+            .load_block_proof_payload(handle, block, Some(block_proof_data))
+            .await?;
+        for subscriber in &self.subscribers {
+            let ctx = ProcessBlockContext {
+                proof_payload: proof_payload
+                    .as_ref()
+                    .filter(|_| subscriber.include_block_proofs()),
+                ..ctx
+            };
+            self.notify_subscriber_with_block(subscriber, ctx).await?;
+        }
+        self.on_block_delivered(handle)?;
+
+        self.metrics
+            .block_apply_stages
+            .subscribers
+            .observe_since(started_at);
+
+        Ok(())
+    }
+
+    /// Remembers the last masterchain block which was delivered by this instance
+    fn on_block_delivered(&self, handle: &BlockHandle) -> Result<()> {
+        if !self.subscribers.is_empty() && handle.id().shard_id.is_masterchain() {
+            self.storage
+                .node_state()
+                .store_last_delivered_mc_seqno(handle.id().seq_no)?;
+        }
+        Ok(())
+    }
+
+    /// Loads the block proof once for all subscribers which requested it
+    async fn load_block_proof_payload(
+        &self,
+        handle: &Arc<BlockHandle>,
+        block: &BlockStuff,
+        block_proof_data: Option<&[u8]>,
+    ) -> Result<Option<BlockProofPayload>> {
+        if !self.subscribers.iter().any(|s| s.include_block_proofs()) {
+            return Ok(None);
+        }
+
+        let mut is_link = false;
+        let block_proof = match block_proof_data {
+            Some(data) => Some(BlockProofStuff::deserialize(
+                handle.id().clone(),
+                data,
+                !handle.id().is_masterchain(),
+            )?),
+            None if handle.has_proof_or_link(&mut is_link) => Some(
+                self.storage
+                    .block_storage()
+                    .load_block_proof(handle, is_link)
+                    .await?,
+            ),
+            None => None,
+        };
+
+        let prev_key_block_seqno = block.block().read_info()?.prev_key_block_seqno();
+        let prev_key_block_id = self
+            .storage
+            .block_handle_storage()
+            .load_key_block_handle(prev_key_block_seqno)
+            .ok()
+            .map(|handle| handle.id().clone());
+
+        Ok(Some(BlockProofPayload {
+            block_proof,
+            prev_key_block_id,
+        }))
+    }
+
+    /// Processes block by the subscriber according to the `subscriber_error_policy`
+    async fn notify_subscriber_with_block(
+        &self,
+        subscriber: &Arc<dyn Subscriber>,
+        ctx: ProcessBlockContext<'_>,
+    ) -> Result<()> {
+        let deliveries = self.storage.subscriber_deliveries();
+        let durable = match subscriber.durable_id() {
+            Some(durable_id) => Some((durable_id, delivery_mc_seqno(ctx.id(), ctx.block())?)),
+            None => None,
+        };
+        if let Some((durable_id, mc_seq_no)) = durable {
+            if deliveries.is_delivered(durable_id, mc_seq_no, ctx.id())? {
+                tracing::debug!(
+                    block_id = %ctx.id().display(),
+                    subscriber = durable_id,
+                    "block was already delivered"
+                );
+                return Ok(());
             }
         }
 
+        let processed = self
+            .run_subscriber_callback(subscriber, ctx.id(), "process_block", || {
+                subscriber.process_block(ctx)
+            })
+            .await?;
+
+        if let Some((durable_id, mc_seq_no)) = durable.filter(|_| processed) {
+            deliveries.mark_delivered(durable_id, mc_seq_no, ctx.id())?;
+        }
         Ok(())
     }
 
+    /// Runs the subscriber callback according to the `subscriber_error_policy`.
+    ///
+    /// Returns `false` if the callback failed and was skipped
+    async fn run_subscriber_callback<F, Fut>(
+        &self,
+        subscriber: &Arc<dyn Subscriber>,
+        block_id: &ton_block::BlockIdExt,
+        callback: &'static str,
+        mut f: F,
+    ) -> Result<bool>
+    where
+        F: FnMut() -> Fut,
+        Fut: std::future::Future<Output = Result<()>>,
+    {
+        use futures_util::FutureExt;
+
+        let mut attempt = 0;
+        loop {
+            let error = match std::panic::AssertUnwindSafe(f()).catch_unwind().await {
+                Ok(Ok(())) => return Ok(true),
+                Ok(Err(e)) => e,
+                Err(_) => EngineError::SubscriberPanicked.into(),
+            };
+
+            match self.sync_options.subscriber_error_policy {
+                SubscriberErrorPolicy::Halt => return Err(error),
+                SubscriberErrorPolicy::Retry {
+                    min_interval_ms,
+                    max_interval_ms,
+                    max_attempts,
+                } => {
+                    attempt += 1;
+                    if matches!(max_attempts, Some(max_attempts) if attempt >= max_attempts) {
+                        return Err(error);
+                    }
+
+                    let interval = min_interval_ms
+                        .saturating_mul(1u64 << std::cmp::min(attempt - 1, 32))
+                        .min(max_interval_ms);
+                    tracing::warn!(
+                        block_id = %block_id.display(),
+                        callback,
+                        attempt,
+                        "subscriber failed: {error:?}"
+                    );
+                    tokio::time::sleep(Duration::from_millis(interval)).await;
+                }
+                SubscriberErrorPolicy::Skip => {
+                    let subscriber_id = self.subscriber_id(subscriber);
+                    tracing::error!(
+                        block_id = %block_id.display(),
+                        callback,
+                        subscriber = %subscriber_id,
+                        "subscriber failed, skipping: {error:?}"
+                    );
+                    self.storage
+                        .node_state()
+                        .store_failed_subscriber_block(&subscriber_id, block_id)?;
+                    return Ok(false);
+                }
+            }
+        }
+    }
+
+    /// Durable id of the subscriber or `subscriber_{index}` for non-durable subscribers
+    fn subscriber_id(&self, subscriber: &Arc<dyn Subscriber>) -> String {
+        if let Some(durable_id) = subscriber.durable_id() {
+            return durable_id.to_owned();
+        }
+
+        let ptr = Arc::as_ptr(subscriber).cast::<()>();
+        let index = self
+            .subscribers
+            .iter()
+            .position(|item| std::ptr::eq(Arc::as_ptr(item).cast::<()>(), ptr))
+            .unwrap_or_default();
+        format!("subscriber_{index}")
+    }
+
+    /// Removes delivery records of the durable subscriber,
+    /// so that the replayed blocks are delivered to it again.
+    ///
+    /// NOTE: iterates over all records, must not be called from the async context
+    ///
+    /// Returns the number of removed records
+    pub fn forget_subscriber_deliveries(&self, durable_id: &str) -> Result<usize> {
+        self.storage
+            .subscriber_deliveries()
+            .forget_subscriber(durable_id)
+    }
+
+    /// Returns blocks which were skipped by subscribers with the `skip` error policy
+    pub fn load_failed_subscriber_blocks(&self) -> Result<Vec<FailedSubscriberBlock>> {
+        self.storage.node_state().load_failed_subscriber_blocks()
+    }
+
+    /// Removes the block from the list of blocks skipped by the subscriber
+    /// (e.g. after reprocessing)
+    pub fn remove_failed_subscriber_block(
+        &self,
+        subscriber_id: &str,
+        block_id: &ton_block::BlockIdExt,
+    ) -> Result<()> {
+        self.storage
+            .node_state()
+            .remove_failed_subscriber_block(subscriber_id, block_id)
+    }
+
     async fn notify_subscribers_with_full_state(&self, state: &ShardStateStuff) -> Result<()> {
+        if !self.is_leader() {
+            return Ok(());
+        }
+
         for subscriber in &self.subscribers {
-            subscriber.process_full_state(state).await?;
+            self.run_subscriber_callback(
+                subscriber,
+                state.block_id(),
+                "process_full_state",
+                || subscriber.process_full_state(state),
+            )
+            .await?;
         }
         Ok(())
     }
 
     async fn check_block_proof(&self, block_proof: &BlockProofStuff) -> Result<BriefBlockInfo> {
+        let started_at = std::time::Instant::now();
+        let res = self.check_block_proof_impl(block_proof).await;
+        self.metrics
+            .block_apply_stages
+            .proof_check
+            .observe_since(started_at);
+        res
+    }
+
+    async fn check_block_proof_impl(
+        &self,
+        block_proof: &BlockProofStuff,
+    ) -> Result<BriefBlockInfo> {
         let block_handle_storage = self.storage.block_handle_storage();
         let block_storage = self.storage.block_storage();
 
@@ -1319,6 +2396,9 @@ impl Engine {
                     block_id = %handle.id().display(),
                     "received hard fork key block, ignoring proof",
                 );
+                self.record_event(EngineEvent::HardForkDetected {
+                    block_id: handle.id().to_string(),
+                });
             }
         }
 
@@ -1359,6 +2439,7 @@ impl Engine {
             timeouts,
             client: &self.masterchain_client,
             storage: self.storage.as_ref(),
+            max_requests_per_peer: self.sync_options.max_block_requests_per_peer,
             downloader,
             explicit_neighbour: None,
             counters,
@@ -1385,6 +2466,22 @@ pub trait Subscriber: Send + Sync {
         let _unused_by_default = top_blocks;
     }
 
+    /// Stable subscriber id for the durable delivery.
+    ///
+    /// Blocks which were successfully processed by the durable subscriber are
+    /// not delivered to it again after the restart or replay
+    /// (see [`Engine::forget_subscriber_deliveries`]). Records are kept for
+    /// the last `subscriber_deliveries_options.retention_mc_blocks` masterchain blocks
+    fn durable_id(&self) -> Option<&str> {
+        None
+    }
+
+    /// Whether the block proof and the previous key block id should be
+    /// loaded in advance and passed to `process_block`
+    fn include_block_proofs(&self) -> bool {
+        false
+    }
+
     async fn process_block(&self, ctx: ProcessBlockContext<'_>) -> Result<()> {
         let _unused_by_default = ctx;
         Ok(())
@@ -1445,6 +2542,13 @@ pub struct ProcessBlockContext<'a> {
     shard_state: Option<&'a ShardStateStuff>,
     block_data: Option<&'a [u8]>,
     block_proof_data: Option<&'a [u8]>,
+    proof_payload: Option<&'a BlockProofPayload>,
+}
+
+/// Data for subscribers with [`Subscriber::include_block_proofs`]
+struct BlockProofPayload {
+    block_proof: Option<BlockProofStuff>,
+    prev_key_block_id: Option<ton_block::BlockIdExt>,
 }
 
 impl ProcessBlockContext<'_> {
@@ -1493,6 +2597,22 @@ impl ProcessBlockContext<'_> {
         self.shard_state.is_none()
     }
 
+    /// Preloaded block proof (or proof link for shard blocks).
+    ///
+    /// NOTE: only available for subscribers with [`Subscriber::include_block_proofs`]
+    #[inline(always)]
+    pub fn block_proof(&self) -> Option<&BlockProofStuff> {
+        self.proof_payload?.block_proof.as_ref()
+    }
+
+    /// Id of the key block which precedes this block.
+    ///
+    /// NOTE: only available for subscribers with [`Subscriber::include_block_proofs`]
+    #[inline(always)]
+    pub fn prev_key_block_id(&self) -> Option<&ton_block::BlockIdExt> {
+        self.proof_payload?.prev_key_block_id.as_ref()
+    }
+
     pub async fn load_block_data(&self) -> Result<Vec<u8>> {
         match self.block_data {
             Some(data) => Ok(data.to_vec()),
@@ -1504,6 +2624,10 @@ impl ProcessBlockContext<'_> {
     }
 
     pub async fn load_block_proof(&self) -> Result<BlockProofStuff> {
+        if let Some(block_proof) = self.block_proof() {
+            return Ok(block_proof.clone());
+        }
+
         match self.block_proof_data {
             Some(data) => {
                 BlockProofStuff::deserialize(self.handle.id().clone(), data, !self.is_masterchain())
@@ -1543,24 +2667,80 @@ pub struct EngineMetrics {
     pub download_next_block_requests: DownloaderCounters,
     pub download_block_requests: DownloaderCounters,
     pub download_block_proof_requests: DownloaderCounters,
+
+    pub block_apply_stages: BlockApplyStageMetrics,
+    pub archive_downloads: ArchiveDownloadMetrics,
+    pub disk_space: DiskSpaceMetrics,
+}
+
+/// Archives downloaded during the sync
+#[derive(Debug, Default)]
+pub struct ArchiveDownloadMetrics {
+    /// Number of archives which are being downloaded
+    pub in_flight: AtomicUsize,
+    pub downloaded: AtomicU64,
+    pub downloaded_bytes: AtomicU64,
+}
+
+/// Durations of the block application stages
+#[derive(Debug, Default)]
+pub struct BlockApplyStageMetrics {
+    /// Block proof verification
+    pub proof_check: Histogram,
+    /// Shard state computation and storing
+    pub state_update: Histogram,
+    /// Block connections and applied flag update
+    pub indexing: Histogram,
+    /// Processing the block by all subscribers
+    pub subscribers: Histogram,
 }
 
 #[derive(Debug, Default)]
 pub struct BlockBroadcastCounters {
     pub total: AtomicU64,
     pub invalid: AtomicU64,
+    pub rebroadcasted: AtomicU64,
 }
 
 #[derive(Debug, Default, Serialize, Deserialize, Clone, Copy)]
 pub struct InternalEngineMetrics {
     pub shard_states_cache_len: usize,
+    /// Bytes held by archives, block maps and cached states
+    pub sync_memory_used: usize,
     pub shard_states_operations_len: usize,
     pub block_applying_operations_len: usize,
     pub next_block_applying_operations_len: usize,
     pub download_block_operations_len: usize,
+    pub applied_blocks_operations_len: usize,
     pub cells_cache_stats: CacheStats,
 }
 
+/// The first archive which was not uploaded yet
+struct ArchivesLowerBound {
+    archive_id: AtomicU32,
+    changed: Notify,
+}
+
+/// Masterchain seqno which the delivery record of the block is bound to.
+///
+/// NOTE: shard blocks use their master ref, so that the record key
+/// doesn't depend on the way the block was applied
+pub(crate) fn delivery_mc_seqno(
+    block_id: &ton_block::BlockIdExt,
+    block: &ton_block::Block,
+) -> Result<u32> {
+    if block_id.shard_id.is_masterchain() {
+        return Ok(block_id.seq_no);
+    }
+    Ok(block
+        .read_info()?
+        .read_master_ref()?
+        .map(|master_ref| master_ref.master.seq_no)
+        .unwrap_or_default())
+}
+
+const RETENTION_MANIFEST_FILE: &str = "retention_manifest.json";
+
 #[derive(thiserror::Error, Debug)]
 enum EngineError {
     #[error("Downloading next block is only allowed for masterchain")]
@@ -1571,4 +2751,16 @@ enum EngineError {
     TooDeepRecursion,
     #[error("Overlay not found")]
     OverlayNotFound,
+    #[error("Subscriber panicked")]
+    SubscriberPanicked,
+    #[error("Code hash index is disabled")]
+    CodeHashIndexDisabled,
+    #[error("Shard is not indexed")]
+    ShardNotIndexed,
+    #[error("Shard is detached until the next persistent state")]
+    ShardDetached,
+    #[error("Block not found")]
+    BlockNotFound,
+    #[error("Timeout while waiting for the block")]
+    WaitForBlockTimeout,
 }
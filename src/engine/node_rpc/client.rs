@@ -31,7 +31,7 @@ impl NodeRpcClient {
         let this = &self.0;
 
         let (prepare, neighbour): (proto::PreparedState, _) = this
-            .send_adnl_query(
+            .send_adnl_query_ext(
                 proto::RpcPreparePersistentState {
                     block: full_state_id.block_id.clone(),
                     masterchain_block: full_state_id.mc_block_id.clone(),
@@ -39,6 +39,7 @@ impl NodeRpcClient {
                 None,
                 Some(TIMEOUT_PREPARE),
                 None,
+                proto::Capabilities::FULL_PROTOCOL,
             )
             .await?;
 
@@ -81,11 +82,12 @@ impl NodeRpcClient {
 
         // Prepare
         let (prepare, neighbour): (proto::PreparedState, _) = this
-            .send_adnl_query(
+            .send_adnl_query_ext(
                 proto::RpcPrepareZeroState { block: id.clone() },
                 None,
                 Some(TIMEOUT_PREPARE),
                 None,
+                proto::Capabilities::FULL_PROTOCOL,
             )
             .await?;
 
@@ -203,6 +205,7 @@ impl NodeRpcClient {
     pub async fn download_block_full(
         &self,
         block_id: &ton_block::BlockIdExt,
+        max_requests_per_peer: u32,
     ) -> Result<Option<(BlockStuffAug, BlockProofStuffAug)>> {
         let this = &self.0;
 
@@ -223,6 +226,9 @@ impl NodeRpcClient {
         }
 
         // Download
+        let _slot = neighbour
+            .try_acquire_request_slot(max_requests_per_peer)
+            .ok_or(NodeRpcClientError::NeighbourBusy)?;
         let block_data: proto::DataFull = this
             .send_rldp_query(
                 proto::RpcDownloadBlockFull {
@@ -265,6 +271,7 @@ impl NodeRpcClient {
     pub async fn download_next_block_full(
         &self,
         prev_id: &ton_block::BlockIdExt,
+        max_requests_per_peer: u32,
     ) -> Result<Option<(BlockStuffAug, BlockProofStuffAug)>> {
         const NO_NEIGHBOURS_DELAY: u64 = 1000; // Milliseconds
 
@@ -282,6 +289,9 @@ impl NodeRpcClient {
         };
 
         // Download
+        let _slot = neighbour
+            .try_acquire_request_slot(max_requests_per_peer)
+            .ok_or(NodeRpcClientError::NeighbourBusy)?;
         let data_full: proto::DataFull = this.send_rldp_query(query, neighbour, 0).await?;
 
         // Parse
@@ -338,13 +348,17 @@ impl NodeRpcClient {
 
         let this = &self.0;
 
+        #[cfg(feature = "fault-injection")]
+        let masterchain_seqno = inject_stale_archive(masterchain_seqno);
+
         // Prepare
         let (archive_info, neighbour): (proto::ArchiveInfo, _) = this
-            .send_adnl_query(
+            .send_adnl_query_ext(
                 proto::RpcGetArchiveInfo { masterchain_seqno },
                 Some(1),
                 Some(TIMEOUT_ARCHIVE),
                 neighbour,
+                proto::Capabilities::FULL_PROTOCOL,
             )
             .await?;
 
@@ -356,6 +370,11 @@ impl NodeRpcClient {
             }
         };
 
+        #[cfg(feature = "fault-injection")]
+        if inject_dropped_archive() {
+            return Err(NodeRpcClientError::InjectedFault.into());
+        }
+
         let mut verifier = ArchivePackageVerifier::Start;
 
         let mut offset = 0;
@@ -377,6 +396,13 @@ impl NodeRpcClient {
             .await
             {
                 Ok(Ok(chunk)) => {
+                    #[cfg(feature = "fault-injection")]
+                    let chunk = {
+                        let mut chunk = chunk;
+                        inject_corrupted_chunk(&mut chunk);
+                        chunk
+                    };
+
                     let is_last = chunk.len() < CHUNK_SIZE as usize;
 
                     verifier
@@ -425,10 +451,16 @@ impl NodeRpcClient {
         }
     }
 
-    pub async fn wait_broadcast(&self) -> Result<proto::BlockBroadcast> {
+    /// Waits for the next block broadcast. Returns parsed broadcast and its raw data
+    pub async fn wait_broadcast(&self) -> Result<(proto::BlockBroadcast, Vec<u8>)> {
         let info = self.0.wait_for_broadcast().await;
         let broadcast = tl_proto::deserialize(&info.data)?;
-        Ok(broadcast)
+        Ok((broadcast, info.data))
+    }
+
+    /// Sends serialized block broadcast to random neighbours
+    pub fn broadcast_block(&self, data: Vec<u8>) {
+        self.0.broadcast(data, None);
     }
 }
 
@@ -450,10 +482,15 @@ enum NodeRpcClientError {
     ReceivedBlockIdMismatch,
     #[error("Neighbour not found")]
     NeighbourNotFound,
+    #[error("Neighbour has too many active requests")]
+    NeighbourBusy,
     #[error("Too many failed attempts")]
     TooManyFailedAttempts,
     #[error("Request timeout")]
     RequestTimeout,
     #[error("Failed to get key blocks")]
     KeyBlocksError,
+    #[cfg(feature = "fault-injection")]
+    #[error("Injected fault")]
+    InjectedFault,
 }
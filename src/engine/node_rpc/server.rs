@@ -95,8 +95,8 @@ impl QuerySubscriber for NodeRpcServer {
                 proto::RpcGetCapabilities::TL_ID => {
                     Ok(QueryConsumingResult::Consumed(Some(tl_proto::serialize(
                         proto::Capabilities {
-                            version: 2,
-                            capabilities: 1,
+                            version: proto::Capabilities::VERSION,
+                            capabilities: proto::Capabilities::FULL_PROTOCOL,
                         },
                     ))))
                 }
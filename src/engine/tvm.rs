@@ -0,0 +1,149 @@
+use anyhow::{Context, Result};
+use ton_block::Serializable;
+use ton_types::HashmapType;
+use ton_vm::executor::gas::gas_state::Gas;
+use ton_vm::smart_contract_info::SmartContractInfo;
+use ton_vm::stack::savelist::SaveList;
+use ton_vm::stack::{integer::IntegerData, Stack, StackItem};
+
+use super::accounts::{collect_shard_blocks, find_shard_index};
+use super::Engine;
+
+impl Engine {
+    /// Executes get-method of the account using the state of the specified
+    /// masterchain block (the shard block is resolved from it).
+    ///
+    /// Uses the last applied masterchain block if `at_block` is not specified.
+    /// The execution time is the generation time of that block, so the result is
+    /// reproducible. `gas_limit` defaults to 1000000
+    pub async fn run_get_method(
+        &self,
+        address: &ton_block::MsgAddressInt,
+        method: &str,
+        params: Vec<StackItem>,
+        at_block: Option<&ton_block::BlockIdExt>,
+        gas_limit: Option<i64>,
+    ) -> Result<GetMethodOutput> {
+        let mc_block_id = match at_block {
+            Some(block_id) if !block_id.shard_id.is_masterchain() => {
+                return Err(TvmError::NotMasterchainBlock.into())
+            }
+            Some(block_id) => block_id.clone(),
+            None => self.load_last_applied_mc_block_id()?,
+        };
+        let mc_state = self.load_state(&mc_block_id).await?;
+
+        let mut shard_blocks = collect_shard_blocks(&mc_state)?;
+        let index = find_shard_index(&shard_blocks, address)?;
+        let block_id = shard_blocks.swap_remove(index);
+
+        let state = if block_id == *mc_state.block_id() {
+            mc_state.clone()
+        } else {
+            self.load_state(&block_id).await?
+        };
+
+        let account = state
+            .state()
+            .read_accounts()?
+            .account(&address.address())?
+            .ok_or(TvmError::AccountNotFound)?
+            .read_account()?;
+
+        let (code, data) = match (account.get_code(), account.get_data()) {
+            (Some(code), Some(data)) => (code, data),
+            _ => return Err(TvmError::AccountNotActive.into()),
+        };
+
+        let config = mc_state.config_params()?;
+        let capabilities = config.capabilities();
+
+        let sci = SmartContractInfo {
+            capabilities,
+            myself: ton_types::SliceData::load_cell(address.serialize()?)?,
+            block_lt: account.last_tr_time().unwrap_or_default(),
+            trans_lt: account.last_tr_time().unwrap_or_default(),
+            unix_time: mc_state.state().gen_time(),
+            balance: account.balance().cloned().unwrap_or_default(),
+            config_params: config.config_params.data().cloned(),
+            ..Default::default()
+        };
+
+        let mut ctrls = SaveList::new();
+        ctrls.put(4, &mut StackItem::Cell(data))?;
+        ctrls.put(7, &mut sci.into_temp_data_item())?;
+
+        let mut stack = Stack::new();
+        for param in params {
+            stack.push(param);
+        }
+        let method_id = compute_method_id(method);
+        stack.push(StackItem::int(IntegerData::from_u32(method_id)));
+
+        let libraries = mc_state.state().libraries().clone().inner();
+
+        let mut engine = ton_vm::executor::Engine::with_capabilities(capabilities)
+            .setup_with_libraries(
+                ton_types::SliceData::load_cell(code)?,
+                Some(ctrls),
+                Some(stack),
+                Some(Gas::test_with_limit(
+                    gas_limit.unwrap_or(DEFAULT_GET_METHOD_GAS_LIMIT),
+                )),
+                vec![libraries],
+            );
+
+        let exit_code = match engine.execute() {
+            Ok(exit_code) => exit_code,
+            Err(e) => match ton_vm::error::tvm_exception_code(&e) {
+                Some(code) => code as i32,
+                None => return Err(e).context("Failed to execute get-method"),
+            },
+        };
+
+        Ok(GetMethodOutput {
+            block_id,
+            exit_code,
+            stack: engine.stack().storage.clone(),
+            gas_used: engine.gas_used(),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GetMethodOutput {
+    /// Block with the state which was used for execution
+    pub block_id: ton_block::BlockIdExt,
+    pub exit_code: i32,
+    pub stack: Vec<StackItem>,
+    pub gas_used: i64,
+}
+
+/// Computes get-method id in the same way as FunC compiler
+fn compute_method_id(name: &str) -> u32 {
+    const CRC_16: crc::Crc<u16> = crc::Crc::<u16>::new(&crc::CRC_16_XMODEM);
+    CRC_16.checksum(name.as_bytes()) as u32 | 0x10000
+}
+
+const DEFAULT_GET_METHOD_GAS_LIMIT: i64 = 1_000_000;
+
+#[derive(thiserror::Error, Debug)]
+enum TvmError {
+    #[error("Account not found")]
+    AccountNotFound,
+    #[error("Account is not active")]
+    AccountNotActive,
+    #[error("Get-methods can only be executed at the masterchain block")]
+    NotMasterchainBlock,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn method_ids() {
+        assert_eq!(compute_method_id("seqno"), 85143);
+        assert_eq!(compute_method_id("get_public_key"), 78748);
+    }
+}
@@ -0,0 +1,135 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+
+use crate::config::ArchiveOptions;
+
+/// Archives upload backend
+#[async_trait::async_trait]
+pub trait ArchiveUploader: Send + Sync {
+    /// Interval of polling for new archives
+    fn archives_search_interval(&self) -> Duration {
+        Duration::from_secs(600)
+    }
+
+    /// Uploads an archive.
+    ///
+    /// NOTE: must retry internally until the archive is uploaded
+    async fn upload(&self, archive_id: u32, archive_data: Vec<u8>);
+
+    /// Decides whether this instance should upload the archive
+    /// when the storage is shared between multiple instances.
+    ///
+    /// NOTE: the same archive may still be uploaded by several instances,
+    /// so `upload` must be idempotent
+    async fn acquire_upload_lease(&self, archive_id: u32) -> Result<ArchiveUploadLease> {
+        let _unused_by_default = archive_id;
+        Ok(ArchiveUploadLease::Acquired)
+    }
+
+    /// Downloads an uploaded archive which contains the specified masterchain block.
+    ///
+    /// Returns `None` if there is no such archive in the storage
+    async fn download(&self, mc_seq_no: u32) -> Result<Option<Vec<u8>>> {
+        let _unused_by_default = mc_seq_no;
+        Ok(None)
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ArchiveUploadLease {
+    /// This instance must upload the archive
+    Acquired,
+    /// The archive is already in the storage
+    Uploaded,
+    /// The archive is being uploaded by another instance
+    Busy,
+}
+
+impl std::fmt::Debug for dyn ArchiveUploader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ArchiveUploader")
+            .field("archives_search_interval", &self.archives_search_interval())
+            .finish()
+    }
+}
+
+/// Selects archives uploader from options.
+///
+/// User provided uploader has priority over the built-in one
+pub async fn create_archive_uploader(
+    options: &ArchiveOptions,
+) -> Result<Option<Arc<dyn ArchiveUploader>>> {
+    if let Some(uploader) = &options.uploader {
+        return Ok(Some(uploader.clone()));
+    }
+
+    #[cfg(feature = "archive-uploader")]
+    if let Some(options) = options.uploader_options.clone() {
+        use anyhow::Context;
+
+        let uploader = S3ArchiveUploader::new(options)
+            .await
+            .context("Failed to create archive uploader")?;
+        return Ok(Some(Arc::new(uploader)));
+    }
+
+    Ok(None)
+}
+
+#[cfg(feature = "archive-uploader")]
+struct S3ArchiveUploader {
+    uploader: archive_uploader::ArchiveUploader,
+    interval: Duration,
+}
+
+#[cfg(feature = "archive-uploader")]
+impl S3ArchiveUploader {
+    async fn new(options: archive_uploader::ArchiveUploaderConfig) -> Result<Self> {
+        let interval = Duration::from_secs(options.archives_search_interval_sec);
+        let uploader = archive_uploader::ArchiveUploader::new(options).await?;
+        Ok(Self { uploader, interval })
+    }
+}
+
+#[cfg(feature = "archive-uploader")]
+#[async_trait::async_trait]
+impl ArchiveUploader for S3ArchiveUploader {
+    fn archives_search_interval(&self) -> Duration {
+        self.interval
+    }
+
+    async fn upload(&self, archive_id: u32, archive_data: Vec<u8>) {
+        self.uploader.upload(archive_id, archive_data).await
+    }
+
+    async fn acquire_upload_lease(&self, archive_id: u32) -> Result<ArchiveUploadLease> {
+        if !self.uploader.is_shared() {
+            return Ok(ArchiveUploadLease::Acquired);
+        }
+
+        if self.uploader.is_uploaded(archive_id).await? {
+            Ok(ArchiveUploadLease::Uploaded)
+        } else if self.uploader.try_acquire_upload_lease(archive_id).await? {
+            Ok(ArchiveUploadLease::Acquired)
+        } else {
+            Ok(ArchiveUploadLease::Busy)
+        }
+    }
+
+    async fn download(&self, mc_seq_no: u32) -> Result<Option<Vec<u8>>> {
+        if !self.uploader.is_shared() {
+            return Ok(None);
+        }
+
+        match self
+            .uploader
+            .find_archive(mc_seq_no, crate::storage::ARCHIVE_PACKAGE_SIZE)
+            .await?
+        {
+            Some(archive_id) => self.uploader.download(archive_id).await,
+            None => Ok(None),
+        }
+    }
+}
@@ -1,23 +1,37 @@
 pub use crate::config::*;
 pub use crate::db::RocksdbStats;
 pub use crate::engine::{
-    Engine, EngineMetrics, EngineStatus, InternalEngineMetrics, ProcessBlockContext,
-    ProcessBlocksEdgeContext, Subscriber,
+    AccountChange, AccountState, ArchiveDownloadMetrics, ArchiveUploadLease, ArchiveUploader,
+    BlockApplyStageMetrics, DiskSpaceLevel, DiskSpaceLevelCell, DiskSpaceMetrics, Engine,
+    EngineMetrics, EngineStatus, FileLeaderLock, InternalEngineMetrics, LeaderLock,
+    ProcessBlockContext, ProcessBlocksEdgeContext, SpecialAccount, SpecialAccounts, Subscriber,
+};
+pub use crate::network::{DhtPublishOptions, NeighboursOptions, NetworkMetrics, NodeNetwork};
+pub use crate::storage::{
+    BlockConnection, BlockHandleFlags, BlockHandleFlagsEntry, BlockMeta, BriefBlockMeta,
+    ConfigParamChange, DbMetrics, EngineEvent, EngineEventRecord, FailedSubscriberBlock,
+    KeyBlocksDirection, RetentionManifest, SeqnoRange, ShardSeqnoRange, StorageSnapshot,
+    ValidatorSetEntry, ValidatorSetQuery,
 };
-pub use crate::network::{NeighboursOptions, NetworkMetrics, NodeNetwork};
-pub use crate::storage::{BriefBlockMeta, DbMetrics};
 
+#[cfg(feature = "tvm")]
+pub use crate::engine::GetMethodOutput;
 #[cfg(feature = "archive-uploader")]
 pub use archive_uploader;
 pub use global_config::*;
+#[cfg(feature = "tvm")]
+pub use ton_vm;
 
 mod config;
 mod db;
 mod engine;
+#[cfg(feature = "message-queue")]
+pub mod message_queue;
 mod network;
 mod proto;
 mod storage;
 pub mod utils;
+pub mod wire;
 
 pub mod alloc {
     use broxus_util::alloc::set_jemalloc_param;
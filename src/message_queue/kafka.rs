@@ -0,0 +1,76 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use rdkafka::config::ClientConfig;
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use serde::{Deserialize, Serialize};
+
+use super::{MessageProducer, QueueMessage};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KafkaProducerConfig {
+    /// Comma separated list of brokers
+    pub brokers: String,
+    /// Max time to wait for the message to be acknowledged by all replicas.
+    ///
+    /// Default: 30000
+    #[serde(default = "default_message_timeout_ms")]
+    pub message_timeout_ms: u64,
+    /// Additional `librdkafka` properties (e.g. `security.protocol`)
+    #[serde(default)]
+    pub properties: HashMap<String, String>,
+}
+
+fn default_message_timeout_ms() -> u64 {
+    30000
+}
+
+/// Kafka producer with idempotent delivery acknowledged by all in-sync replicas
+pub struct KafkaProducer {
+    producer: FutureProducer,
+    message_timeout: Duration,
+}
+
+impl KafkaProducer {
+    pub fn new(config: &KafkaProducerConfig) -> Result<Self> {
+        let mut client_config = ClientConfig::new();
+        client_config
+            .set("bootstrap.servers", &config.brokers)
+            .set("message.timeout.ms", config.message_timeout_ms.to_string())
+            .set("enable.idempotence", "true")
+            .set("acks", "all");
+        for (key, value) in &config.properties {
+            client_config.set(key, value);
+        }
+
+        let producer = client_config
+            .create()
+            .context("Failed to create Kafka producer")?;
+
+        Ok(Self {
+            producer,
+            message_timeout: Duration::from_millis(config.message_timeout_ms),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl MessageProducer for KafkaProducer {
+    async fn send(&self, messages: Vec<QueueMessage>) -> Result<()> {
+        let deliveries = messages.iter().map(|message| {
+            let record = FutureRecord::to(&message.topic)
+                .key(&message.key)
+                .payload(&message.payload);
+            self.producer.send(record, self.message_timeout)
+        });
+
+        for result in futures_util::future::join_all(deliveries).await {
+            if let Err((e, _)) = result {
+                return Err(e).context("Failed to deliver Kafka message");
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,231 @@
+//! Forwarding of the applied blocks and transactions to external message queues.
+//!
+//! Messages (topics, partition keys, payloads) are sent by the [`MessageProducer`].
+//! Kafka producer is available with the `message-queue-kafka` feature,
+//! other queues can be used with a custom implementation.
+//!
+//! Delivery is at-least-once with the `halt` and `retry` subscriber error policies.
+//! With [`MessageQueueConfig::durable_id`] the delivered blocks are recorded per subscriber
+//! (see [`Subscriber::durable_id`]) after all messages were confirmed by the producer,
+//! so only the blocks which were not confirmed are sent again after the restart.
+//! Without it every replayed block is sent again.
+//! With the `skip` policy the messages of the failed block are lost, the block
+//! is only remembered with the subscriber id in [`Engine::load_failed_subscriber_blocks`]
+//! for reprocessing.
+//! Consumers must be ready for duplicates in all cases.
+//!
+//! [`Engine::load_failed_subscriber_blocks`]: crate::Engine::load_failed_subscriber_blocks
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use ton_block::{HashmapAugType, Serializable};
+
+use crate::engine::{ProcessBlockContext, Subscriber};
+use crate::utils::BlockIdExtDisplay;
+use crate::wire;
+
+#[cfg(feature = "message-queue-kafka")]
+pub use self::kafka::{KafkaProducer, KafkaProducerConfig};
+
+#[cfg(feature = "message-queue-kafka")]
+mod kafka;
+
+/// Message queue client
+#[async_trait::async_trait]
+pub trait MessageProducer: Send + Sync {
+    /// Sends all messages and waits until they are confirmed by the queue
+    async fn send(&self, messages: Vec<QueueMessage>) -> Result<()>;
+}
+
+#[derive(Debug, Clone)]
+pub struct QueueMessage {
+    pub topic: String,
+    /// Partition key, see [`PartitionBy`]
+    pub key: String,
+    pub payload: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MessageQueueConfig {
+    /// Persistent subscriber id for the delivery records.
+    /// Blocks are sent again after the restart if `None`
+    pub durable_id: Option<String>,
+    /// Topic for the raw block BOCs. Blocks are not forwarded if `None`
+    pub blocks_topic: Option<String>,
+    /// Topic for the transaction BOCs. Transactions are not forwarded if `None`
+    pub transactions_topic: Option<String>,
+    /// Whether to forward masterchain blocks and transactions. Default: true
+    pub include_masterchain: bool,
+    /// Partition key for the transactions. Blocks are always partitioned
+    /// by shard. Default: account
+    pub partition_by: PartitionBy,
+    /// Message payload format. Default: boc
+    pub format: PayloadFormat,
+}
+
+impl Default for MessageQueueConfig {
+    fn default() -> Self {
+        Self {
+            durable_id: None,
+            blocks_topic: None,
+            transactions_topic: None,
+            include_masterchain: true,
+            partition_by: PartitionBy::Account,
+            format: PayloadFormat::Boc,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PartitionBy {
+    /// `{workchain}:{shard_prefix_with_tag:016x}`
+    Shard,
+    /// `{workchain}:{account:064x}`
+    Account,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PayloadFormat {
+    /// Raw block or transaction BOC
+    Boc,
+    /// `Envelope` message from the [`wire::SCHEMA`]
+    Protobuf,
+}
+
+/// Subscriber which converts applied blocks into the queue messages
+/// and passes them to the producer
+pub struct MessageQueueSubscriber<P> {
+    config: MessageQueueConfig,
+    producer: P,
+}
+
+impl<P: MessageProducer> MessageQueueSubscriber<P> {
+    pub fn new(config: MessageQueueConfig, producer: P) -> Self {
+        Self { config, producer }
+    }
+
+    pub fn producer(&self) -> &P {
+        &self.producer
+    }
+
+    async fn collect_messages(&self, ctx: &ProcessBlockContext<'_>) -> Result<Vec<QueueMessage>> {
+        let shard = &ctx.id().shard_id;
+
+        let mut messages = Vec::new();
+
+        if let Some(topic) = &self.config.blocks_topic {
+            messages.push(QueueMessage {
+                topic: topic.clone(),
+                key: shard_key(shard),
+                payload: match self.config.format {
+                    PayloadFormat::Boc => ctx.load_block_data().await?,
+                    PayloadFormat::Protobuf => wire::encode_block(ctx.id(), ctx.block(), false)?,
+                },
+            });
+        }
+
+        if let Some(topic) = &self.config.transactions_topic {
+            let partition_by = self.config.partition_by;
+            let format = self.config.format;
+
+            ctx.block()
+                .read_extra()?
+                .read_account_blocks()?
+                .iterate_objects(|account_block| {
+                    let key = match partition_by {
+                        PartitionBy::Shard => shard_key(shard),
+                        PartitionBy::Account => account_key(
+                            shard.workchain_id(),
+                            &account_block.account_id().clone().get_next_hash()?,
+                        ),
+                    };
+
+                    account_block
+                        .transactions()
+                        .iterate_objects(|transaction| {
+                            let cell = transaction.0.serialize()?;
+                            let boc = ton_types::serialize_toc(&cell)?;
+                            let payload = match format {
+                                PayloadFormat::Boc => boc,
+                                PayloadFormat::Protobuf => wire::encode_transaction(
+                                    shard.workchain_id(),
+                                    &cell.repr_hash(),
+                                    &transaction.0,
+                                    Some(&boc),
+                                )?,
+                            };
+                            messages.push(QueueMessage {
+                                topic: topic.clone(),
+                                key: key.clone(),
+                                payload,
+                            });
+                            Ok(true)
+                        })?;
+                    Ok(true)
+                })?;
+        }
+
+        Ok(messages)
+    }
+}
+
+#[async_trait::async_trait]
+impl<P: MessageProducer> Subscriber for MessageQueueSubscriber<P> {
+    fn durable_id(&self) -> Option<&str> {
+        self.config.durable_id.as_deref()
+    }
+
+    async fn process_block(&self, ctx: ProcessBlockContext<'_>) -> Result<()> {
+        if ctx.is_masterchain() && !self.config.include_masterchain {
+            return Ok(());
+        }
+
+        let messages = self.collect_messages(&ctx).await?;
+        if messages.is_empty() {
+            return Ok(());
+        }
+
+        let count = messages.len();
+        self.producer.send(messages).await?;
+
+        tracing::debug!(
+            target: "message_queue",
+            block_id = %ctx.id().display(),
+            count,
+            "forwarded messages"
+        );
+        Ok(())
+    }
+}
+
+fn shard_key(shard: &ton_block::ShardIdent) -> String {
+    format!(
+        "{}:{:016x}",
+        shard.workchain_id(),
+        shard.shard_prefix_with_tag()
+    )
+}
+
+fn account_key(workchain: i32, account: &ton_types::UInt256) -> String {
+    format!("{workchain}:{}", hex::encode(account.as_slice()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn partition_keys() {
+        assert_eq!(
+            shard_key(&ton_block::ShardIdent::masterchain()),
+            "-1:8000000000000000"
+        );
+        assert_eq!(
+            account_key(0, &ton_types::UInt256::from([0xab; 32])),
+            format!("0:{}", "ab".repeat(32))
+        );
+    }
+}
@@ -0,0 +1,92 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use rand::Rng;
+
+use super::WorkingState;
+
+#[derive(Debug, Copy, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DhtPublishOptions {
+    /// Interval between successful republishing of the DHT records.
+    /// Must be less than the records TTL.
+    ///
+    /// Default: 500
+    pub interval_sec: u64,
+    /// Max random delay added to the interval to spread the load
+    ///
+    /// Default: 60
+    pub max_jitter_sec: u64,
+    /// Delay between retries after failed publishing
+    ///
+    /// Default: 10
+    pub retry_interval_sec: u64,
+    /// Number of retries after which the task waits the full interval
+    ///
+    /// Default: 5
+    pub max_retries: u32,
+}
+
+impl Default for DhtPublishOptions {
+    fn default() -> Self {
+        Self {
+            interval_sec: 500,
+            max_jitter_sec: 60,
+            retry_interval_sec: 10,
+            max_retries: 5,
+        }
+    }
+}
+
+impl DhtPublishOptions {
+    fn next_interval(&self) -> Duration {
+        let jitter = match self.max_jitter_sec {
+            0 => 0,
+            max_jitter => rand::thread_rng().gen_range(0..=max_jitter * 1000),
+        };
+        Duration::from_millis(self.interval_sec * 1000 + jitter)
+    }
+}
+
+/// Periodically calls `publish` until the network is stopped
+pub(super) fn start_publishing<F, R>(
+    working_state: Arc<WorkingState>,
+    options: DhtPublishOptions,
+    record: &'static str,
+    mut publish: F,
+) where
+    F: FnMut() -> R + Send + 'static,
+    R: Future<Output = Result<()>> + Send,
+{
+    let retry_interval = Duration::from_secs(options.retry_interval_sec);
+
+    tokio::spawn(async move {
+        let mut retries = 0;
+        while working_state.is_working() {
+            let interval = match publish().await {
+                Ok(()) => {
+                    tracing::debug!(record, "published DHT record");
+                    retries = 0;
+                    options.next_interval()
+                }
+                Err(e) if retries < options.max_retries => {
+                    retries += 1;
+                    tracing::warn!(record, retries, "failed to publish DHT record: {e:?}");
+                    retry_interval
+                }
+                Err(e) => {
+                    tracing::error!(record, "failed to publish DHT record: {e:?}");
+                    retries = 0;
+                    options.next_interval()
+                }
+            };
+
+            if working_state.wait_or_complete(interval).await {
+                break;
+            }
+        }
+        tracing::warn!(record, "stopped publishing DHT record");
+    });
+}
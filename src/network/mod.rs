@@ -14,12 +14,15 @@ use everscale_network::*;
 use global_config::*;
 use tokio_util::sync::CancellationToken;
 
-pub use self::neighbour::Neighbour;
+use self::dht_publisher::start_publishing;
+pub use self::dht_publisher::DhtPublishOptions;
+pub use self::neighbour::{Neighbour, NeighbourRequestSlot};
 use self::neighbours::Neighbours;
 pub use self::neighbours::{NeighboursMetrics, NeighboursOptions};
 pub use self::overlay_client::OverlayClient;
 use crate::utils::FastDashMap;
 
+mod dht_publisher;
 mod neighbour;
 mod neighbours;
 mod neighbours_cache;
@@ -30,6 +33,7 @@ pub struct NodeNetwork {
     dht: Arc<dht::Node>,
     overlay: Arc<overlay::Node>,
     rldp: Arc<rldp::Node>,
+    dht_publish_options: DhtPublishOptions,
     neighbours_options: NeighboursOptions,
     overlay_shard_options: overlay::OverlayOptions,
     overlays: Arc<FastDashMap<overlay::IdShort, Arc<OverlayClient>>>,
@@ -54,6 +58,7 @@ impl NodeNetwork {
         adnl_options: adnl::NodeOptions,
         rldp_options: rldp::NodeOptions,
         dht_options: dht::NodeOptions,
+        dht_publish_options: DhtPublishOptions,
         neighbours_options: NeighboursOptions,
         overlay_shard_options: overlay::OverlayOptions,
         global_config: GlobalConfig,
@@ -75,17 +80,28 @@ impl NodeNetwork {
 
         let dht_key = adnl.key_by_tag(Self::TAG_DHT_KEY)?.clone();
         tracing::info!(local_id = %dht_key.id(), "created DHT node");
-        start_broadcasting_our_ip(working_state.clone(), dht.clone(), dht_key);
+        start_broadcasting_our_ip(
+            working_state.clone(),
+            dht_publish_options,
+            dht.clone(),
+            dht_key,
+        );
 
         let overlay_key = adnl.key_by_tag(Self::TAG_OVERLAY_KEY)?.clone();
         tracing::info!(local_id = %overlay_key.id(), "created overlay node");
-        start_broadcasting_our_ip(working_state.clone(), dht.clone(), overlay_key);
+        start_broadcasting_our_ip(
+            working_state.clone(),
+            dht_publish_options,
+            dht.clone(),
+            overlay_key,
+        );
 
         let node_network = Arc::new(NodeNetwork {
             adnl,
             dht,
             overlay,
             rldp,
+            dht_publish_options,
             neighbours_options,
             overlay_shard_options,
             overlays: Arc::new(Default::default()),
@@ -163,6 +179,7 @@ impl NodeNetwork {
 
         start_broadcasting_our_node(
             self.working_state.clone(),
+            self.dht_publish_options,
             self.dht.clone(),
             overlay_full_id,
             node,
@@ -295,49 +312,36 @@ impl WorkingState {
 
 fn start_broadcasting_our_ip(
     working_state: Arc<WorkingState>,
+    options: DhtPublishOptions,
     dht: Arc<dht::Node>,
     key: Arc<adnl::Key>,
 ) {
-    const IP_BROADCASTING_INTERVAL: u64 = 500; // Seconds
-    let interval = Duration::from_secs(IP_BROADCASTING_INTERVAL);
     let addr = dht.adnl().socket_addr();
 
-    tokio::spawn(async move {
-        while working_state.is_working() {
-            if let Err(e) = dht.store_address(&key, addr).await {
-                tracing::warn!("failed to store address in DHT: {e:?}")
-            }
-
-            if working_state.wait_or_complete(interval).await {
-                break;
-            }
-        }
-        tracing::warn!("stopped broadcasting our ip");
+    start_publishing(working_state, options, "address", move || {
+        let dht = dht.clone();
+        let key = key.clone();
+        async move { dht.store_address(&key, addr).await.map(|_| ()) }
     });
 }
 
 fn start_broadcasting_our_node(
     working_state: Arc<WorkingState>,
+    options: DhtPublishOptions,
     dht: Arc<dht::Node>,
     overlay_full_id: overlay::IdFull,
     overlay_node: proto::overlay::NodeOwned,
 ) {
-    const NODE_BROADCASTING_INTERVAL: u64 = 500; // Seconds
-    let interval = Duration::from_secs(NODE_BROADCASTING_INTERVAL);
-
-    tokio::spawn(async move {
-        while working_state.is_working() {
-            let result = dht
-                .store_overlay_node(&overlay_full_id, overlay_node.as_equivalent_ref())
-                .await;
-
-            tracing::info!("overlay_store status: {result:?}");
-
-            if working_state.wait_or_complete(interval).await {
-                break;
-            }
+    let overlay_node = Arc::new(overlay_node);
+
+    start_publishing(working_state, options, "overlay_node", move || {
+        let dht = dht.clone();
+        let overlay_node = overlay_node.clone();
+        async move {
+            dht.store_overlay_node(&overlay_full_id, overlay_node.as_equivalent_ref())
+                .await
+                .map(|_| ())
         }
-        tracing::warn!("stopped broadcasting our node");
     });
 }
 
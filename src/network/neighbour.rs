@@ -1,4 +1,5 @@
 use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
 
 use everscale_network::adnl;
 use rand::Rng;
@@ -21,6 +22,8 @@ pub struct Neighbour {
     penalty_points: AtomicU32,
     active_check: AtomicBool,
     unreliability: AtomicU32,
+
+    active_requests: AtomicU32,
 }
 
 #[derive(Default, Copy, Clone)]
@@ -42,6 +45,7 @@ impl Neighbour {
             penalty_points: Default::default(),
             active_check: Default::default(),
             unreliability: Default::default(),
+            active_requests: Default::default(),
         }
     }
 
@@ -50,6 +54,7 @@ impl Neighbour {
         rng: &mut R,
         total_weight: &mut u64,
         average_failures: f64,
+        latency_weighted: bool,
     ) -> bool {
         let mut unreliability = self.unreliability.load(Ordering::Acquire);
         let penalty_points = self.penalty_points.load(Ordering::Acquire);
@@ -60,7 +65,9 @@ impl Neighbour {
 
         if proto_version < PROTO_VERSION {
             unreliability += 4;
-        } else if proto_version == PROTO_VERSION && capabilities < PROTO_CAPABILITIES {
+        } else if proto_version == PROTO_VERSION
+            && capabilities & PROTO_CAPABILITIES != PROTO_CAPABILITIES
+        {
             unreliability += 2;
         }
 
@@ -85,7 +92,17 @@ impl Neighbour {
             self.active_check.store(true, Ordering::Release);
         }
 
-        let weight = (1 << (FAIL_UNRELIABILITY - unreliability)) as u64;
+        let mut weight = (1 << (FAIL_UNRELIABILITY - unreliability)) as u64;
+        if latency_weighted {
+            // NOTE: RLDP roundtrip includes the transfer time, so it also reflects throughput
+            if let Some(roundtrip) = self.roundtrip_rldp() {
+                weight = std::cmp::max(
+                    weight * REFERENCE_ROUNDTRIP_MS
+                        / roundtrip.clamp(MIN_ROUNDTRIP_MS, MAX_ROUNDTRIP_MS),
+                    1,
+                );
+            }
+        }
         *total_weight += weight;
 
         rng.gen_range(0..*total_weight) < weight
@@ -109,6 +126,11 @@ impl Neighbour {
             .store(data.capabilities, Ordering::Release);
     }
 
+    /// Whether the neighbour advertised all `required` capabilities
+    pub fn has_capabilities(&self, required: u64) -> bool {
+        (self.capabilities.load(Ordering::Acquire) & required) == required
+    }
+
     pub fn update_stats(
         &self,
         roundtrip: u64,
@@ -195,6 +217,42 @@ impl Neighbour {
     pub fn unreliability(&self) -> u32 {
         self.unreliability.load(Ordering::Acquire)
     }
+
+    /// Reserves a slot for the request to this neighbour.
+    ///
+    /// Returns `None` if there are already `limit` active requests.
+    /// Zero `limit` means no limit
+    pub fn try_acquire_request_slot(self: &Arc<Self>, limit: u32) -> Option<NeighbourRequestSlot> {
+        self.active_requests
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |active| {
+                if limit == 0 || active < limit {
+                    Some(active + 1)
+                } else {
+                    None
+                }
+            })
+            .ok()?;
+        Some(NeighbourRequestSlot(self.clone()))
+    }
+
+    pub fn active_requests(&self) -> u32 {
+        self.active_requests.load(Ordering::Acquire)
+    }
+}
+
+/// Active request to the neighbour. Releases the slot on drop
+pub struct NeighbourRequestSlot(Arc<Neighbour>);
+
+impl NeighbourRequestSlot {
+    pub fn neighbour(&self) -> &Arc<Neighbour> {
+        &self.0
+    }
+}
+
+impl Drop for NeighbourRequestSlot {
+    fn drop(&mut self) {
+        self.0.active_requests.fetch_sub(1, Ordering::AcqRel);
+    }
 }
 
 fn fetch_roundtrip(storage: &AtomicU64) -> Option<u64> {
@@ -216,6 +274,11 @@ fn set_roundtrip(storage: &AtomicU64, roundtrip: u64) {
     storage.store(roundtrip, Ordering::Release);
 }
 
-const PROTO_VERSION: u32 = 2;
-const PROTO_CAPABILITIES: u64 = 1;
+const PROTO_VERSION: u32 = proto::Capabilities::VERSION;
+const PROTO_CAPABILITIES: u64 = proto::Capabilities::FULL_PROTOCOL;
 const FAIL_UNRELIABILITY: u32 = 10;
+
+/// Neighbours with this roundtrip keep their reliability weight
+const REFERENCE_ROUNDTRIP_MS: u64 = 1000;
+const MIN_ROUNDTRIP_MS: u64 = 10;
+const MAX_ROUNDTRIP_MS: u64 = 60000;
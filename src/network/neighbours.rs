@@ -49,6 +49,12 @@ pub struct NeighboursOptions {
     pub max_ping_tasks: usize,
     /// Default: 6
     pub max_exchange_tasks: usize,
+    /// Probability of choosing a neighbour without taking its latency into account.
+    /// Allows to measure neighbours which were slow before.
+    /// Clamped to `0..=1`, NaN is replaced with the default.
+    ///
+    /// Default: 0.1
+    pub exploration_factor: f64,
 }
 
 impl Default for NeighboursOptions {
@@ -64,6 +70,7 @@ impl Default for NeighboursOptions {
             default_rldp_roundtrip_ms: 2000,
             max_ping_tasks: 6,
             max_exchange_tasks: 6,
+            exploration_factor: 0.1,
         }
     }
 }
@@ -73,8 +80,15 @@ impl Neighbours {
         dht: &Arc<dht::Node>,
         overlay: &Arc<overlay::Overlay>,
         initial_peers: &[adnl::NodeIdShort],
-        options: NeighboursOptions,
+        mut options: NeighboursOptions,
     ) -> Arc<Self> {
+        // NOTE: `gen_bool` panics on probabilities outside `0..=1` (including NaN)
+        options.exploration_factor = if options.exploration_factor.is_nan() {
+            NeighboursOptions::default().exploration_factor
+        } else {
+            options.exploration_factor.clamp(0.0, 1.0)
+        };
+
         let cache = Arc::new(NeighboursCache::new(
             initial_peers,
             options.max_neighbours,
@@ -225,8 +239,29 @@ impl Neighbours {
     }
 
     pub fn choose_neighbour(&self) -> Option<Arc<Neighbour>> {
+        self.choose_neighbour_with_capabilities(0)
+    }
+
+    /// Prefers neighbours which advertised all `required` capabilities.
+    /// Falls back to any neighbour if there are no such neighbours yet
+    pub fn choose_neighbour_with_capabilities(&self, required: u64) -> Option<Arc<Neighbour>> {
+        use rand::Rng;
+
+        let rng = &mut rand::thread_rng();
+        let average_failures = self.average_failures();
+        let latency_weighted = !rng.gen_bool(self.options.exploration_factor);
+
+        if required != 0 {
+            if let Some(neighbour) =
+                self.cache
+                    .choose_neighbour(rng, average_failures, required, latency_weighted)
+            {
+                return Some(neighbour);
+            }
+        }
+
         self.cache
-            .choose_neighbour(&mut rand::thread_rng(), self.average_failures())
+            .choose_neighbour(rng, average_failures, 0, latency_weighted)
     }
 
     pub fn average_failures(&self) -> f64 {
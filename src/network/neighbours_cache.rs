@@ -42,10 +42,17 @@ impl NeighboursCache {
         &self,
         rng: &mut impl Rng,
         average_failures: f64,
+        required_capabilities: u64,
+        latency_weighted: bool,
     ) -> Option<Arc<Neighbour>> {
         self.state
             .read()
-            .choose_neighbour(rng, average_failures)
+            .choose_neighbour(
+                rng,
+                average_failures,
+                required_capabilities,
+                latency_weighted,
+            )
             .cloned()
     }
 
@@ -101,21 +108,25 @@ impl NeighboursCacheState {
         &self,
         rng: &mut impl Rng,
         average_failures: f64,
+        required_capabilities: u64,
+        latency_weighted: bool,
     ) -> Option<&Arc<Neighbour>> {
         if self.indices.len() == 1 {
             let first = self.indices.first();
-            return first.and_then(|peer_id| self.values.get(peer_id));
+            return first
+                .and_then(|peer_id| self.values.get(peer_id))
+                .filter(|neighbour| neighbour.has_capabilities(required_capabilities));
         }
 
         let mut best_neighbour = None;
         let mut total_weight = 0;
         for neighbour in &self.indices {
             let neighbour = match self.values.get(neighbour) {
-                Some(neighbour) => neighbour,
-                None => continue,
+                Some(neighbour) if neighbour.has_capabilities(required_capabilities) => neighbour,
+                _ => continue,
             };
 
-            if neighbour.try_select(rng, &mut total_weight, average_failures) {
+            if neighbour.try_select(rng, &mut total_weight, average_failures, latency_weighted) {
                 best_neighbour = Some(neighbour);
             }
         }
@@ -96,6 +96,23 @@ impl OverlayClient {
         timeout: Option<u64>,
         explicit_neighbour: Option<&Arc<Neighbour>>,
     ) -> Result<(A, Arc<Neighbour>)>
+    where
+        Q: TlWrite,
+        for<'a> A: TlRead<'a, Repr = tl_proto::Boxed> + 'static,
+    {
+        self.send_adnl_query_ext(query, attempts, timeout, explicit_neighbour, 0)
+            .await
+    }
+
+    /// Sends ADNL query preferring neighbours with the `required_capabilities`
+    pub async fn send_adnl_query_ext<Q, A>(
+        &self,
+        query: Q,
+        attempts: Option<u32>,
+        timeout: Option<u64>,
+        explicit_neighbour: Option<&Arc<Neighbour>>,
+        required_capabilities: u64,
+    ) -> Result<(A, Arc<Neighbour>)>
     where
         Q: TlWrite,
         for<'a> A: TlRead<'a, Repr = tl_proto::Boxed> + 'static,
@@ -110,7 +127,10 @@ impl OverlayClient {
         for _ in 0..attempts {
             let neighbour = match explicit_neighbour {
                 Some(neighbour) => neighbour.clone(),
-                None => match self.neighbours.choose_neighbour() {
+                None => match self
+                    .neighbours
+                    .choose_neighbour_with_capabilities(required_capabilities)
+                {
                     Some(neighbour) => neighbour,
                     None => {
                         tokio::time::sleep(Duration::from_millis(NO_NEIGHBOURS_DELAY)).await;
@@ -218,6 +238,13 @@ impl OverlayClient {
             )
             .await?;
 
+        #[cfg(feature = "fault-injection")]
+        let (answer, roundtrip) = {
+            let delay = crate::utils::inject_response_delay().await;
+            let answer = answer.filter(|_| !crate::utils::inject_dropped_response());
+            (answer, roundtrip + delay.as_millis() as u64)
+        };
+
         match answer {
             Some(answer) => Ok((answer, neighbour, roundtrip)),
             None => {
@@ -259,6 +259,16 @@ pub struct Capabilities {
     pub capabilities: u64,
 }
 
+impl Capabilities {
+    /// Protocol version advertised by this node
+    pub const VERSION: u32 = 2;
+
+    /// Full protocol support (archives and states downloading).
+    ///
+    /// NOTE: this is the only flag which is advertised by the nodes
+    pub const FULL_PROTOCOL: u64 = 1;
+}
+
 mod tl_signature_pair_vec {
     use super::*;
 
@@ -1,85 +1,117 @@
 use std::sync::Arc;
 
 use anyhow::Result;
+use parking_lot::Mutex;
 
 use super::models::BlockHandle;
 use crate::db::*;
-use crate::utils::{read_block_id_le, write_block_id_le, StoredValue};
+use crate::utils::{read_block_id_le, write_block_id_le, FastHashMap, StoredValue};
 
 /// Stores relations between blocks
 pub struct BlockConnectionStorage {
     db: Arc<Db>,
+    /// Connections which were added to the batch but not written yet
+    pending: Mutex<FastHashMap<(ton_types::UInt256, BlockConnection), ton_block::BlockIdExt>>,
 }
 
 impl BlockConnectionStorage {
     pub fn new(db: Arc<Db>) -> Result<Self> {
-        Ok(Self { db })
+        Ok(Self {
+            db,
+            pending: Default::default(),
+        })
     }
 
-    pub fn store_connection(
+    /// Adds connection and updated block handle to the batch.
+    ///
+    /// Handle flags are updated immediately and the connection is visible
+    /// through [`BlockConnectionStorage::load_connection`] until the batch is written.
+    /// Use [`BlockConnectionStorage::commit_pending`] after the batch was written
+    /// or [`BlockConnectionStorage::rollback_pending`] if it was not
+    pub fn store_connection_batched(
         &self,
-        handle: &BlockHandle,
+        batch: &mut rocksdb::WriteBatch,
+        pending: &mut PendingConnections,
+        handle: &Arc<BlockHandle>,
         direction: BlockConnection,
         connected_block_id: &ton_block::BlockIdExt,
-    ) -> Result<()> {
+    ) {
         // Use strange match because all columns have different types
         let store = match direction {
             BlockConnection::Prev1 => {
                 if handle.meta().has_prev1() {
-                    return Ok(());
+                    return;
                 }
-                store_block_connection_impl(&self.db.prev1, handle, connected_block_id)?;
+                store_block_connection_impl(batch, &self.db.prev1, handle, connected_block_id);
                 handle.meta().set_has_prev1()
             }
             BlockConnection::Prev2 => {
                 if handle.meta().has_prev2() {
-                    return Ok(());
+                    return;
                 }
-                store_block_connection_impl(&self.db.prev2, handle, connected_block_id)?;
+                store_block_connection_impl(batch, &self.db.prev2, handle, connected_block_id);
                 handle.meta().set_has_prev2()
             }
             BlockConnection::Next1 => {
                 if handle.meta().has_next1() {
-                    return Ok(());
+                    return;
                 }
-                store_block_connection_impl(&self.db.next1, handle, connected_block_id)?;
+                store_block_connection_impl(batch, &self.db.next1, handle, connected_block_id);
                 handle.meta().set_has_next1()
             }
             BlockConnection::Next2 => {
                 if handle.meta().has_next2() {
-                    return Ok(());
+                    return;
                 }
-                store_block_connection_impl(&self.db.next2, handle, connected_block_id)?;
+                store_block_connection_impl(batch, &self.db.next2, handle, connected_block_id);
                 handle.meta().set_has_next2()
             }
         };
 
         if store {
+            pending.items.push((handle.clone(), direction));
+            self.pending.lock().insert(
+                (handle.id().root_hash, direction),
+                connected_block_id.clone(),
+            );
+
             let id = handle.id();
 
+            batch.put_cf(
+                &self.db.block_handles.cf(),
+                id.root_hash.as_slice(),
+                handle.meta().to_vec(),
+            );
             if handle.is_key_block() {
-                let mut write_batch = rocksdb::WriteBatch::default();
-
-                write_batch.put_cf(
-                    &self.db.block_handles.cf(),
-                    id.root_hash.as_slice(),
-                    handle.meta().to_vec(),
-                );
-                write_batch.put_cf(
+                batch.put_cf(
                     &self.db.key_blocks.cf(),
                     id.seq_no.to_be_bytes(),
                     id.to_vec(),
                 );
-
-                self.db.raw().write(write_batch)?;
-            } else {
-                self.db
-                    .block_handles
-                    .insert(id.root_hash.as_slice(), handle.meta().to_vec())?;
             }
         }
+    }
 
-        Ok(())
+    /// Forgets connections which were written with the batch
+    pub fn commit_pending(&self, pending: PendingConnections) {
+        let mut map = self.pending.lock();
+        for (handle, direction) in pending.items {
+            map.remove(&(handle.id().root_hash, direction));
+        }
+    }
+
+    /// Resets handle flags of the connections which were not written
+    pub fn rollback_pending(&self, pending: PendingConnections) {
+        let mut map = self.pending.lock();
+        for (handle, direction) in pending.items {
+            map.remove(&(handle.id().root_hash, direction));
+            match direction {
+                BlockConnection::Prev1 => handle.meta().clear_has_prev1(),
+                BlockConnection::Prev2 => handle.meta().clear_has_prev2(),
+                BlockConnection::Next1 => handle.meta().clear_has_next1(),
+                BlockConnection::Next2 => handle.meta().clear_has_next2(),
+            }
+        }
     }
 
     pub fn load_connection(
@@ -87,6 +119,10 @@ impl BlockConnectionStorage {
         block_id: &ton_block::BlockIdExt,
         direction: BlockConnection,
     ) -> Result<ton_block::BlockIdExt> {
+        if let Some(id) = self.pending.lock().get(&(block_id.root_hash, direction)) {
+            return Ok(id.clone());
+        }
+
         match direction {
             BlockConnection::Prev1 => load_block_connection_impl(&self.db.prev1, block_id),
             BlockConnection::Prev2 => load_block_connection_impl(&self.db.prev2, block_id),
@@ -96,7 +132,13 @@ impl BlockConnectionStorage {
     }
 }
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+/// Connections which were added to the batch but not written yet
+#[derive(Default)]
+pub struct PendingConnections {
+    items: Vec<(Arc<BlockHandle>, BlockConnection)>,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 pub enum BlockConnection {
     Prev1,
     Prev2,
@@ -106,14 +148,15 @@ pub enum BlockConnection {
 
 #[inline]
 fn store_block_connection_impl<T>(
+    batch: &mut rocksdb::WriteBatch,
     db: &Table<T>,
     handle: &BlockHandle,
     block_id: &ton_block::BlockIdExt,
-) -> Result<(), rocksdb::Error>
-where
+) where
     T: ColumnFamily,
 {
-    db.insert(
+    batch.put_cf(
+        &db.cf(),
         handle.id().root_hash.as_slice(),
         write_block_id_le(block_id),
     )
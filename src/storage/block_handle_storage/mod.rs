@@ -25,26 +25,6 @@ impl BlockHandleStorage {
         })
     }
 
-    pub fn store_block_applied(&self, handle: &Arc<BlockHandle>) -> Result<bool> {
-        if handle.meta().set_is_applied() {
-            self.store_handle(handle)?;
-            Ok(true)
-        } else {
-            Ok(false)
-        }
-    }
-
-    pub fn assign_mc_ref_seq_no(
-        &self,
-        handle: &Arc<BlockHandle>,
-        mc_ref_seq_no: u32,
-    ) -> Result<()> {
-        if handle.set_masterchain_ref_seqno(mc_ref_seq_no)? {
-            self.store_handle(handle)?;
-        }
-        Ok(())
-    }
-
     pub fn create_or_load_handle(
         &self,
         block_id: &ton_block::BlockIdExt,
@@ -89,6 +69,25 @@ impl BlockHandleStorage {
         })
     }
 
+    /// Adds block handle to the batch
+    pub fn store_handle_batched(&self, batch: &mut rocksdb::WriteBatch, handle: &BlockHandle) {
+        let id = handle.id();
+
+        batch.put_cf(
+            &self.db.block_handles.cf(),
+            id.root_hash.as_slice(),
+            handle.meta().to_vec(),
+        );
+
+        if handle.is_key_block() {
+            batch.put_cf(
+                &self.db.key_blocks.cf(),
+                id.seq_no.to_be_bytes(),
+                id.to_vec(),
+            );
+        }
+    }
+
     pub fn store_handle(&self, handle: &BlockHandle) -> Result<()> {
         let id = handle.id();
 
@@ -105,6 +104,102 @@ impl BlockHandleStorage {
         Ok(())
     }
 
+    /// Loads handle flags of all blocks in the shard within the seqno range.
+    ///
+    /// NOTE: only blocks which still have some package entries (data, proof or proof link)
+    /// are visible here, so missing seqnos are either not downloaded or already removed by GC.
+    pub fn load_handle_flags_range(
+        &self,
+        shard: &ton_block::ShardIdent,
+        seqno_range: std::ops::RangeInclusive<u32>,
+    ) -> Result<Vec<BlockHandleFlagsEntry>> {
+        const ROOT_HASH_OFFSET: usize = BlockIdShort::SIZE_HINT;
+
+        let mut iter = self.db.package_entries.raw_iterator();
+        iter.seek((*shard, *seqno_range.start()).to_vec());
+
+        let upper_bound = (*shard, *seqno_range.end()).to_vec();
+
+        let mut result: Vec<BlockHandleFlagsEntry> = Vec::new();
+        while let Some(key) = iter.key() {
+            if key.len() < ROOT_HASH_OFFSET + 32 || key[..ROOT_HASH_OFFSET] > upper_bound[..] {
+                break;
+            }
+
+            let (_, seqno) = BlockIdShort::from_slice(&key[..ROOT_HASH_OFFSET])?;
+            let root_hash = ton_types::UInt256::from_slice(&key[ROOT_HASH_OFFSET..][..32]);
+
+            // Different entries of the same block are adjacent
+            let is_same_block = matches!(
+                result.last(),
+                Some(last) if last.seqno == seqno && last.root_hash == root_hash
+            );
+
+            if !is_same_block {
+                if let Some(meta) = self.db.block_handles.get(root_hash.as_slice())? {
+                    result.push(BlockHandleFlagsEntry {
+                        seqno,
+                        root_hash,
+                        flags: BlockMeta::from_slice(meta.as_ref())?.flags(),
+                    });
+                }
+            }
+
+            iter.next();
+        }
+        iter.status()?;
+
+        Ok(result)
+    }
+
+    /// Finds the applied block by its shard and seqno.
+    ///
+    /// NOTE: only blocks which still have some package entries are visible here
+    pub fn find_applied_block_id(
+        &self,
+        shard: &ton_block::ShardIdent,
+        seqno: u32,
+    ) -> Result<Option<ton_block::BlockIdExt>> {
+        use ton_block::Deserializable;
+
+        const ROOT_HASH_OFFSET: usize = BlockIdShort::SIZE_HINT;
+
+        let prefix = (*shard, seqno).to_vec();
+
+        let mut iter = self.db.package_entries.raw_iterator();
+        iter.seek(&prefix);
+        while let (Some(key), Some(value)) = (iter.key(), iter.value()) {
+            if key.len() != ROOT_HASH_OFFSET + 32 + 1 || !key.starts_with(&prefix) {
+                break;
+            }
+
+            let root_hash = ton_types::UInt256::from_slice(&key[ROOT_HASH_OFFSET..][..32]);
+            let file_hash = match key[ROOT_HASH_OFFSET + 32] {
+                0 => ton_types::UInt256::calc_file_hash(value),
+                _ => {
+                    ton_block::BlockProof::construct_from_bytes(value)?
+                        .proof_for
+                        .file_hash
+                }
+            };
+
+            let block_id = ton_block::BlockIdExt {
+                shard_id: *shard,
+                seq_no: seqno,
+                root_hash,
+                file_hash,
+            };
+            if matches!(self.load_handle(&block_id)?, Some(handle) if handle.meta().is_applied()) {
+                return Ok(Some(block_id));
+            }
+
+            iter.next();
+        }
+        iter.status()?;
+
+        Ok(None)
+    }
+
     pub fn load_key_block_handle(&self, seq_no: u32) -> Result<Arc<BlockHandle>> {
         let key_block_id = self
             .db
@@ -215,22 +310,7 @@ impl BlockHandleStorage {
         &self,
         direction: KeyBlocksDirection,
     ) -> impl Iterator<Item = Result<ton_block::BlockIdExt>> + '_ {
-        let mut raw_iterator = self.db.key_blocks.raw_iterator();
-        let reverse = match direction {
-            KeyBlocksDirection::ForwardFrom(seq_no) => {
-                raw_iterator.seek(seq_no.to_be_bytes());
-                false
-            }
-            KeyBlocksDirection::Backward => {
-                raw_iterator.seek_to_last();
-                true
-            }
-        };
-
-        KeyBlocksIterator {
-            raw_iterator,
-            reverse,
-        }
+        make_key_blocks_iterator(self.db.key_blocks.raw_iterator(), direction)
     }
 
     pub fn gc_handles_cache(&self, top_blocks: &TopBlocks) -> usize {
@@ -296,6 +376,28 @@ pub enum KeyBlocksDirection {
     Backward,
 }
 
+/// Wraps raw iterator over the `key_blocks` column family
+pub(super) fn make_key_blocks_iterator(
+    mut raw_iterator: rocksdb::DBRawIterator<'_>,
+    direction: KeyBlocksDirection,
+) -> impl Iterator<Item = Result<ton_block::BlockIdExt>> + '_ {
+    let reverse = match direction {
+        KeyBlocksDirection::ForwardFrom(seq_no) => {
+            raw_iterator.seek(seq_no.to_be_bytes());
+            false
+        }
+        KeyBlocksDirection::Backward => {
+            raw_iterator.seek_to_last();
+            true
+        }
+    };
+
+    KeyBlocksIterator {
+        raw_iterator,
+        reverse,
+    }
+}
+
 struct KeyBlocksIterator<'a> {
     raw_iterator: rocksdb::DBRawIterator<'a>,
     reverse: bool,
@@ -319,6 +421,13 @@ impl Iterator for KeyBlocksIterator<'_> {
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct BlockHandleFlagsEntry {
+    pub seqno: u32,
+    pub root_hash: ton_types::UInt256,
+    pub flags: BlockHandleFlags,
+}
+
 #[derive(thiserror::Error, Debug)]
 enum BlockHandleStorageError {
     #[error("Failed to create block handle")]
@@ -0,0 +1,170 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+
+use crate::db::*;
+use crate::utils::*;
+
+/// Indexes of the applied masterchain blocks by time and by seqno.
+///
+/// NOTE: entries of the blocks removed by the blocks GC are removed with them.
+/// Only key blocks remain in the time index, and top shard blocks are kept
+/// only for the retained masterchain blocks
+pub struct BlockIndexStorage {
+    db: Arc<Db>,
+}
+
+impl BlockIndexStorage {
+    pub fn new(db: Arc<Db>) -> Result<Self> {
+        Ok(Self { db })
+    }
+
+    /// Adds masterchain block with its top shard blocks to the batch
+    pub fn index_mc_block_batched(
+        &self,
+        batch: &mut rocksdb::WriteBatch,
+        block_id: &ton_block::BlockIdExt,
+        gen_utime: u32,
+        shard_blocks: &[ton_block::BlockIdExt],
+    ) {
+        let mut key = [0; 8];
+        key[..4].copy_from_slice(&gen_utime.to_be_bytes());
+        key[4..].copy_from_slice(&block_id.seq_no.to_be_bytes());
+        batch.put_cf(&self.db.mc_blocks_by_utime.cf(), key, block_id.to_vec());
+
+        batch.put_cf(
+            &self.db.mc_shard_blocks.cf(),
+            block_id.seq_no.to_be_bytes(),
+            encode_block_ids(shard_blocks),
+        );
+    }
+
+    /// Removes entries of the masterchain blocks after the specified one (e.g. after rollback)
+    pub fn remove_mc_blocks_after(&self, mc_seq_no: u32, gen_utime: u32) -> Result<()> {
+        let mut batch = rocksdb::WriteBatch::default();
+
+        // Blocks after the specified one can't be generated earlier
+        let mut key = [0; 8];
+        key[..4].copy_from_slice(&gen_utime.to_be_bytes());
+
+        let mc_blocks_by_utime_cf = self.db.mc_blocks_by_utime.cf();
+        let mut iter = self.db.mc_blocks_by_utime.raw_iterator();
+        iter.seek(key);
+        loop {
+            let key = match iter.key() {
+                Some(key) => key,
+                None => break iter.status()?,
+            };
+            if matches!(key.get(4..8), Some(seq_no) if u32::from_be_bytes(seq_no.try_into().unwrap()) > mc_seq_no)
+            {
+                batch.delete_cf(&mc_blocks_by_utime_cf, key);
+            }
+            iter.next();
+        }
+
+        batch.delete_range_cf(
+            &self.db.mc_shard_blocks.cf(),
+            (mc_seq_no + 1).to_be_bytes(),
+            u32::MAX.to_be_bytes(),
+        );
+
+        self.db.raw().write(batch)?;
+        Ok(())
+    }
+
+    /// Finds the last masterchain block generated at or before the specified time.
+    ///
+    /// Returns `None` if the time is before the retained range of the indexed
+    /// blocks, because the block might have been removed by the blocks GC
+    /// (or applied before the index was introduced)
+    pub fn find_mc_block_at_time(&self, utime: u32) -> Result<Option<ton_block::BlockIdExt>> {
+        let mut key = [0xff; 8];
+        key[..4].copy_from_slice(&utime.to_be_bytes());
+
+        let mut iter = self.db.mc_blocks_by_utime.raw_iterator();
+        iter.seek_for_prev(key);
+        let block_id = match iter.value() {
+            Some(value) => ton_block::BlockIdExt::from_slice(value)?,
+            None => {
+                iter.status()?;
+                return Ok(None);
+            }
+        };
+
+        // NOTE: only key blocks remain in the time index below the retained range,
+        // so the found key block is valid only if it is right before the range
+        match self.find_first_retained_mc_seq_no()? {
+            Some(first) if block_id.seq_no + 1 >= first => Ok(Some(block_id)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Returns the seqno of the oldest masterchain block with the indexed shard blocks
+    fn find_first_retained_mc_seq_no(&self) -> Result<Option<u32>> {
+        let mut iter = self.db.mc_shard_blocks.raw_iterator();
+        iter.seek_to_first();
+        match iter.key() {
+            Some(key) => Ok(key
+                .try_into()
+                .ok()
+                .map(|seq_no: [u8; 4]| u32::from_be_bytes(seq_no))),
+            None => {
+                iter.status()?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Loads top shard blocks of the masterchain block.
+    /// Returns `None` if the block is unknown or was removed by the blocks GC
+    pub fn load_shard_blocks(&self, mc_seq_no: u32) -> Result<Option<Vec<ton_block::BlockIdExt>>> {
+        match self.db.mc_shard_blocks.get(mc_seq_no.to_be_bytes())? {
+            Some(value) => decode_block_ids(value.as_ref()).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+fn encode_block_ids(block_ids: &[ton_block::BlockIdExt]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(block_ids.len() * ton_block::BlockIdExt::SIZE_HINT);
+    for block_id in block_ids {
+        block_id.serialize(&mut result);
+    }
+    result
+}
+
+pub(super) fn decode_block_ids(mut data: &[u8]) -> Result<Vec<ton_block::BlockIdExt>> {
+    let mut result = Vec::with_capacity(data.len() / ton_block::BlockIdExt::SIZE_HINT);
+    while !data.is_empty() {
+        result.push(ton_block::BlockIdExt::deserialize(&mut data)?);
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn block_ids_encoding() {
+        let block_ids = [
+            ton_block::BlockIdExt {
+                shard_id: ton_block::ShardIdent::with_tagged_prefix(0, 0x4000000000000000).unwrap(),
+                seq_no: 123,
+                root_hash: ton_types::UInt256::from([1u8; 32]),
+                file_hash: ton_types::UInt256::from([2u8; 32]),
+            },
+            ton_block::BlockIdExt {
+                shard_id: ton_block::ShardIdent::with_tagged_prefix(0, 0xc000000000000000).unwrap(),
+                seq_no: 456,
+                root_hash: ton_types::UInt256::from([3u8; 32]),
+                file_hash: ton_types::UInt256::from([4u8; 32]),
+            },
+        ];
+
+        let data = encode_block_ids(&block_ids);
+        assert_eq!(decode_block_ids(&data).unwrap(), block_ids);
+        assert!(decode_block_ids(&[]).unwrap().is_empty());
+        assert!(decode_block_ids(&data[1..]).is_err());
+    }
+}
@@ -41,12 +41,6 @@ impl BlockStorage {
     }
 
     fn preload(&self) -> Result<()> {
-        fn check_archive(value: &[u8]) -> Result<(), ArchivePackageError> {
-            let mut verifier = ArchivePackageVerifier::default();
-            verifier.verify(value)?;
-            verifier.final_check()
-        }
-
         let mut iter = self.db.archives.raw_iterator();
         iter.seek_to_first();
 
@@ -70,11 +64,137 @@ impl BlockStorage {
         Ok(())
     }
 
+    /// Truncates the last archive to its last valid entry if it is broken
+    /// and appends the removed entries again from the package entries.
+    ///
+    /// NOTE: entries which were already removed by blocks GC can't be restored.
+    /// This is a blocking operation, all package entries are scanned if the archive is broken.
+    ///
+    /// Returns the number of restored entries
+    pub fn repair_last_archive(&self) -> Result<usize> {
+        let mut archive_ids = self.archive_ids.write();
+        let last_id = match archive_ids.iter().next_back() {
+            Some(id) => *id,
+            None => return Ok(0),
+        };
+        let last_id_bytes = last_id.to_be_bytes();
+
+        let data = match self.db.archives.get(last_id_bytes)? {
+            Some(data) => data,
+            None => return Ok(0),
+        };
+
+        let valid_len = find_valid_archive_len(&data);
+        if valid_len == data.len() {
+            return Ok(0);
+        }
+
+        tracing::warn!(
+            archive_id = last_id,
+            valid_len,
+            total_len = data.len(),
+            "truncating broken archive"
+        );
+
+        // Collect entries which are left in the archive
+        let mut entries = FastHashSet::default();
+        if valid_len > 0 {
+            let mut reader = ArchivePackageViewReader::new(&data[..valid_len])?;
+            while let Some(entry) = reader.read_next()? {
+                entries.insert(entry.name.to_owned());
+            }
+        }
+
+        let archives_cf = self.db.archives.cf();
+        let mut batch = rocksdb::WriteBatch::default();
+        if valid_len > 0 {
+            batch.put_cf(&archives_cf, last_id_bytes, &data[..valid_len]);
+        } else {
+            batch.delete_cf(&archives_cf, last_id_bytes);
+        }
+
+        // Archived blocks which were referenced after the last archive start
+        // belong to it, so restore their entries which were truncated
+        let mut restored = 0;
+        let mut iter = self.db.package_entries.raw_iterator();
+        iter.seek_to_first();
+        while let (Some(key), Some(value)) = (iter.key(), iter.value()) {
+            if let Some(entry_id) = self.find_archived_entry(key, value, last_id)? {
+                let filename = entry_id.filename();
+                if !entries.contains(&filename) {
+                    batch.merge_cf(
+                        &archives_cf,
+                        last_id_bytes,
+                        make_archive_segment(&filename, value),
+                    );
+                    restored += 1;
+                }
+            }
+            iter.next();
+        }
+        iter.status()?;
+
+        if valid_len == 0 && restored == 0 {
+            archive_ids.remove(&last_id);
+        }
+        self.db.raw().write(batch)?;
+
+        tracing::warn!(archive_id = last_id, restored, "repaired archive");
+        Ok(restored)
+    }
+
+    /// Returns the id of the package entry if its block
+    /// was moved into the archive which starts at `archive_id` or later
+    fn find_archived_entry(
+        &self,
+        key: &[u8],
+        value: &[u8],
+        archive_id: u32,
+    ) -> Result<Option<PackageEntryId<ton_block::BlockIdExt>>> {
+        use ton_block::Deserializable;
+
+        const ROOT_HASH_OFFSET: usize = BlockIdShort::SIZE_HINT;
+
+        if key.len() != ROOT_HASH_OFFSET + 32 + 1 {
+            return Ok(None);
+        }
+        let root_hash = &key[ROOT_HASH_OFFSET..][..32];
+
+        match self.db.block_handles.get(root_hash)? {
+            Some(meta) => {
+                let meta = BlockMeta::from_slice(meta.as_ref())?;
+                if !meta.is_archived() || meta.masterchain_ref_seqno() < archive_id {
+                    return Ok(None);
+                }
+            }
+            None => return Ok(None),
+        }
+
+        let (shard_id, seq_no) = BlockIdShort::from_slice(&key[..ROOT_HASH_OFFSET])?;
+        Ok(Some(match key[ROOT_HASH_OFFSET + 32] {
+            0 => PackageEntryId::Block(ton_block::BlockIdExt {
+                shard_id,
+                seq_no,
+                root_hash: ton_types::UInt256::from_slice(root_hash),
+                file_hash: ton_types::UInt256::calc_file_hash(value),
+            }),
+            1 => {
+                PackageEntryId::Proof(ton_block::BlockProof::construct_from_bytes(value)?.proof_for)
+            }
+            2 => PackageEntryId::ProofLink(
+                ton_block::BlockProof::construct_from_bytes(value)?.proof_for,
+            ),
+            _ => return Ok(None),
+        }))
+    }
+
     pub async fn store_block_data(
         &self,
         block: &BlockStuffAug,
         meta_data: BlockMetaData,
     ) -> Result<StoreBlockResult> {
+        self.db.check_writes()?;
+
         let block_id = block.id();
         let (handle, status) = self
             .block_handle_storage
@@ -131,6 +251,8 @@ impl BlockStorage {
         proof: &BlockProofStuffAug,
         handle: BlockProofHandle,
     ) -> Result<StoreBlockResult> {
+        self.db.check_writes()?;
+
         let block_id = proof.id();
         if matches!(&handle, BlockProofHandle::Existing(handle) if handle.id() != block_id) {
             return Err(BlockStorageError::BlockHandleIdMismatch.into());
@@ -239,6 +361,8 @@ impl BlockStorage {
     }
 
     pub async fn move_into_archive(&self, handle: &BlockHandle) -> Result<()> {
+        self.db.check_writes()?;
+
         if handle.meta().is_archived() {
             return Ok(());
         }
@@ -321,6 +445,8 @@ impl BlockStorage {
         block_data: &[u8],
         block_proof_data: &[u8],
     ) -> Result<()> {
+        self.db.check_writes()?;
+
         if handle.meta().is_archived() {
             return Ok(());
         }
@@ -383,7 +509,16 @@ impl BlockStorage {
         }
     }
 
-    #[allow(unused)]
+    /// Returns the first and the last stored archive ids and the total number of archives
+    pub fn archive_ids_range(&self) -> (Option<(u32, u32)>, usize) {
+        let archive_ids = self.archive_ids.read();
+        let range = match (archive_ids.iter().next(), archive_ids.iter().next_back()) {
+            (Some(&first), Some(&last)) => Some((first, last)),
+            _ => None,
+        };
+        (range, archive_ids.len())
+    }
+
     pub fn get_archives(
         &self,
         range: impl RangeBounds<u32> + 'static,
@@ -501,6 +636,7 @@ impl BlockStorage {
             mc_package_entries_removed,
             total_package_entries_removed,
             total_handles_removed,
+            mc_index_entries_removed,
         } = tokio::task::spawn_blocking(move || {
             remove_blocks(db, max_blocks_per_batch, &top_blocks)
         })
@@ -512,6 +648,7 @@ impl BlockStorage {
             mc_package_entries_removed,
             total_package_entries_removed,
             total_handles_removed,
+            mc_index_entries_removed,
             "finished blocks GC"
         );
 
@@ -573,6 +710,42 @@ impl BlockStorage {
         Ok(())
     }
 
+    /// Merges small consecutive archives which end before `until_id` into larger ones.
+    ///
+    /// Returns the number of merged archives
+    pub async fn repack_archives(&self, until_id: u32) -> Result<usize> {
+        let _compaction_guard = self.db.delay_compaction().await;
+
+        let merges = plan_archives_repack(&self.archive_ids.read(), until_id);
+        if merges.is_empty() {
+            return Ok(0);
+        }
+
+        let archives_cf = self.db.archives.cf();
+
+        let mut merged = 0;
+        for (target_id, source_id) in merges {
+            let data = match self.db.archives.get(source_id.to_be_bytes())? {
+                Some(data) => data,
+                None => continue,
+            };
+
+            let mut archive_ids = self.archive_ids.write();
+
+            // NOTE: archive prefix is stripped by the merge operator
+            let mut batch = rocksdb::WriteBatch::default();
+            batch.merge_cf(&archives_cf, target_id.to_be_bytes(), data.as_ref());
+            batch.delete_cf(&archives_cf, source_id.to_be_bytes());
+            self.db.raw().write(batch)?;
+
+            archive_ids.remove(&source_id);
+            merged += 1;
+        }
+
+        tracing::info!(merged, until_id, "archives repack: done");
+        Ok(merged)
+    }
+
     fn add_data<I>(&self, id: &PackageEntryId<I>, data: &[u8]) -> Result<(), rocksdb::Error>
     where
         I: Borrow<ton_block::BlockIdExt> + Hash,
@@ -780,15 +953,82 @@ fn remove_blocks(
         raw.write(batch)?;
     }
 
+    // Remove index entries of the removed masterchain blocks
+    remove_mc_block_indices(&db, top_blocks, &mut stats)?;
+
     // Done
     Ok(stats)
 }
 
+fn remove_mc_block_indices(
+    db: &Db,
+    top_blocks: &TopBlocks,
+    stats: &mut BlockGcStats,
+) -> Result<()> {
+    let raw = db.raw().as_ref();
+    let mc_blocks_by_utime_cf = db.mc_blocks_by_utime.cf();
+    let mc_shard_blocks_cf = db.mc_shard_blocks.cf();
+    let key_blocks_cf = db.key_blocks.cf();
+
+    let key_blocks_readopts = db.key_blocks.new_read_config();
+
+    let mut batch = rocksdb::WriteBatch::default();
+
+    // Top shard blocks of all older masterchain blocks (including key blocks) are removed
+    let top_mc_seq_no = top_blocks.mc_block.1;
+    batch.delete_range_cf(
+        &mc_shard_blocks_cf,
+        0u32.to_be_bytes(),
+        top_mc_seq_no.to_be_bytes(),
+    );
+
+    let mut iter = db.mc_blocks_by_utime.raw_iterator();
+    iter.seek_to_first();
+
+    loop {
+        let key = match iter.key() {
+            Some(key) => key,
+            None => break iter.status()?,
+        };
+
+        // Key structure:
+        // [gen_utime, 4 bytes]
+        // [seqno, 4 bytes]
+        let seq_no = match key.get(4..8) {
+            Some(seq_no) => u32::from_be_bytes(seq_no.try_into().unwrap()),
+            None => {
+                iter.next();
+                continue;
+            }
+        };
+
+        // Keep index entries of the retained blocks
+        if seq_no >= top_mc_seq_no
+            || seq_no == 0
+            || raw
+                .get_pinned_cf_opt(&key_blocks_cf, seq_no.to_be_bytes(), &key_blocks_readopts)?
+                .is_some()
+        {
+            iter.next();
+            continue;
+        }
+
+        batch.delete_cf(&mc_blocks_by_utime_cf, key);
+        stats.mc_index_entries_removed += 1;
+
+        iter.next();
+    }
+
+    raw.write(batch)?;
+    Ok(())
+}
+
 #[derive(Debug, Copy, Clone, Default)]
 pub struct BlockGcStats {
     pub mc_package_entries_removed: usize,
     pub total_package_entries_removed: usize,
     pub total_handles_removed: usize,
+    pub mc_index_entries_removed: usize,
 }
 
 struct BlockContentsLock<'a> {
@@ -802,6 +1042,54 @@ impl<'a> AsRef<[u8]> for BlockContentsLock<'a> {
     }
 }
 
+/// Returns pairs of `(target, source)` archive ids, where the source archive
+/// can be appended to the target without breaking archive lookup by seqno
+fn plan_archives_repack(archive_ids: &BTreeSet<u32>, until_id: u32) -> Vec<(u32, u32)> {
+    let mut result = Vec::new();
+
+    let mut ids = archive_ids.iter().copied().peekable();
+    let mut target_id = match ids.next() {
+        Some(id) => id,
+        None => return result,
+    };
+
+    while let Some(id) = ids.next() {
+        // Archive contains blocks in range `[id, end_id)`
+        let end_id = match ids.peek() {
+            Some(&end_id) if end_id <= until_id => end_id,
+            _ => break,
+        };
+
+        if end_id <= target_id.saturating_add(ARCHIVE_PACKAGE_SIZE) {
+            result.push((target_id, id));
+        } else {
+            target_id = id;
+        }
+    }
+
+    result
+}
+
+fn check_archive(value: &[u8]) -> Result<(), ArchivePackageError> {
+    let mut verifier = ArchivePackageVerifier::default();
+    verifier.verify(value)?;
+    verifier.final_check()
+}
+
+/// Returns the length of the longest archive prefix which consists of complete entries
+fn find_valid_archive_len(data: &[u8]) -> usize {
+    let mut reader = match ArchivePackageViewReader::new(data) {
+        Ok(reader) => reader,
+        Err(_) => return 0,
+    };
+
+    let mut valid_len = reader.offset();
+    while let Ok(Some(_)) = reader.read_next() {
+        valid_len = reader.offset();
+    }
+    valid_len
+}
+
 pub const ARCHIVE_PACKAGE_SIZE: u32 = 100;
 pub const ARCHIVE_SLICE_SIZE: u32 = 20_000;
 
@@ -818,3 +1106,43 @@ enum BlockStorageError {
     #[error("Offset is outside of the archive slice")]
     InvalidOffset,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn archives_repack_plan() {
+        let ids = BTreeSet::from([0, 10, 30, 90, 100, 150, 170, 260, 300]);
+
+        assert!(plan_archives_repack(&ids, 0).is_empty());
+        assert!(plan_archives_repack(&BTreeSet::new(), 1000).is_empty());
+
+        assert_eq!(
+            plan_archives_repack(&ids, 1000),
+            [(0, 10), (0, 30), (0, 90), (100, 150), (100, 170)]
+        );
+
+        // Archives which end after the bound are not touched
+        assert_eq!(plan_archives_repack(&ids, 90), [(0, 10), (0, 30)]);
+    }
+
+    #[test]
+    fn valid_archive_len() {
+        let mut data = ARCHIVE_PREFIX.to_vec();
+        assert_eq!(find_valid_archive_len(&data), data.len());
+
+        data.extend_from_slice(&make_archive_segment("first", &[1; 10]));
+        data.extend_from_slice(&make_archive_segment("second", &[2; 20]));
+        let full_len = data.len();
+        assert_eq!(find_valid_archive_len(&data), full_len);
+
+        // Partially written entry
+        let segment = make_archive_segment("third", &[3; 30]);
+        data.extend_from_slice(&segment[..segment.len() - 1]);
+        assert_eq!(find_valid_archive_len(&data), full_len);
+
+        // Broken header
+        assert_eq!(find_valid_archive_len(&data[1..]), 0);
+    }
+}
@@ -0,0 +1,299 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use ton_block::HashmapAugType;
+
+use crate::db::*;
+use crate::utils::*;
+
+/// Index of the accounts by their code hash
+pub struct CodeHashStorage {
+    db: Arc<Db>,
+}
+
+impl CodeHashStorage {
+    pub fn new(db: Arc<Db>) -> Result<Self> {
+        Ok(Self { db })
+    }
+
+    /// Adds code hash changes of all accounts modified in the block to the batch
+    pub fn update_batched(
+        &self,
+        batch: &mut rocksdb::WriteBatch,
+        block: &BlockStuff,
+        shard_state: &ShardStateStuff,
+    ) -> Result<()> {
+        let code_hashes_cf = self.db.code_hashes.cf();
+        let code_hashes_by_address_cf = self.db.code_hashes_by_address.cf();
+
+        let workchain = block.id().shard_id.workchain_id() as i8;
+        let accounts = shard_state.state().read_accounts()?;
+
+        block
+            .block()
+            .read_extra()?
+            .read_account_blocks()?
+            .iterate_objects(|account_block| {
+                let account = account_block.account_id().clone().get_next_hash()?;
+
+                let new_code_hash = match accounts.account(account_block.account_id())? {
+                    Some(account) => account
+                        .read_account()?
+                        .get_code()
+                        .map(|code| code.repr_hash()),
+                    None => None,
+                };
+
+                let address_key = AddressKey { workchain, account };
+                let old_code_hash = self
+                    .db
+                    .code_hashes_by_address
+                    .get(address_key.to_bytes())?
+                    .map(|value| ton_types::UInt256::from_slice(value.as_ref()));
+
+                if old_code_hash == new_code_hash {
+                    return Ok(true);
+                }
+
+                if let Some(old_code_hash) = &old_code_hash {
+                    batch.delete_cf(&code_hashes_cf, code_hash_key(old_code_hash, &address_key));
+                }
+
+                match &new_code_hash {
+                    Some(new_code_hash) => {
+                        batch.put_cf(
+                            &code_hashes_cf,
+                            code_hash_key(new_code_hash, &address_key),
+                            [],
+                        );
+                        batch.put_cf(
+                            &code_hashes_by_address_cf,
+                            address_key.to_bytes(),
+                            new_code_hash.as_slice(),
+                        );
+                    }
+                    None => batch.delete_cf(&code_hashes_by_address_cf, address_key.to_bytes()),
+                }
+
+                Ok(true)
+            })?;
+
+        Ok(())
+    }
+
+    /// Returns the masterchain seqno since which the index contains all accounts.
+    ///
+    /// `None` means that the index was never filled or was cleared
+    pub fn load_valid_since(&self) -> Result<Option<u32>> {
+        Ok(
+            match self.db.node_states.get(CODE_HASH_INDEX_VALID_SINCE)? {
+                Some(data) if data.len() >= 4 => {
+                    Some(u32::from_le_bytes(data[..4].try_into().unwrap()))
+                }
+                _ => None,
+            },
+        )
+    }
+
+    pub fn store_valid_since(&self, mc_seq_no: u32) -> Result<()> {
+        let node_states = &self.db.node_states;
+        node_states.insert(CODE_HASH_INDEX_VALID_SINCE, mc_seq_no.to_le_bytes())?;
+        Ok(())
+    }
+
+    /// Removes all index entries together with the validity marker
+    pub fn clear(&self) -> Result<()> {
+        let mut batch = rocksdb::WriteBatch::default();
+        batch.delete_range_cf(
+            &self.db.code_hashes.cf(),
+            [0x00; CODE_HASH_KEY_LEN],
+            [0xff; CODE_HASH_KEY_LEN + 1],
+        );
+        batch.delete_range_cf(
+            &self.db.code_hashes_by_address.cf(),
+            [0x00; AddressKey::SIZE],
+            [0xff; AddressKey::SIZE + 1],
+        );
+        batch.delete_cf(&self.db.node_states.cf(), CODE_HASH_INDEX_VALID_SINCE);
+        self.db.raw().write(batch)?;
+        Ok(())
+    }
+
+    /// Adds all accounts of the shard state to the index, replacing their previous entries.
+    ///
+    /// NOTE: entries of the accounts which no longer exist are left as is
+    pub fn backfill(&self, shard_state: &ShardStateStuff) -> Result<()> {
+        let code_hashes_cf = self.db.code_hashes.cf();
+        let code_hashes_by_address_cf = self.db.code_hashes_by_address.cf();
+
+        let workchain = shard_state.block_id().shard_id.workchain_id() as i8;
+
+        let mut batch = rocksdb::WriteBatch::default();
+        shard_state.state().read_accounts()?.iterate_with_keys(
+            |account: ton_types::UInt256, shard_account| {
+                let code_hash = match shard_account.read_account()?.get_code() {
+                    Some(code) => code.repr_hash(),
+                    None => return Ok(true),
+                };
+
+                let address_key = AddressKey { workchain, account };
+                let old_code_hash = self
+                    .db
+                    .code_hashes_by_address
+                    .get(address_key.to_bytes())?
+                    .map(|value| ton_types::UInt256::from_slice(value.as_ref()));
+                match old_code_hash {
+                    Some(old_code_hash) if old_code_hash == code_hash => return Ok(true),
+                    Some(old_code_hash) => batch
+                        .delete_cf(&code_hashes_cf, code_hash_key(&old_code_hash, &address_key)),
+                    None => {}
+                }
+
+                batch.put_cf(&code_hashes_cf, code_hash_key(&code_hash, &address_key), []);
+                batch.put_cf(
+                    &code_hashes_by_address_cf,
+                    address_key.to_bytes(),
+                    code_hash.as_slice(),
+                );
+
+                if batch.len() >= BACKFILL_BATCH_LEN {
+                    self.db.raw().write(std::mem::take(&mut batch))?;
+                }
+                Ok(true)
+            },
+        )?;
+        self.db.raw().write(batch)?;
+
+        Ok(())
+    }
+
+    /// Loads at most `limit` addresses of the accounts with the specified code hash.
+    ///
+    /// Addresses are ordered by workchain and account id, iteration continues
+    /// from the address after `after` (exclusive)
+    pub fn find_accounts(
+        &self,
+        code_hash: &ton_types::UInt256,
+        after: Option<&ton_block::MsgAddressInt>,
+        limit: usize,
+    ) -> Result<Vec<ton_block::MsgAddressInt>> {
+        find_accounts_in(self.db.code_hashes.raw_iterator(), code_hash, after, limit)
+    }
+}
+
+/// Loads accounts with the specified code hash using the `code_hashes` iterator
+pub(super) fn find_accounts_in(
+    mut iter: rocksdb::DBRawIterator<'_>,
+    code_hash: &ton_types::UInt256,
+    after: Option<&ton_block::MsgAddressInt>,
+    limit: usize,
+) -> Result<Vec<ton_block::MsgAddressInt>> {
+    match after {
+        Some(address) => {
+            let address_key = AddressKey::from_address(address)?;
+            let key = code_hash_key(code_hash, &address_key);
+            iter.seek(key);
+            // Skip the `after` address itself
+            if matches!(iter.key(), Some(current) if current == key) {
+                iter.next();
+            }
+        }
+        None => iter.seek(code_hash.as_slice()),
+    }
+
+    let mut result = Vec::with_capacity(std::cmp::min(limit, 1024));
+    while result.len() < limit {
+        let key = match iter.key() {
+            Some(key) if key.len() == CODE_HASH_KEY_LEN && &key[..32] == code_hash.as_slice() => {
+                key
+            }
+            _ => break,
+        };
+
+        let address_key = AddressKey::from_slice(&key[32..]);
+        result.push(address_key.to_address()?);
+        iter.next();
+    }
+    iter.status()?;
+
+    Ok(result)
+}
+
+/// Account address in the index
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+struct AddressKey {
+    workchain: i8,
+    account: ton_types::UInt256,
+}
+
+impl AddressKey {
+    const SIZE: usize = 1 + 32;
+
+    fn from_address(address: &ton_block::MsgAddressInt) -> Result<Self> {
+        Ok(Self {
+            workchain: address.workchain_id() as i8,
+            account: address.address().get_next_hash()?,
+        })
+    }
+
+    fn from_slice(data: &[u8]) -> Self {
+        Self {
+            workchain: data[0] as i8,
+            account: ton_types::UInt256::from_slice(&data[1..Self::SIZE]),
+        }
+    }
+
+    fn to_bytes(self) -> [u8; Self::SIZE] {
+        let mut result = [0; Self::SIZE];
+        result[0] = self.workchain as u8;
+        result[1..].copy_from_slice(self.account.as_slice());
+        result
+    }
+
+    fn to_address(self) -> Result<ton_block::MsgAddressInt> {
+        ton_block::MsgAddressInt::with_standart(None, self.workchain, self.account.into())
+    }
+}
+
+fn code_hash_key(code_hash: &ton_types::UInt256, address: &AddressKey) -> [u8; CODE_HASH_KEY_LEN] {
+    let mut result = [0; CODE_HASH_KEY_LEN];
+    result[..32].copy_from_slice(code_hash.as_slice());
+    result[32..].copy_from_slice(&address.to_bytes());
+    result
+}
+
+const CODE_HASH_KEY_LEN: usize = 32 + AddressKey::SIZE;
+
+const CODE_HASH_INDEX_VALID_SINCE: &[u8] = b"code_hash_index_valid_since";
+
+/// Max number of writes in a single backfill batch
+const BACKFILL_BATCH_LEN: usize = 100_000;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn address_key_order() {
+        let code_hash = ton_types::UInt256::from([1u8; 32]);
+
+        let masterchain = AddressKey {
+            workchain: -1,
+            account: ton_types::UInt256::from([0u8; 32]),
+        };
+        let basechain = AddressKey {
+            workchain: 0,
+            account: ton_types::UInt256::from([2u8; 32]),
+        };
+
+        let address = basechain.to_address().unwrap();
+        assert_eq!(AddressKey::from_address(&address).unwrap(), basechain);
+
+        let key = code_hash_key(&code_hash, &basechain);
+        assert_eq!(&key[..32], code_hash.as_slice());
+        assert_eq!(AddressKey::from_slice(&key[32..]), basechain);
+
+        // Basechain accounts go first because workchain is stored as `u8`
+        assert!(code_hash_key(&code_hash, &basechain) < code_hash_key(&code_hash, &masterchain));
+    }
+}
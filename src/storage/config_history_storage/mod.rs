@@ -0,0 +1,157 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use ton_types::HashmapType;
+
+use crate::db::*;
+
+/// Index of the global config params changes
+pub struct ConfigHistoryStorage {
+    db: Arc<Db>,
+}
+
+impl ConfigHistoryStorage {
+    pub fn new(db: Arc<Db>) -> Result<Self> {
+        Ok(Self { db })
+    }
+
+    /// Adds changed params from the key block config to the batch
+    pub fn store_config_batched(
+        &self,
+        batch: &mut rocksdb::WriteBatch,
+        mc_seq_no: u32,
+        config: &ton_block::ConfigParams,
+    ) -> Result<()> {
+        let cf = self.db.config_history.cf();
+
+        let mut known_params = self.load_known_params()?;
+
+        let mut params = Vec::new();
+        config.config_params.iterate_slices(|mut key, value| {
+            let param_id = key.get_next_u32()?;
+            params.push((param_id, ton_types::serialize_toc(&value.reference(0)?)?));
+            Ok(true)
+        })?;
+
+        for (param_id, value) in params {
+            known_params.retain(|&id| id != param_id);
+            if self.load_latest_value(param_id, mc_seq_no)?.as_ref() != Some(&value) {
+                batch.put_cf(&cf, make_key(param_id, mc_seq_no), value);
+            }
+        }
+
+        // Remaining params were removed from the config
+        for param_id in known_params {
+            let latest = self.load_latest_value(param_id, mc_seq_no)?;
+            if !matches!(latest, Some(value) if value.is_empty()) {
+                batch.put_cf(&cf, make_key(param_id, mc_seq_no), []);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Loads all known changes of the config param
+    pub fn load_history(&self, param_id: u32) -> Result<Vec<ConfigParamChange>> {
+        let mut iter = self.db.config_history.raw_iterator();
+        iter.seek(make_key(param_id, 0));
+
+        let mut result = Vec::new();
+        while let (Some(key), Some(value)) = (iter.key(), iter.value()) {
+            let (id, mc_seq_no) = parse_key(key)?;
+            if id != param_id {
+                break;
+            }
+
+            result.push(ConfigParamChange {
+                mc_seq_no,
+                value: if value.is_empty() {
+                    None
+                } else {
+                    Some(ton_types::deserialize_tree_of_cells(&mut &*value)?)
+                },
+            });
+            iter.next();
+        }
+        iter.status()?;
+
+        Ok(result)
+    }
+
+    /// Finds the value of the param which was set at or before the specified block
+    fn load_latest_value(&self, param_id: u32, mc_seq_no: u32) -> Result<Option<Vec<u8>>> {
+        let mut iter = self.db.config_history.raw_iterator();
+        iter.seek_for_prev(make_key(param_id, mc_seq_no));
+
+        match (iter.key(), iter.value()) {
+            (Some(key), Some(value)) if parse_key(key)?.0 == param_id => Ok(Some(value.to_vec())),
+            _ => {
+                iter.status()?;
+                Ok(None)
+            }
+        }
+    }
+
+    fn load_known_params(&self) -> Result<Vec<u32>> {
+        let mut iter = self.db.config_history.raw_iterator();
+        iter.seek_to_first();
+
+        let mut result = Vec::new();
+        while let Some(key) = iter.key() {
+            let (param_id, _) = parse_key(key)?;
+            result.push(param_id);
+
+            match param_id.checked_add(1) {
+                Some(next_id) => iter.seek(make_key(next_id, 0)),
+                None => break,
+            }
+        }
+        iter.status()?;
+
+        Ok(result)
+    }
+}
+
+/// Config param value which was set in the key block
+#[derive(Debug, Clone)]
+pub struct ConfigParamChange {
+    pub mc_seq_no: u32,
+    /// Param value, `None` if the param was removed
+    pub value: Option<ton_types::Cell>,
+}
+
+fn make_key(param_id: u32, mc_seq_no: u32) -> [u8; 8] {
+    let mut key = [0; 8];
+    key[..4].copy_from_slice(&param_id.to_be_bytes());
+    key[4..].copy_from_slice(&mc_seq_no.to_be_bytes());
+    key
+}
+
+fn parse_key(key: &[u8]) -> Result<(u32, u32)> {
+    match key {
+        [a, b, c, d, e, f, g, h] => Ok((
+            u32::from_be_bytes([*a, *b, *c, *d]),
+            u32::from_be_bytes([*e, *f, *g, *h]),
+        )),
+        _ => Err(ConfigHistoryStorageError::InvalidKey.into()),
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+enum ConfigHistoryStorageError {
+    #[error("Invalid config history key")]
+    InvalidKey,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_are_ordered_by_param_and_seqno() {
+        assert!(make_key(1, u32::MAX) < make_key(2, 0));
+        assert!(make_key(1, 100) < make_key(1, 101));
+        assert_eq!(parse_key(&make_key(34, 123)).unwrap(), (34, 123));
+        assert!(parse_key(&[0; 4]).is_err());
+    }
+}
@@ -0,0 +1,177 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::db::*;
+
+/// Append-only journal of significant engine events.
+///
+/// Old events are pruned according to the `EventJournalOptions`
+pub struct EventJournalStorage {
+    db: Arc<Db>,
+    next_id: AtomicU64,
+}
+
+impl EventJournalStorage {
+    pub fn new(db: Arc<Db>) -> Result<Self> {
+        let next_id = {
+            let mut iter = db.engine_events.raw_iterator();
+            iter.seek_to_last();
+            match iter.key() {
+                Some(key) => read_event_id(key)? + 1,
+                None => {
+                    iter.status()?;
+                    0
+                }
+            }
+        };
+
+        Ok(Self {
+            db,
+            next_id: AtomicU64::new(next_id),
+        })
+    }
+
+    /// Appends a new event to the journal
+    pub fn append(&self, event: EngineEvent) -> Result<u64> {
+        self.db.check_writes()?;
+
+        let id = self.next_id.fetch_add(1, Ordering::AcqRel);
+
+        let record = EngineEventRecord {
+            id,
+            timestamp: broxus_util::now(),
+            event,
+        };
+
+        self.db
+            .engine_events
+            .insert(id.to_be_bytes(), serde_json::to_vec(&record)?)?;
+        Ok(id)
+    }
+
+    /// Removes events which are older than `min_timestamp` or which are not
+    /// among the `max_count` latest events
+    pub fn prune(&self, min_timestamp: Option<u32>, max_count: Option<u64>) -> Result<()> {
+        let mut until_id = match max_count {
+            Some(max_count) => self
+                .next_id
+                .load(Ordering::Acquire)
+                .saturating_sub(max_count),
+            None => 0,
+        };
+
+        if let Some(min_timestamp) = min_timestamp {
+            // NOTE: ids are assigned in the order of timestamps
+            let mut iter = self.db.engine_events.raw_iterator();
+            iter.seek(until_id.to_be_bytes());
+            while let (Some(key), Some(value)) = (iter.key(), iter.value()) {
+                let record: EngineEventRecord = serde_json::from_slice(value)?;
+                if record.timestamp >= min_timestamp {
+                    break;
+                }
+                until_id = read_event_id(key)? + 1;
+                iter.next();
+            }
+            iter.status()?;
+        }
+
+        if until_id > 0 {
+            let cf = self.db.engine_events.cf();
+            let write_options = self.db.engine_events.write_config();
+            self.db.raw().delete_range_cf_opt(
+                &cf,
+                0u64.to_be_bytes(),
+                until_id.to_be_bytes(),
+                write_options,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Loads at most `limit` events starting from the `from_id` (inclusive)
+    pub fn load_events(&self, from_id: u64, limit: usize) -> Result<Vec<EngineEventRecord>> {
+        let mut iter = self.db.engine_events.raw_iterator();
+        iter.seek(from_id.to_be_bytes());
+
+        let mut result = Vec::new();
+        while result.len() < limit {
+            let value = match iter.value() {
+                Some(value) => value,
+                None => break,
+            };
+            result.push(serde_json::from_slice(value)?);
+            iter.next();
+        }
+        iter.status()?;
+
+        Ok(result)
+    }
+}
+
+fn read_event_id(key: &[u8]) -> Result<u64> {
+    match key.try_into() {
+        Ok(key) => Ok(u64::from_be_bytes(key)),
+        Err(_) => Err(EventJournalStorageError::InvalidEventId.into()),
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EngineEventRecord {
+    /// Sequential event id
+    pub id: u64,
+    /// Unix timestamp in seconds
+    pub timestamp: u32,
+    pub event: EngineEvent,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EngineEvent {
+    Booted {
+        last_mc_block_id: String,
+    },
+    SyncStarted {
+        historical: bool,
+        from_mc_seqno: u32,
+    },
+    SyncFinished {
+        historical: bool,
+        last_mc_block_id: String,
+    },
+    StatesGc {
+        shards_client_mc_seqno: u32,
+        success: bool,
+    },
+    BlocksGc {
+        success: bool,
+    },
+    ArchivesGc {
+        until_id: u32,
+        success: bool,
+    },
+    HardForkDetected {
+        block_id: String,
+    },
+    InvalidArchive {
+        mc_seqno: u32,
+    },
+    RolledBack {
+        from_mc_block_id: String,
+        to_mc_block_id: String,
+    },
+    DiskSpaceExhausted {
+        available_bytes: u64,
+    },
+    LeadershipChanged {
+        is_leader: bool,
+    },
+}
+
+#[derive(thiserror::Error, Debug)]
+enum EventJournalStorageError {
+    #[error("Invalid event id")]
+    InvalidEventId,
+}
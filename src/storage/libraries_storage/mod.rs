@@ -0,0 +1,73 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+
+use crate::db::*;
+use crate::utils::FastHashSet;
+
+/// Index of the libraries published in the masterchain
+pub struct LibrariesStorage {
+    db: Arc<Db>,
+}
+
+impl LibrariesStorage {
+    pub fn new(db: Arc<Db>) -> Result<Self> {
+        Ok(Self { db })
+    }
+
+    /// Adds libraries from the masterchain state to the batch if they have changed.
+    ///
+    /// Returns `true` if the index was updated
+    pub fn update_batched(
+        &self,
+        batch: &mut rocksdb::WriteBatch,
+        libraries: &ton_block::Libraries,
+    ) -> Result<bool> {
+        let root_hash = libraries
+            .root()
+            .map(|cell| cell.repr_hash())
+            .unwrap_or_default();
+
+        let libraries_table = &self.db.libraries;
+        let indexed_root_hash = libraries_table.get(ROOT_HASH_KEY)?;
+        if matches!(indexed_root_hash, Some(value) if value.as_ref() == root_hash.as_slice()) {
+            return Ok(false);
+        }
+
+        let cf = libraries_table.cf();
+
+        let mut current = FastHashSet::default();
+        libraries.iterate_with_keys(|hash: ton_types::UInt256, descr| {
+            batch.put_cf(&cf, hash.as_slice(), ton_types::serialize_toc(descr.lib())?);
+            current.insert(hash);
+            Ok(true)
+        })?;
+
+        // Remove libraries which are no longer published
+        let mut iter = libraries_table.raw_iterator();
+        iter.seek_to_first();
+        while let Some(key) = iter.key() {
+            if key.len() == 32 && !current.contains(&ton_types::UInt256::from_slice(key)) {
+                batch.delete_cf(&cf, key);
+            }
+            iter.next();
+        }
+        iter.status()?;
+
+        batch.put_cf(&cf, ROOT_HASH_KEY, root_hash.as_slice());
+        Ok(true)
+    }
+
+    /// Loads library code by its hash
+    pub fn load_library(&self, hash: &ton_types::UInt256) -> Result<Option<ton_types::Cell>> {
+        match self.db.libraries.get(hash.as_slice())? {
+            Some(data) => Ok(Some(ton_types::deserialize_tree_of_cells(
+                &mut data.as_ref(),
+            )?)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Hash of the indexed libraries dictionary
+const ROOT_HASH_KEY: &[u8] = b"root_hash";
@@ -5,8 +5,19 @@ use anyhow::Result;
 
 pub use self::block_connection_storage::*;
 pub use self::block_handle_storage::*;
+pub use self::block_index_storage::*;
+pub use self::block_storage::ARCHIVE_PACKAGE_SIZE;
+pub use self::code_hash_storage::*;
+pub use self::config_history_storage::*;
+pub use self::event_journal_storage::*;
+pub use self::libraries_storage::*;
 pub use self::models::*;
+pub use self::node_state_storage::FailedSubscriberBlock;
+pub use self::retention_manifest::*;
 pub use self::runtime_storage::*;
+pub use self::storage_snapshot::*;
+pub use self::subscriber_delivery_storage::*;
+pub use self::validator_set_storage::*;
 
 use self::block_storage::*;
 use self::node_state_storage::*;
@@ -18,13 +29,24 @@ mod models;
 
 mod block_connection_storage;
 mod block_handle_storage;
+mod block_index_storage;
 mod block_storage;
+mod code_hash_storage;
+mod config_history_storage;
+mod event_journal_storage;
+mod libraries_storage;
 mod node_state_storage;
+mod retention_manifest;
 mod runtime_storage;
 mod shard_state_storage;
+mod storage_snapshot;
+mod subscriber_delivery_storage;
+mod validator_set_storage;
 
 pub struct Storage {
     file_db_path: PathBuf,
+    downloads_dir: PathBuf,
+    archives_dir: PathBuf,
 
     runtime_storage: Arc<RuntimeStorage>,
     block_handle_storage: Arc<BlockHandleStorage>,
@@ -32,14 +54,36 @@ pub struct Storage {
     shard_state_storage: ShardStateStorage,
     block_connection_storage: BlockConnectionStorage,
     node_state_storage: NodeStateStorage,
+    event_journal_storage: EventJournalStorage,
+    config_history_storage: ConfigHistoryStorage,
+    validator_set_storage: ValidatorSetStorage,
+    libraries_storage: LibrariesStorage,
+    block_index_storage: BlockIndexStorage,
+    code_hash_storage: CodeHashStorage,
+    subscriber_delivery_storage: SubscriberDeliveryStorage,
 }
 
 impl Storage {
     pub async fn new(
         db: Arc<Db>,
         file_db_path: PathBuf,
+        download_tmp_path: Option<PathBuf>,
         max_cell_cache_size_bytes: u64,
     ) -> Result<Arc<Self>> {
+        let downloads_dir = download_tmp_path
+            .unwrap_or_else(|| file_db_path.join("downloads"))
+            .join(PARTIAL_DOWNLOADS_DIR);
+        let archives_dir = file_db_path.join(DOWNLOADED_ARCHIVES_DIR);
+        let states_dir = file_db_path.join(DOWNLOADED_STATES_DIR);
+        tokio::fs::create_dir_all(&file_db_path).await?;
+        tokio::fs::create_dir_all(&downloads_dir).await?;
+        tokio::fs::create_dir_all(&archives_dir).await?;
+        tokio::fs::create_dir_all(&states_dir).await?;
+        remove_partial_downloads(&downloads_dir, true).await?;
+        remove_partial_downloads(&archives_dir, true).await?;
+        remove_partial_downloads(&states_dir, true).await?;
+        remove_partial_downloads(&file_db_path, false).await?;
+
         let block_handle_storage = Arc::new(BlockHandleStorage::new(db.clone())?);
         let runtime_storage = Arc::new(RuntimeStorage::new(block_handle_storage.clone()));
         let block_storage = Arc::new(BlockStorage::new(db.clone(), block_handle_storage.clone())?);
@@ -47,21 +91,38 @@ impl Storage {
             db.clone(),
             block_handle_storage.clone(),
             block_storage.clone(),
-            file_db_path.clone(),
+            downloads_dir.clone(),
+            states_dir,
             max_cell_cache_size_bytes,
         )
         .await?;
         let node_state_storage = NodeStateStorage::new(db.clone())?;
+        let event_journal_storage = EventJournalStorage::new(db.clone())?;
+        let config_history_storage = ConfigHistoryStorage::new(db.clone())?;
+        let validator_set_storage = ValidatorSetStorage::new(db.clone())?;
+        let libraries_storage = LibrariesStorage::new(db.clone())?;
+        let block_index_storage = BlockIndexStorage::new(db.clone())?;
+        let code_hash_storage = CodeHashStorage::new(db.clone())?;
+        let subscriber_delivery_storage = SubscriberDeliveryStorage::new(db.clone())?;
         let block_connection_storage = BlockConnectionStorage::new(db)?;
 
         Ok(Arc::new(Self {
             file_db_path,
+            downloads_dir,
+            archives_dir,
 
             block_handle_storage,
             block_storage,
             shard_state_storage,
             block_connection_storage,
             node_state_storage,
+            event_journal_storage,
+            config_history_storage,
+            validator_set_storage,
+            libraries_storage,
+            block_index_storage,
+            code_hash_storage,
+            subscriber_delivery_storage,
             runtime_storage,
         }))
     }
@@ -71,6 +132,25 @@ impl Storage {
         &self.file_db_path
     }
 
+    /// Directory for partially downloaded files
+    #[inline(always)]
+    pub fn downloads_dir(&self) -> &Path {
+        &self.downloads_dir
+    }
+
+    /// Directory in the file DB for the completely downloaded archives
+    #[inline(always)]
+    pub fn archives_dir(&self) -> &Path {
+        &self.archives_dir
+    }
+
+    /// Moves the completed file from the downloads dir into the file DB.
+    ///
+    /// See [`move_completed_file`]
+    pub fn move_into_file_db(&self, path: &Path, name: &str) -> Result<PathBuf> {
+        move_completed_file(path, &self.file_db_path, name).map_err(From::from)
+    }
+
     #[inline(always)]
     pub fn runtime_storage(&self) -> &RuntimeStorage {
         self.runtime_storage.as_ref()
@@ -101,6 +181,41 @@ impl Storage {
         &self.node_state_storage
     }
 
+    #[inline(always)]
+    pub fn event_journal(&self) -> &EventJournalStorage {
+        &self.event_journal_storage
+    }
+
+    #[inline(always)]
+    pub fn config_history(&self) -> &ConfigHistoryStorage {
+        &self.config_history_storage
+    }
+
+    #[inline(always)]
+    pub fn validator_sets(&self) -> &ValidatorSetStorage {
+        &self.validator_set_storage
+    }
+
+    #[inline(always)]
+    pub fn libraries(&self) -> &LibrariesStorage {
+        &self.libraries_storage
+    }
+
+    #[inline(always)]
+    pub fn block_index(&self) -> &BlockIndexStorage {
+        &self.block_index_storage
+    }
+
+    #[inline(always)]
+    pub fn code_hashes(&self) -> &CodeHashStorage {
+        &self.code_hash_storage
+    }
+
+    #[inline(always)]
+    pub fn subscriber_deliveries(&self) -> &SubscriberDeliveryStorage {
+        &self.subscriber_delivery_storage
+    }
+
     pub fn metrics(&self) -> DbMetrics {
         DbMetrics {
             shard_state_storage: self.shard_state_storage.metrics(),
@@ -112,6 +227,57 @@ impl Storage {
     }
 }
 
+/// Removes files which were left after the previous run.
+///
+/// NOTE: only the own partial downloads dir must be cleared completely,
+/// in other dirs only the `*.partial` files are removed
+async fn remove_partial_downloads(dir: &Path, remove_all: bool) -> Result<()> {
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        let is_partial = remove_all
+            || path.extension().and_then(|ext| ext.to_str()) == Some(PARTIAL_FILE_EXTENSION);
+
+        if is_partial && entry.file_type().await?.is_file() {
+            tracing::debug!(path = %entry.path().display(), "removing partial download");
+            tokio::fs::remove_file(entry.path()).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Moves the completed file into the `dir` under the `name`.
+///
+/// The file is either fully written under the `name` or not visible at all.
+/// When the file is on a different volume, it is copied next to the
+/// destination first
+pub(crate) fn move_completed_file(path: &Path, dir: &Path, name: &str) -> std::io::Result<PathBuf> {
+    let target = dir.join(name);
+
+    if let Err(e) = std::fs::rename(path, &target) {
+        if e.raw_os_error() != Some(libc::EXDEV) {
+            return Err(e);
+        }
+
+        let temp = dir.join(format!("{name}.{PARTIAL_FILE_EXTENSION}"));
+        std::fs::copy(path, &temp)?;
+        std::fs::rename(&temp, &target)?;
+        std::fs::remove_file(path)?;
+    }
+
+    Ok(target)
+}
+
+/// Subdirectory of the `download_tmp_path` which is owned by the node
+const PARTIAL_DOWNLOADS_DIR: &str = "ton-indexer-partial";
+/// Subdirectories of the file DB with the completely downloaded files.
+///
+/// NOTE: files are removed after they are imported, so these dirs
+/// are also cleared on startup
+const DOWNLOADED_ARCHIVES_DIR: &str = "archives";
+const DOWNLOADED_STATES_DIR: &str = "states";
+const PARTIAL_FILE_EXTENSION: &str = "partial";
+
 #[derive(Debug, Copy, Clone)]
 pub struct DbMetrics {
     pub shard_state_storage: ShardStateStorageMetrics,
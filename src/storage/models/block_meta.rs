@@ -66,6 +66,11 @@ impl BlockMeta {
         }
     }
 
+    /// Returns a typed snapshot of the block handle flags
+    pub fn flags(&self) -> BlockHandleFlags {
+        BlockHandleFlags::from_raw(self.flags.load(Ordering::Acquire))
+    }
+
     pub fn masterchain_ref_seqno(&self) -> u32 {
         self.flags.load(Ordering::Acquire) as u32
     }
@@ -74,6 +79,11 @@ impl BlockMeta {
         self.flags.fetch_or(seqno as u64, Ordering::Release) as u32
     }
 
+    /// Resets masterchain ref seqno after a failed write
+    pub fn clear_masterchain_ref_seqno(&self) {
+        self.flags.fetch_and(!(u32::MAX as u64), Ordering::Release);
+    }
+
     #[inline]
     pub fn gen_utime(&self) -> u32 {
         self.gen_utime
@@ -133,6 +143,10 @@ impl BlockMeta {
         self.test_flag(BLOCK_META_FLAG_HAS_NEXT_1)
     }
 
+    pub fn clear_has_next1(&self) {
+        self.clear_flag(BLOCK_META_FLAG_HAS_NEXT_1)
+    }
+
     pub fn set_has_next2(&self) -> bool {
         self.set_flag(BLOCK_META_FLAG_HAS_NEXT_2)
     }
@@ -141,6 +155,10 @@ impl BlockMeta {
         self.test_flag(BLOCK_META_FLAG_HAS_NEXT_2)
     }
 
+    pub fn clear_has_next2(&self) {
+        self.clear_flag(BLOCK_META_FLAG_HAS_NEXT_2)
+    }
+
     pub fn set_has_prev1(&self) -> bool {
         self.set_flag(BLOCK_META_FLAG_HAS_PREV_1)
     }
@@ -149,6 +167,10 @@ impl BlockMeta {
         self.test_flag(BLOCK_META_FLAG_HAS_PREV_1)
     }
 
+    pub fn clear_has_prev1(&self) {
+        self.clear_flag(BLOCK_META_FLAG_HAS_PREV_1)
+    }
+
     pub fn set_has_prev2(&self) -> bool {
         self.set_flag(BLOCK_META_FLAG_HAS_PREV_2)
     }
@@ -157,6 +179,10 @@ impl BlockMeta {
         self.test_flag(BLOCK_META_FLAG_HAS_PREV_2)
     }
 
+    pub fn clear_has_prev2(&self) {
+        self.clear_flag(BLOCK_META_FLAG_HAS_PREV_2)
+    }
+
     pub fn set_is_applied(&self) -> bool {
         self.set_flag(BLOCK_META_FLAG_IS_APPLIED)
     }
@@ -165,6 +191,15 @@ impl BlockMeta {
         self.test_flag(BLOCK_META_FLAG_IS_APPLIED)
     }
 
+    pub fn clear_is_applied(&self) {
+        self.clear_flag(BLOCK_META_FLAG_IS_APPLIED)
+    }
+
+    /// Resets applied and state flags so that the block will be applied again
+    pub fn clear_applied_and_state(&self) {
+        self.flags.fetch_and(CLEAR_APPLIED_MASK, Ordering::Release);
+    }
+
     pub fn is_key_block(&self) -> bool {
         self.test_flag(BLOCK_META_FLAG_IS_KEY_BLOCK)
     }
@@ -188,6 +223,10 @@ impl BlockMeta {
     fn set_flag(&self, flag: u64) -> bool {
         self.flags.fetch_or(flag, Ordering::Release) & flag != flag
     }
+
+    fn clear_flag(&self, flag: u64) {
+        self.flags.fetch_and(!flag, Ordering::Release);
+    }
 }
 
 impl StoredValue for BlockMeta {
@@ -247,6 +286,37 @@ impl BriefBlockMeta {
     }
 }
 
+/// Block handle flags in a stable representation
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, serde::Serialize)]
+pub struct BlockHandleFlags {
+    pub has_data: bool,
+    pub has_proof: bool,
+    pub has_proof_link: bool,
+    pub has_state: bool,
+    pub has_persistent_state: bool,
+    pub is_applied: bool,
+    pub is_key_block: bool,
+    pub is_archived: bool,
+    pub masterchain_ref_seqno: u32,
+}
+
+impl BlockHandleFlags {
+    fn from_raw(flags: u64) -> Self {
+        let test = |flag: u64| flags & flag == flag;
+        Self {
+            has_data: test(BLOCK_META_FLAG_HAS_DATA),
+            has_proof: test(BLOCK_META_FLAG_HAS_PROOF),
+            has_proof_link: test(BLOCK_META_FLAG_HAS_PROOF_LINK),
+            has_state: test(BLOCK_META_FLAG_HAS_STATE),
+            has_persistent_state: test(BLOCK_META_FLAG_HAS_PERSISTENT_STATE),
+            is_applied: test(BLOCK_META_FLAG_IS_APPLIED),
+            is_key_block: test(BLOCK_META_FLAG_IS_KEY_BLOCK),
+            is_archived: test(BLOCK_META_FLAG_MOVED_TO_ARCHIVE),
+            masterchain_ref_seqno: flags as u32,
+        }
+    }
+}
+
 const BLOCK_META_FLAG_HAS_DATA: u64 = 1 << 32;
 const BLOCK_META_FLAG_HAS_PROOF: u64 = 1 << (32 + 1);
 const BLOCK_META_FLAG_HAS_PROOF_LINK: u64 = 1 << (32 + 2);
@@ -266,6 +336,8 @@ const BLOCK_META_FLAG_MOVED_TO_ARCHIVE: u64 = 1 << (32 + 13);
 const CLEAR_DATA_MASK: u64 =
     !(BLOCK_META_FLAG_HAS_DATA | BLOCK_META_FLAG_HAS_PROOF | BLOCK_META_FLAG_HAS_PROOF_LINK);
 
+const CLEAR_APPLIED_MASK: u64 = !(BLOCK_META_FLAG_IS_APPLIED | BLOCK_META_FLAG_HAS_STATE);
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -274,4 +346,26 @@ mod tests {
     pub fn fully_on_stack() {
         assert!(!BlockMeta::default().to_vec().spilled());
     }
+
+    #[test]
+    fn typed_flags() {
+        let meta = BlockMeta::with_data(BlockMetaData {
+            is_key_block: true,
+            gen_utime: 0,
+            mc_ref_seqno: Some(123),
+        });
+        meta.set_has_data();
+        meta.set_is_applied();
+
+        assert_eq!(
+            meta.flags(),
+            BlockHandleFlags {
+                has_data: true,
+                is_applied: true,
+                is_key_block: true,
+                masterchain_ref_seqno: 123,
+                ..Default::default()
+            }
+        );
+    }
 }
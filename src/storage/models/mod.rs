@@ -1,5 +1,5 @@
 pub use block_handle::BlockHandle;
-pub use block_meta::{BlockMeta, BlockMetaData, BriefBlockMeta};
+pub use block_meta::{BlockHandleFlags, BlockMeta, BlockMetaData, BriefBlockMeta};
 
 mod block_handle;
 mod block_meta;
@@ -50,14 +50,12 @@ impl NodeStateStorage {
         ton_block::BlockIdExt::from_slice(data.as_ref())
     }
 
-    #[allow(unused)]
     pub fn store_last_uploaded_archive(&self, archive_id: u32) -> Result<()> {
         let node_states = &self.db.node_states;
         node_states.insert(LAST_UPLOADED_ARCHIVE, archive_id.to_le_bytes())?;
         Ok(())
     }
 
-    #[allow(unused)]
     pub fn load_last_uploaded_archive(&self) -> Result<Option<u32>> {
         Ok(match self.db.node_states.get(LAST_UPLOADED_ARCHIVE)? {
             Some(data) if data.len() >= 4 => {
@@ -67,10 +65,88 @@ impl NodeStateStorage {
         })
     }
 
+    pub fn store_last_delivered_mc_seqno(&self, seqno: u32) -> Result<()> {
+        let node_states = &self.db.node_states;
+        node_states.insert(LAST_DELIVERED_MC_SEQNO, seqno.to_le_bytes())?;
+        Ok(())
+    }
+
+    pub fn load_last_delivered_mc_seqno(&self) -> Result<Option<u32>> {
+        Ok(match self.db.node_states.get(LAST_DELIVERED_MC_SEQNO)? {
+            Some(data) if data.len() >= 4 => {
+                Some(u32::from_le_bytes(data[..4].try_into().unwrap()))
+            }
+            _ => None,
+        })
+    }
+
+    /// Remembers the block which was skipped by the subscriber
+    pub fn store_failed_subscriber_block(
+        &self,
+        subscriber_id: &str,
+        id: &ton_block::BlockIdExt,
+    ) -> Result<()> {
+        let node_states = &self.db.node_states;
+        node_states.insert(failed_subscriber_block_key(subscriber_id, id), id.to_vec())?;
+        Ok(())
+    }
+
+    pub fn remove_failed_subscriber_block(
+        &self,
+        subscriber_id: &str,
+        id: &ton_block::BlockIdExt,
+    ) -> Result<()> {
+        let node_states = &self.db.node_states;
+        node_states.remove(failed_subscriber_block_key(subscriber_id, id))?;
+        Ok(())
+    }
+
+    /// Returns all blocks which were skipped by subscribers
+    pub fn load_failed_subscriber_blocks(&self) -> Result<Vec<FailedSubscriberBlock>> {
+        const ID_OFFSET: usize = FAILED_SUBSCRIBER_BLOCK_PREFIX.len() + 32;
+
+        let mut iter = self.db.node_states.raw_iterator();
+        iter.seek(FAILED_SUBSCRIBER_BLOCK_PREFIX);
+
+        let mut result = Vec::new();
+        while let (Some(key), Some(value)) = (iter.key(), iter.value()) {
+            if !key.starts_with(FAILED_SUBSCRIBER_BLOCK_PREFIX) {
+                break;
+            }
+            result.push(FailedSubscriberBlock {
+                subscriber_id: String::from_utf8_lossy(key.get(ID_OFFSET..).unwrap_or_default())
+                    .into_owned(),
+                block_id: ton_block::BlockIdExt::from_slice(value)?,
+            });
+            iter.next();
+        }
+        iter.status()?;
+
+        Ok(result)
+    }
+
     pub fn store_last_mc_block_id(&self, id: &ton_block::BlockIdExt) -> Result<()> {
         self.store_block_id(&self.last_mc_block_id, id)
     }
 
+    /// Adds last mc block id to the batch.
+    ///
+    /// NOTE: cached value is updated only by [`NodeStateStorage::on_last_mc_block_id_written`]
+    pub fn store_last_mc_block_id_batched(
+        &self,
+        batch: &mut rocksdb::WriteBatch,
+        id: &ton_block::BlockIdExt,
+    ) {
+        let (_, key) = &self.last_mc_block_id;
+        batch.put_cf(&self.db.node_states.cf(), key, write_block_id_le(id));
+    }
+
+    /// Updates cached last mc block id after the batch was written
+    pub fn on_last_mc_block_id_written(&self, id: &ton_block::BlockIdExt) {
+        let (cache, _) = &self.last_mc_block_id;
+        *cache.lock() = Some(id.clone());
+    }
+
     pub fn load_last_mc_block_id(&self) -> Result<ton_block::BlockIdExt> {
         self.load_block_id(&self.last_mc_block_id)
     }
@@ -91,6 +167,43 @@ impl NodeStateStorage {
         self.load_block_id(&self.shards_client_mc_block_id)
     }
 
+    /// Remembers the indexed shard which lost its connection to the applied blocks
+    pub fn store_detached_shard(&self, shard: &ton_block::ShardIdent) -> Result<()> {
+        let node_states = &self.db.node_states;
+        node_states.insert(detached_shard_key(shard), [])?;
+        Ok(())
+    }
+
+    pub fn remove_detached_shard(&self, shard: &ton_block::ShardIdent) -> Result<()> {
+        let node_states = &self.db.node_states;
+        node_states.remove(detached_shard_key(shard))?;
+        Ok(())
+    }
+
+    /// Returns all shards which are waiting for the next persistent state
+    pub fn load_detached_shards(&self) -> Result<Vec<ton_block::ShardIdent>> {
+        let mut iter = self.db.node_states.raw_iterator();
+        iter.seek(DETACHED_SHARD_PREFIX);
+
+        let mut result = Vec::new();
+        while let Some(key) = iter.key() {
+            let key = match key.strip_prefix(DETACHED_SHARD_PREFIX) {
+                Some(key) if key.len() == 12 => key,
+                Some(_) => return Err(NodeStateStorageError::InvalidShardIdent.into()),
+                None => break,
+            };
+            let workchain = i32::from_be_bytes(key[..4].try_into().unwrap());
+            let prefix = u64::from_be_bytes(key[4..].try_into().unwrap());
+            result.push(ton_block::ShardIdent::with_tagged_prefix(
+                workchain, prefix,
+            )?);
+            iter.next();
+        }
+        iter.status()?;
+
+        Ok(result)
+    }
+
     #[inline(always)]
     fn store_block_id(
         &self,
@@ -118,6 +231,32 @@ impl NodeStateStorage {
     }
 }
 
+/// Block which was skipped by the subscriber with the `skip` error policy
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct FailedSubscriberBlock {
+    /// Durable id of the subscriber or `subscriber_{index}`
+    pub subscriber_id: String,
+    pub block_id: ton_block::BlockIdExt,
+}
+
+/// Key structure: `prefix, root_hash: [u8; 32], subscriber_id: [u8]`
+fn failed_subscriber_block_key(subscriber_id: &str, id: &ton_block::BlockIdExt) -> Vec<u8> {
+    let mut key =
+        Vec::with_capacity(FAILED_SUBSCRIBER_BLOCK_PREFIX.len() + 32 + subscriber_id.len());
+    key.extend_from_slice(FAILED_SUBSCRIBER_BLOCK_PREFIX);
+    key.extend_from_slice(id.root_hash.as_slice());
+    key.extend_from_slice(subscriber_id.as_bytes());
+    key
+}
+
+fn detached_shard_key(shard: &ton_block::ShardIdent) -> Vec<u8> {
+    let mut key = Vec::with_capacity(DETACHED_SHARD_PREFIX.len() + 12);
+    key.extend_from_slice(DETACHED_SHARD_PREFIX);
+    key.extend_from_slice(&shard.workchain_id().to_be_bytes());
+    key.extend_from_slice(&shard.shard_prefix_with_tag().to_be_bytes());
+    key
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum NodeStateStorageError {
     #[error("High block not found")]
@@ -126,6 +265,8 @@ pub enum NodeStateStorageError {
     ParamNotFound,
     #[error("Invalid block id")]
     InvalidBlockId,
+    #[error("Invalid shard ident")]
+    InvalidShardIdent,
 }
 
 type BlockIdCache = (Mutex<Option<ton_block::BlockIdExt>>, &'static [u8]);
@@ -134,7 +275,11 @@ const HISTORICAL_SYNC_LOW: &[u8] = b"background_sync_low";
 const HISTORICAL_SYNC_HIGH: &[u8] = b"background_sync_high";
 
 const LAST_UPLOADED_ARCHIVE: &[u8] = b"last_uploaded_archive";
+const LAST_DELIVERED_MC_SEQNO: &[u8] = b"last_delivered_mc_seqno";
+
+const FAILED_SUBSCRIBER_BLOCK_PREFIX: &[u8] = b"failed_subscriber_block_";
+const DETACHED_SHARD_PREFIX: &[u8] = b"detached_shard_";
 
-const LAST_MC_BLOCK_ID: &[u8] = b"LastMcBlockId";
+pub(super) const LAST_MC_BLOCK_ID: &[u8] = b"LastMcBlockId";
 const INIT_MC_BLOCK_ID: &[u8] = b"InitMcBlockId";
 const SHARDS_CLIENT_MC_BLOCK_ID: &[u8] = b"ShardsClientMcBlockId";
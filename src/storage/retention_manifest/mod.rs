@@ -0,0 +1,128 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use super::Storage;
+use crate::db::*;
+use crate::utils::*;
+
+/// Describes which data is currently retained locally
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetentionManifest {
+    /// Unix timestamp in seconds
+    pub generated_at: u32,
+    pub last_applied_mc_seqno: Option<u32>,
+    pub shards_client_mc_seqno: Option<u32>,
+    /// Masterchain blocks with stored data or proofs.
+    /// Blocks below this range were removed by GC
+    pub mc_blocks: Option<SeqnoRange>,
+    /// Masterchain blocks with stored states.
+    /// States below this range were removed by GC
+    pub mc_states: Option<SeqnoRange>,
+    /// Blocks with stored data or proofs of the top shards
+    /// of the shards client block
+    pub shard_blocks: Vec<ShardSeqnoRange>,
+    /// Ids of the stored archives
+    pub archives: Option<SeqnoRange>,
+    pub archive_count: usize,
+    /// Id of the last archive which was uploaded to the external storage
+    pub last_uploaded_archive: Option<u32>,
+}
+
+/// Inclusive range of seqnos
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct SeqnoRange {
+    pub from: u32,
+    pub to: u32,
+}
+
+/// Inclusive range of seqnos in the shard
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ShardSeqnoRange {
+    /// Shard ident in the `workchain:prefix` format
+    pub shard: String,
+    #[serde(flatten)]
+    pub range: SeqnoRange,
+}
+
+impl RetentionManifest {
+    pub(crate) fn build(db: &Db, storage: &Storage) -> Result<Self> {
+        let node_state = storage.node_state();
+        let masterchain = ton_block::ShardIdent::masterchain();
+
+        let (archives, archive_count) = storage.block_storage().archive_ids_range();
+
+        let shards_client_mc_seqno = node_state
+            .load_shards_client_mc_block_id()
+            .ok()
+            .map(|id| id.seq_no);
+
+        let top_blocks = match shards_client_mc_seqno {
+            Some(seqno) => storage
+                .block_index()
+                .load_shard_blocks(seqno)?
+                .unwrap_or_default(),
+            None => Vec::new(),
+        };
+        let mut shard_blocks = Vec::with_capacity(top_blocks.len());
+        for block_id in top_blocks {
+            if let Some(range) =
+                find_seqno_range(db.package_entries.raw_iterator(), &block_id.shard_id)?
+            {
+                shard_blocks.push(ShardSeqnoRange {
+                    shard: block_id.shard_id.to_string(),
+                    range,
+                });
+            }
+        }
+
+        Ok(Self {
+            generated_at: broxus_util::now(),
+            last_applied_mc_seqno: node_state.load_last_mc_block_id().ok().map(|id| id.seq_no),
+            shards_client_mc_seqno,
+            mc_blocks: find_seqno_range(db.package_entries.raw_iterator(), &masterchain)?,
+            mc_states: find_seqno_range(db.shard_states.raw_iterator(), &masterchain)?,
+            shard_blocks,
+            archives: archives.map(|(from, to)| SeqnoRange { from, to }),
+            archive_count,
+            last_uploaded_archive: node_state.load_last_uploaded_archive()?,
+        })
+    }
+}
+
+/// Finds the lowest and the highest seqno of the shard in the table
+/// with keys prefixed with `BlockIdShort`
+fn find_seqno_range(
+    mut iter: rocksdb::DBRawIterator<'_>,
+    shard: &ton_block::ShardIdent,
+) -> Result<Option<SeqnoRange>> {
+    let read_seqno = |iter: &rocksdb::DBRawIterator<'_>| -> Result<Option<u32>> {
+        match iter.key() {
+            Some(key) if key.len() >= BlockIdShort::SIZE_HINT => {
+                let (key_shard, seqno) = BlockIdShort::from_slice(&key[..BlockIdShort::SIZE_HINT])?;
+                Ok(if key_shard == *shard {
+                    Some(seqno)
+                } else {
+                    None
+                })
+            }
+            _ => {
+                iter.status()?;
+                Ok(None)
+            }
+        }
+    };
+
+    iter.seek((*shard, 0).to_vec());
+    let from = match read_seqno(&iter)? {
+        Some(seqno) => seqno,
+        None => return Ok(None),
+    };
+
+    iter.seek_for_prev((*shard, u32::MAX).to_vec());
+    let to = match read_seqno(&iter)? {
+        Some(seqno) => seqno,
+        None => return Ok(None),
+    };
+
+    Ok(Some(SeqnoRange { from, to }))
+}
@@ -7,13 +7,18 @@ use tokio::io::{AsyncWriteExt, BufWriter};
 use crate::utils::MappedFile;
 
 pub struct FilesContext {
+    states_dir: PathBuf,
     cells_path: PathBuf,
     cells_file: Option<BufWriter<File>>,
     hashes_path: PathBuf,
 }
 
 impl FilesContext {
-    pub async fn new<P>(downloads_dir: P, block_id: &ton_block::BlockIdExt) -> Result<Self>
+    pub async fn new<P>(
+        downloads_dir: P,
+        states_dir: PathBuf,
+        block_id: &ton_block::BlockIdExt,
+    ) -> Result<Self>
     where
         P: AsRef<Path>,
     {
@@ -43,6 +48,7 @@ impl FilesContext {
         ));
 
         Ok(Self {
+            states_dir,
             cells_path,
             cells_file,
             hashes_path,
@@ -67,15 +73,35 @@ impl FilesContext {
         Ok(mapped_file)
     }
 
+    /// Moves the completely downloaded cells file into the file DB and maps it
     pub async fn create_mapped_cells_file(&mut self) -> Result<MappedFile> {
-        let file = match self.cells_file.take() {
+        match self.cells_file.take() {
             Some(mut file) => {
                 file.flush().await?;
-                file.into_inner().into_std().await
+                file.into_inner().sync_all().await?;
             }
             None => return Err(FilesContextError::AlreadyFinalized.into()),
         };
 
+        let path = self.cells_path.clone();
+        let states_dir = self.states_dir.clone();
+        self.cells_path = tokio::task::spawn_blocking(move || {
+            let name = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .context("Invalid cells file name")?
+                .to_owned();
+            crate::storage::move_completed_file(&path, &states_dir, &name)
+                .context("Failed to move cells file into the file DB")
+        })
+        .await??;
+
+        let file = std::fs::OpenOptions::new()
+            .write(true)
+            .read(true)
+            .open(&self.cells_path)
+            .context("Failed to open cells file")?;
+
         let mapped_file = MappedFile::from_existing_file(file)?;
         Ok(mapped_file)
     }
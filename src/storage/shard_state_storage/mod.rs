@@ -27,6 +27,7 @@ pub struct ShardStateStorage {
     block_storage: Arc<BlockStorage>,
     cell_storage: Arc<CellStorage>,
     downloads_dir: Arc<PathBuf>,
+    states_dir: PathBuf,
 
     gc_lock: tokio::sync::RwLock<()>,
     min_ref_mc_state: Arc<MinRefMcState>,
@@ -39,11 +40,10 @@ impl ShardStateStorage {
         db: Arc<Db>,
         block_handle_storage: Arc<BlockHandleStorage>,
         block_storage: Arc<BlockStorage>,
-        file_db_path: PathBuf,
+        downloads_dir: PathBuf,
+        states_dir: PathBuf,
         cache_size_bytes: u64,
     ) -> Result<Self> {
-        let downloads_dir = prepare_file_db_dir(file_db_path, "downloads").await?;
-
         let cell_storage = CellStorage::new(db.clone(), cache_size_bytes)?;
 
         let res = Self {
@@ -51,7 +51,8 @@ impl ShardStateStorage {
             block_handle_storage,
             block_storage,
             cell_storage,
-            downloads_dir,
+            downloads_dir: Arc::new(downloads_dir),
+            states_dir,
             gc_lock: Default::default(),
             min_ref_mc_state: Arc::new(Default::default()),
             max_new_mc_cell_count: AtomicUsize::new(0),
@@ -89,6 +90,8 @@ impl ShardStateStorage {
         handle: &Arc<BlockHandle>,
         state: &ShardStateStuff,
     ) -> Result<bool> {
+        self.db.check_writes()?;
+
         if handle.id() != state.block_id() {
             return Err(ShardStateStorageError::BlockHandleIdMismatch.into());
         }
@@ -140,6 +143,17 @@ impl ShardStateStorage {
         block_id: &ton_block::BlockIdExt,
     ) -> Result<Arc<ShardStateStuff>> {
         let cell_id = self.load_state_root(block_id.shard_id, block_id.seq_no)?;
+        self.load_state_from_root(block_id, cell_id)
+    }
+
+    /// Loads the state using the root which was read elsewhere (e.g. from a snapshot).
+    ///
+    /// NOTE: cells are content-addressed, so they are always read from the shared storage
+    pub(crate) fn load_state_from_root(
+        &self,
+        block_id: &ton_block::BlockIdExt,
+        cell_id: UInt256,
+    ) -> Result<Arc<ShardStateStuff>> {
         let cell = self.cell_storage.load_cell(cell_id)?;
 
         ShardStateStuff::new(
@@ -154,7 +168,14 @@ impl ShardStateStorage {
         &'_ self,
         block_id: &ton_block::BlockIdExt,
     ) -> Result<(ShardStateReplaceTransaction<'_>, FilesContext)> {
-        let ctx = FilesContext::new(self.downloads_dir.as_ref(), block_id).await?;
+        self.db.check_writes()?;
+
+        let ctx = FilesContext::new(
+            self.downloads_dir.as_ref(),
+            self.states_dir.clone(),
+            block_id,
+        )
+        .await?;
 
         Ok((
             ShardStateReplaceTransaction::new(&self.db, &self.cell_storage, &self.min_ref_mc_state),
@@ -262,7 +283,7 @@ impl ShardStateStorage {
 
         // Find block id using states table
         let mc_block_id = match self
-            .find_mc_block_id(mc_seq_no)
+            .find_block_id(ton_block::ShardIdent::masterchain(), mc_seq_no)
             .context("Failed to find block id by seqno")?
         {
             Some(block_id) => block_id,
@@ -286,10 +307,11 @@ impl ShardStateStorage {
 
         // Find full min masterchain reference id
         let min_ref_mc_seqno = block_info.min_ref_mc_seqno();
-        let min_ref_block_id = match self.find_mc_block_id(min_ref_mc_seqno)? {
-            Some(block_id) => block_id,
-            None => return Ok(None),
-        };
+        let min_ref_block_id =
+            match self.find_block_id(ton_block::ShardIdent::masterchain(), min_ref_mc_seqno)? {
+                Some(block_id) => block_id,
+                None => return Ok(None),
+            };
 
         // Find block handle
         let min_ref_block_handle = match self
@@ -323,29 +345,40 @@ impl ShardStateStorage {
         }
     }
 
-    fn find_mc_block_id(&self, mc_seq_no: u32) -> Result<Option<ton_block::BlockIdExt>> {
+    /// Searches for the full block id using the stored shard state
+    pub fn find_block_id(
+        &self,
+        shard_ident: ton_block::ShardIdent,
+        seq_no: u32,
+    ) -> Result<Option<ton_block::BlockIdExt>> {
         let shard_states = &self.db.shard_states;
         Ok(shard_states
-            .get((ton_block::ShardIdent::masterchain(), mc_seq_no).to_vec())?
-            .and_then(|value| {
-                let value = value.as_ref();
-                if value.len() < 96 {
-                    return None;
-                }
-
-                let root_hash: [u8; 32] = value[32..64].try_into().unwrap();
-                let file_hash: [u8; 32] = value[64..96].try_into().unwrap();
-
-                Some(ton_block::BlockIdExt {
-                    shard_id: ton_block::ShardIdent::masterchain(),
-                    seq_no: mc_seq_no,
-                    root_hash: UInt256::from(root_hash),
-                    file_hash: UInt256::from(file_hash),
-                })
-            }))
+            .get((shard_ident, seq_no).to_vec())?
+            .and_then(|value| parse_shard_state_block_id(shard_ident, seq_no, value.as_ref())))
     }
 }
 
+/// Extracts block id from the `shard_states` entry value
+pub fn parse_shard_state_block_id(
+    shard_ident: ton_block::ShardIdent,
+    seq_no: u32,
+    value: &[u8],
+) -> Option<ton_block::BlockIdExt> {
+    if value.len() < 96 {
+        return None;
+    }
+
+    let root_hash: [u8; 32] = value[32..64].try_into().unwrap();
+    let file_hash: [u8; 32] = value[64..96].try_into().unwrap();
+
+    Some(ton_block::BlockIdExt {
+        shard_id: shard_ident,
+        seq_no,
+        root_hash: UInt256::from(root_hash),
+        file_hash: UInt256::from(file_hash),
+    })
+}
+
 #[derive(Debug, Copy, Clone)]
 pub struct ShardStateStorageMetrics {
     #[cfg(feature = "count-cells")]
@@ -356,12 +389,6 @@ pub struct ShardStateStorageMetrics {
     pub max_new_sc_cell_count: usize,
 }
 
-async fn prepare_file_db_dir(file_db_path: PathBuf, folder: &str) -> Result<Arc<PathBuf>> {
-    let dir = Arc::new(file_db_path.join(folder));
-    tokio::fs::create_dir_all(dir.as_ref()).await?;
-    Ok(dir)
-}
-
 #[derive(thiserror::Error, Debug)]
 enum ShardStateStorageError {
     #[error("Not found")]
@@ -0,0 +1,210 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use ton_types::UInt256;
+
+use super::block_connection_storage::BlockConnection;
+use super::block_handle_storage::{make_key_blocks_iterator, KeyBlocksDirection};
+use super::block_index_storage::decode_block_ids;
+use super::code_hash_storage::find_accounts_in;
+use super::models::BlockMeta;
+use super::node_state_storage::LAST_MC_BLOCK_ID;
+use super::shard_state_storage::parse_shard_state_block_id;
+use super::Storage;
+use crate::db::*;
+use crate::utils::*;
+
+/// Read-only storage view which is consistent across all column families.
+///
+/// NOTE: bypasses in-memory caches, so only data which was fully written
+/// before the snapshot creation is visible
+pub struct StorageSnapshot<'a> {
+    snapshot: DbSnapshot<'a>,
+    storage: &'a Storage,
+}
+
+impl<'a> StorageSnapshot<'a> {
+    pub(crate) fn new(db: &'a Db, storage: &'a Storage) -> Self {
+        Self {
+            snapshot: db.snapshot(),
+            storage,
+        }
+    }
+
+    pub fn load_last_applied_mc_block_id(&self) -> Result<ton_block::BlockIdExt> {
+        let db = self.snapshot.db();
+        match self.snapshot.get(&db.node_states, LAST_MC_BLOCK_ID)? {
+            Some(value) => read_block_id_le(value.as_ref())
+                .ok_or_else(|| StorageSnapshotError::InvalidBlockId.into()),
+            None => Err(StorageSnapshotError::LastMcBlockIdNotFound.into()),
+        }
+    }
+
+    pub fn load_key_block_id(&self, seq_no: u32) -> Result<ton_block::BlockIdExt> {
+        let db = self.snapshot.db();
+        match self.snapshot.get(&db.key_blocks, seq_no.to_be_bytes())? {
+            Some(value) => ton_block::BlockIdExt::from_slice(value.as_ref()),
+            None => Err(StorageSnapshotError::KeyBlockNotFound(seq_no).into()),
+        }
+    }
+
+    /// Loads the shard state which was stored before the snapshot creation.
+    ///
+    /// NOTE: the state root is resolved through the snapshot, but its cells
+    /// are loaded lazily from the shared cell storage
+    pub fn load_state(&self, block_id: &ton_block::BlockIdExt) -> Result<Arc<ShardStateStuff>> {
+        let db = self.snapshot.db();
+        let root = match self.snapshot.get(
+            &db.shard_states,
+            (block_id.shard_id, block_id.seq_no).to_vec(),
+        )? {
+            Some(value) => UInt256::from_be_bytes(value.as_ref()),
+            None => return Err(StorageSnapshotError::StateNotFound.into()),
+        };
+
+        self.storage
+            .shard_state_storage()
+            .load_state_from_root(block_id, root)
+    }
+
+    /// Loads top shard blocks of the masterchain block.
+    /// Returns `None` if the block is unknown or was removed by the blocks GC
+    pub fn load_shard_blocks(&self, mc_seq_no: u32) -> Result<Option<Vec<ton_block::BlockIdExt>>> {
+        let db = self.snapshot.db();
+        match self
+            .snapshot
+            .get(&db.mc_shard_blocks, mc_seq_no.to_be_bytes())?
+        {
+            Some(value) => decode_block_ids(value.as_ref()).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Loads at most `limit` addresses of the accounts with the specified code hash,
+    /// starting after the `after` address (exclusive).
+    ///
+    /// NOTE: allows paginating over the same index version
+    pub fn find_accounts_by_code_hash(
+        &self,
+        code_hash: &ton_types::UInt256,
+        after: Option<&ton_block::MsgAddressInt>,
+        limit: usize,
+    ) -> Result<Vec<ton_block::MsgAddressInt>> {
+        let db = self.snapshot.db();
+        find_accounts_in(
+            self.snapshot.raw_iterator(&db.code_hashes),
+            code_hash,
+            after,
+            limit,
+        )
+    }
+
+    pub fn load_block_meta(&self, block_id: &ton_block::BlockIdExt) -> Result<Option<BlockMeta>> {
+        let db = self.snapshot.db();
+        match self
+            .snapshot
+            .get(&db.block_handles, block_id.root_hash.as_slice())?
+        {
+            Some(value) => Ok(Some(BlockMeta::from_slice(value.as_ref())?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn load_block_data(&self, block_id: &ton_block::BlockIdExt) -> Result<Option<BlockStuff>> {
+        let db = self.snapshot.db();
+        match self.snapshot.get(
+            &db.package_entries,
+            PackageEntryId::Block(block_id).to_vec(),
+        )? {
+            Some(data) => Ok(Some(BlockStuff::deserialize(
+                block_id.clone(),
+                data.as_ref(),
+            )?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn load_block_data_raw(&self, block_id: &ton_block::BlockIdExt) -> Result<Option<Vec<u8>>> {
+        let db = self.snapshot.db();
+        Ok(self
+            .snapshot
+            .get(
+                &db.package_entries,
+                PackageEntryId::Block(block_id).to_vec(),
+            )?
+            .map(|data| data.to_vec()))
+    }
+
+    pub fn load_block_proof_raw(
+        &self,
+        block_id: &ton_block::BlockIdExt,
+        is_link: bool,
+    ) -> Result<Option<Vec<u8>>> {
+        let db = self.snapshot.db();
+        let key = if is_link {
+            PackageEntryId::ProofLink(block_id).to_vec()
+        } else {
+            PackageEntryId::Proof(block_id).to_vec()
+        };
+        Ok(self
+            .snapshot
+            .get(&db.package_entries, key)?
+            .map(|data| data.to_vec()))
+    }
+
+    pub fn load_connection(
+        &self,
+        block_id: &ton_block::BlockIdExt,
+        direction: BlockConnection,
+    ) -> Result<Option<ton_block::BlockIdExt>> {
+        let db = self.snapshot.db();
+        let key = block_id.root_hash.as_slice();
+        let value = match direction {
+            BlockConnection::Prev1 => self.snapshot.get(&db.prev1, key)?,
+            BlockConnection::Prev2 => self.snapshot.get(&db.prev2, key)?,
+            BlockConnection::Next1 => self.snapshot.get(&db.next1, key)?,
+            BlockConnection::Next2 => self.snapshot.get(&db.next2, key)?,
+        };
+
+        match value {
+            Some(value) => match read_block_id_le(value.as_ref()) {
+                Some(block_id) => Ok(Some(block_id)),
+                None => Err(StorageSnapshotError::InvalidBlockId.into()),
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Searches for the block with the stored shard state
+    pub fn find_block_id(
+        &self,
+        shard_ident: ton_block::ShardIdent,
+        seq_no: u32,
+    ) -> Result<Option<ton_block::BlockIdExt>> {
+        let db = self.snapshot.db();
+        Ok(self
+            .snapshot
+            .get(&db.shard_states, (shard_ident, seq_no).to_vec())?
+            .and_then(|value| parse_shard_state_block_id(shard_ident, seq_no, value.as_ref())))
+    }
+
+    pub fn key_blocks_iterator(
+        &self,
+        direction: KeyBlocksDirection,
+    ) -> impl Iterator<Item = Result<ton_block::BlockIdExt>> + '_ {
+        let db = self.snapshot.db();
+        make_key_blocks_iterator(self.snapshot.raw_iterator(&db.key_blocks), direction)
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+enum StorageSnapshotError {
+    #[error("Invalid block id")]
+    InvalidBlockId,
+    #[error("Last applied masterchain block id not found")]
+    LastMcBlockIdNotFound,
+    #[error("Key block {0} not found")]
+    KeyBlockNotFound(u32),
+    #[error("Shard state not found")]
+    StateNotFound,
+}
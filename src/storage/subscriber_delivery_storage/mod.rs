@@ -0,0 +1,144 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+
+use crate::db::*;
+use crate::utils::*;
+
+/// Blocks which were delivered to the durable subscribers
+pub struct SubscriberDeliveryStorage {
+    db: Arc<Db>,
+}
+
+impl SubscriberDeliveryStorage {
+    pub fn new(db: Arc<Db>) -> Result<Self> {
+        Ok(Self { db })
+    }
+
+    pub fn is_delivered(
+        &self,
+        subscriber_id: &str,
+        mc_seq_no: u32,
+        block_id: &ton_block::BlockIdExt,
+    ) -> Result<bool> {
+        Ok(self
+            .db
+            .subscriber_deliveries
+            .get(make_key(mc_seq_no, block_id, subscriber_id))?
+            .is_some())
+    }
+
+    /// Removes the delivery record so that the block is delivered again
+    pub fn remove_delivered(
+        &self,
+        subscriber_id: &str,
+        mc_seq_no: u32,
+        block_id: &ton_block::BlockIdExt,
+    ) -> Result<()> {
+        self.db
+            .subscriber_deliveries
+            .remove(make_key(mc_seq_no, block_id, subscriber_id))?;
+        Ok(())
+    }
+
+    pub fn mark_delivered(
+        &self,
+        subscriber_id: &str,
+        mc_seq_no: u32,
+        block_id: &ton_block::BlockIdExt,
+    ) -> Result<()> {
+        self.db
+            .subscriber_deliveries
+            .insert(make_key(mc_seq_no, block_id, subscriber_id), [])?;
+        Ok(())
+    }
+
+    /// Removes all delivery records of the subscriber so that
+    /// the replayed blocks are delivered again.
+    ///
+    /// NOTE: iterates over all records
+    ///
+    /// Returns the number of removed records
+    pub fn forget_subscriber(&self, subscriber_id: &str) -> Result<usize> {
+        let cf = self.db.subscriber_deliveries.cf();
+
+        let mut batch = rocksdb::WriteBatch::default();
+        let mut removed = 0;
+
+        let mut iter = self.db.subscriber_deliveries.raw_iterator();
+        iter.seek_to_first();
+        while let Some(key) = iter.key() {
+            if key.len() >= KEY_PREFIX_LEN && &key[KEY_PREFIX_LEN..] == subscriber_id.as_bytes() {
+                batch.delete_cf(&cf, key);
+                removed += 1;
+            }
+            iter.next();
+        }
+        iter.status()?;
+
+        if removed > 0 {
+            self.db.raw().write(batch)?;
+        }
+        Ok(removed)
+    }
+
+    /// Removes delivery records of the blocks referenced by the masterchain
+    /// blocks before `until_mc_seqno`
+    pub fn remove_outdated(&self, until_mc_seqno: u32) -> Result<()> {
+        let cf = self.db.subscriber_deliveries.cf();
+        let write_options = self.db.subscriber_deliveries.write_config();
+
+        self.db.raw().delete_range_cf_opt(
+            &cf,
+            [0; 4],
+            until_mc_seqno.to_be_bytes(),
+            write_options,
+        )?;
+        Ok(())
+    }
+}
+
+/// Key structure: `mc_seq_no: u32 BE, BlockIdShort, root_hash: [u8; 32], subscriber_id: [u8]`
+fn make_key(mc_seq_no: u32, block_id: &ton_block::BlockIdExt, subscriber_id: &str) -> Vec<u8> {
+    let mut key = Vec::with_capacity(KEY_PREFIX_LEN + subscriber_id.len());
+    key.extend_from_slice(&mc_seq_no.to_be_bytes());
+    key.extend_from_slice(&(block_id.shard_id, block_id.seq_no).to_vec());
+    key.extend_from_slice(block_id.root_hash.as_slice());
+    key.extend_from_slice(subscriber_id.as_bytes());
+    key
+}
+
+const KEY_PREFIX_LEN: usize = 4 + BlockIdShort::SIZE_HINT + 32;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_layout() {
+        let block_id = ton_block::BlockIdExt {
+            shard_id: ton_block::ShardIdent::masterchain(),
+            seq_no: 123,
+            root_hash: ton_types::UInt256::from([1u8; 32]),
+            file_hash: ton_types::UInt256::from([2u8; 32]),
+        };
+
+        let key = make_key(100, &block_id, "kafka");
+        assert_eq!(key.len(), KEY_PREFIX_LEN + 5);
+        assert_eq!(&key[..4], 100u32.to_be_bytes());
+
+        let (shard, seq_no) =
+            BlockIdShort::from_slice(&key[4..4 + BlockIdShort::SIZE_HINT]).unwrap();
+        assert_eq!(shard, block_id.shard_id);
+        assert_eq!(seq_no, block_id.seq_no);
+        assert_eq!(
+            &key[4 + BlockIdShort::SIZE_HINT..KEY_PREFIX_LEN],
+            block_id.root_hash.as_slice()
+        );
+
+        // Records are ordered by the masterchain seqno
+        assert!(make_key(99, &block_id, "kafka") < key);
+        assert!(key < make_key(256, &block_id, "kafka"));
+        assert_eq!(&key[KEY_PREFIX_LEN..], b"kafka");
+    }
+}
@@ -0,0 +1,105 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use ton_block::{Deserializable, Serializable};
+
+use crate::db::*;
+
+/// Index of the validator set transitions
+pub struct ValidatorSetStorage {
+    db: Arc<Db>,
+}
+
+impl ValidatorSetStorage {
+    pub fn new(db: Arc<Db>) -> Result<Self> {
+        Ok(Self { db })
+    }
+
+    /// Adds current validator set from the key block config to the batch if it has changed
+    pub fn store_validator_set_batched(
+        &self,
+        batch: &mut rocksdb::WriteBatch,
+        mc_seq_no: u32,
+        config: &ton_block::ConfigParams,
+    ) -> Result<()> {
+        let data = config.validator_set()?.write_to_bytes()?;
+
+        let mut iter = self.db.validator_sets.raw_iterator();
+        iter.seek_for_prev(mc_seq_no.to_be_bytes());
+        if iter.value() == Some(data.as_slice()) {
+            return Ok(());
+        }
+        iter.status()?;
+
+        batch.put_cf(&self.db.validator_sets.cf(), mc_seq_no.to_be_bytes(), data);
+        Ok(())
+    }
+
+    /// Finds validator set which was active at the specified point
+    pub fn load_validator_set(
+        &self,
+        query: ValidatorSetQuery,
+    ) -> Result<Option<ValidatorSetEntry>> {
+        let mut iter = self.db.validator_sets.raw_iterator();
+        match query {
+            ValidatorSetQuery::McSeqNo(seq_no) => {
+                iter.seek_for_prev(seq_no.to_be_bytes());
+                match (iter.key(), iter.value()) {
+                    (Some(key), Some(value)) => Ok(Some(ValidatorSetEntry::from_raw(key, value)?)),
+                    _ => {
+                        iter.status()?;
+                        Ok(None)
+                    }
+                }
+            }
+            ValidatorSetQuery::Utime(utime) => {
+                // NOTE: validator sets are changed rarely, so a reverse scan is cheap
+                iter.seek_to_last();
+                while let (Some(key), Some(value)) = (iter.key(), iter.value()) {
+                    let entry = ValidatorSetEntry::from_raw(key, value)?;
+                    if entry.validator_set.utime_since() <= utime {
+                        return Ok(Some(entry));
+                    }
+                    iter.prev();
+                }
+                iter.status()?;
+                Ok(None)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum ValidatorSetQuery {
+    /// Unix timestamp in seconds
+    Utime(u32),
+    /// Masterchain block seqno
+    McSeqNo(u32),
+}
+
+#[derive(Debug, Clone)]
+pub struct ValidatorSetEntry {
+    /// Seqno of the key block in which this set was first seen
+    pub mc_seq_no: u32,
+    pub validator_set: ton_block::ValidatorSet,
+}
+
+impl ValidatorSetEntry {
+    fn from_raw(key: &[u8], value: &[u8]) -> Result<Self> {
+        let mc_seq_no = match key.try_into() {
+            Ok(key) => u32::from_be_bytes(key),
+            Err(_) => return Err(ValidatorSetStorageError::InvalidKey.into()),
+        };
+
+        Ok(Self {
+            mc_seq_no,
+            validator_set: ton_block::ValidatorSet::construct_from_bytes(value)?,
+        })
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+enum ValidatorSetStorageError {
+    #[error("Invalid validator set key")]
+    InvalidKey,
+}
@@ -29,6 +29,11 @@ impl<'a> ArchivePackageViewReader<'a> {
     ) -> Result<Option<ArchivePackageEntryView<'a>>, ArchivePackageError> {
         ArchivePackageEntryView::read_from_view(self.data, &mut self.offset)
     }
+
+    /// Number of bytes which were successfully read
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
 }
 
 fn read_package_header(buf: &[u8], offset: &mut usize) -> Result<(), ArchivePackageError> {
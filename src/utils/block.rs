@@ -202,3 +202,64 @@ impl BlockIdExtExtension for ton_block::BlockIdExt {
         self.shard().is_masterchain()
     }
 }
+
+/// Checks whether two shards (specified by prefixes with tags) overlap
+pub fn shard_prefixes_intersect(left: u64, right: u64) -> bool {
+    let left_tag = left & left.wrapping_neg();
+    let right_tag = right & right.wrapping_neg();
+
+    // Compare only the bits of the shortest prefix
+    let tag = std::cmp::max(left_tag, right_tag);
+    let mask = !(tag.wrapping_shl(1).wrapping_sub(1));
+    (left ^ right) & mask == 0
+}
+
+/// Checks whether the shard (specified by prefix with tag) contains the account
+pub fn shard_contains_account(shard_prefix: u64, account_prefix: u64) -> bool {
+    // Treat account as the deepest possible shard
+    shard_prefixes_intersect(shard_prefix, account_prefix | 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FULL: u64 = 0x8000_0000_0000_0000;
+    const LEFT: u64 = 0x4000_0000_0000_0000;
+    const RIGHT: u64 = 0xc000_0000_0000_0000;
+
+    #[test]
+    fn shard_prefixes_intersection() {
+        assert!(shard_prefixes_intersect(FULL, FULL));
+        assert!(shard_prefixes_intersect(FULL, LEFT));
+        assert!(shard_prefixes_intersect(RIGHT, FULL));
+        assert!(!shard_prefixes_intersect(LEFT, RIGHT));
+
+        // Children of the left shard
+        assert!(shard_prefixes_intersect(LEFT, 0x2000_0000_0000_0000));
+        assert!(shard_prefixes_intersect(0x6000_0000_0000_0000, LEFT));
+        assert!(!shard_prefixes_intersect(0x6000_0000_0000_0000, RIGHT));
+        assert!(!shard_prefixes_intersect(
+            0x2000_0000_0000_0000,
+            0x6000_0000_0000_0000
+        ));
+
+        // Deepest shards
+        assert!(shard_prefixes_intersect(1, 1));
+        assert!(!shard_prefixes_intersect(1, 3));
+        assert!(shard_prefixes_intersect(1, FULL));
+    }
+
+    #[test]
+    fn shard_account_containment() {
+        assert!(shard_contains_account(FULL, 0));
+        assert!(shard_contains_account(FULL, u64::MAX));
+        assert!(shard_contains_account(LEFT, 0x7fff_ffff_ffff_ffff));
+        assert!(!shard_contains_account(LEFT, 0x8000_0000_0000_0000));
+        assert!(shard_contains_account(RIGHT, 0x8000_0000_0000_0000));
+        assert!(!shard_contains_account(
+            0x2000_0000_0000_0000,
+            0x4000_0000_0000_0000
+        ));
+    }
+}
@@ -0,0 +1,241 @@
+//! Fault injection points for the network and downloader layers.
+//!
+//! Faults are disabled until [`set_fault_injection`] is called. All decisions
+//! are made by the seeded random generator, so the same seed and the same
+//! sequence of requests produce the same faults.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use parking_lot::Mutex;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+#[derive(Debug, Clone, Default)]
+pub struct FaultInjectionConfig {
+    /// Seed of the random generator
+    pub seed: u64,
+    /// Probability of dropping the RLDP response, percent
+    pub drop_responses_percent: u8,
+    /// Probability of delaying the RLDP response, percent
+    pub delay_responses_percent: u8,
+    /// Delay of the RLDP response
+    pub response_delay: Duration,
+    /// Probability of failing the archive download, percent
+    pub drop_archives_percent: u8,
+    /// Probability of flipping a random byte in the archive chunk, percent
+    pub corrupt_archives_percent: u8,
+    /// Probability of requesting an older archive instead of the expected one, percent
+    pub stale_archives_percent: u8,
+    /// Stale archive is requested for `mc_seq_no - stale_archive_offset`
+    pub stale_archive_offset: u32,
+}
+
+/// Number of injected faults since the last [`set_fault_injection`]
+#[derive(Debug, Default)]
+pub struct FaultInjectionStats {
+    pub dropped_responses: AtomicU64,
+    pub delayed_responses: AtomicU64,
+    pub dropped_archives: AtomicU64,
+    pub corrupted_archives: AtomicU64,
+    pub stale_archives: AtomicU64,
+}
+
+impl FaultInjectionStats {
+    fn reset(&self) {
+        for counter in [
+            &self.dropped_responses,
+            &self.delayed_responses,
+            &self.dropped_archives,
+            &self.corrupted_archives,
+            &self.stale_archives,
+        ] {
+            counter.store(0, Ordering::Release);
+        }
+    }
+}
+
+/// Enables fault injection with the specified config
+pub fn set_fault_injection(config: FaultInjectionConfig) {
+    let rng = StdRng::seed_from_u64(config.seed);
+    *STATE.lock() = Some(FaultInjectionState { config, rng });
+    STATS.reset();
+}
+
+/// Disables fault injection
+pub fn reset_fault_injection() {
+    *STATE.lock() = None;
+}
+
+pub fn fault_injection_stats() -> &'static FaultInjectionStats {
+    &STATS
+}
+
+/// Returns `true` if the RLDP response must be dropped
+pub(crate) fn inject_dropped_response() -> bool {
+    roll(
+        |config| config.drop_responses_percent,
+        &STATS.dropped_responses,
+    )
+}
+
+/// Sleeps if the RLDP response must be delayed. Returns the delay
+pub(crate) async fn inject_response_delay() -> Duration {
+    let delay = with_state(|state| {
+        let delay = state.config.response_delay;
+        state
+            .roll(state.config.delay_responses_percent)
+            .then(|| delay)
+    })
+    .flatten();
+
+    match delay {
+        Some(delay) => {
+            STATS.delayed_responses.fetch_add(1, Ordering::Relaxed);
+            tokio::time::sleep(delay).await;
+            delay
+        }
+        None => Duration::ZERO,
+    }
+}
+
+/// Returns `true` if the archive download must fail
+pub(crate) fn inject_dropped_archive() -> bool {
+    roll(
+        |config| config.drop_archives_percent,
+        &STATS.dropped_archives,
+    )
+}
+
+/// Flips a random byte in the archive chunk
+pub(crate) fn inject_corrupted_chunk(chunk: &mut [u8]) {
+    if chunk.is_empty() {
+        return;
+    }
+
+    let index = with_state(|state| {
+        let percent = state.config.corrupt_archives_percent;
+        let corrupt = state.roll(percent);
+        corrupt.then(|| state.rng.gen_range(0..chunk.len()))
+    })
+    .flatten();
+
+    if let Some(index) = index {
+        STATS.corrupted_archives.fetch_add(1, Ordering::Relaxed);
+        chunk[index] ^= 0xff;
+    }
+}
+
+/// Returns seqno of the archive to request
+pub(crate) fn inject_stale_archive(mc_seq_no: u32) -> u32 {
+    let offset = with_state(|state| {
+        let offset = state.config.stale_archive_offset;
+        state
+            .roll(state.config.stale_archives_percent)
+            .then(|| offset)
+    })
+    .flatten();
+
+    match offset {
+        Some(offset) => {
+            STATS.stale_archives.fetch_add(1, Ordering::Relaxed);
+            mc_seq_no.saturating_sub(offset)
+        }
+        None => mc_seq_no,
+    }
+}
+
+fn roll<F>(percent: F, counter: &AtomicU64) -> bool
+where
+    F: FnOnce(&FaultInjectionConfig) -> u8,
+{
+    let triggered = with_state(|state| {
+        let percent = percent(&state.config);
+        state.roll(percent)
+    })
+    .unwrap_or_default();
+
+    if triggered {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+    triggered
+}
+
+fn with_state<F, R>(f: F) -> Option<R>
+where
+    F: FnOnce(&mut FaultInjectionState) -> R,
+{
+    STATE.lock().as_mut().map(f)
+}
+
+struct FaultInjectionState {
+    config: FaultInjectionConfig,
+    rng: StdRng,
+}
+
+impl FaultInjectionState {
+    fn roll(&mut self, percent: u8) -> bool {
+        // NOTE: generator is not used for disabled faults,
+        // so enabling one fault doesn't change the sequence of others
+        percent > 0 && self.rng.gen_range(0..100) < percent
+    }
+}
+
+static STATE: Mutex<Option<FaultInjectionState>> = parking_lot::const_mutex(None);
+static STATS: FaultInjectionStats = FaultInjectionStats {
+    dropped_responses: AtomicU64::new(0),
+    delayed_responses: AtomicU64::new(0),
+    dropped_archives: AtomicU64::new(0),
+    corrupted_archives: AtomicU64::new(0),
+    stale_archives: AtomicU64::new(0),
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deterministic_faults() {
+        // Disabled by default
+        assert!(!inject_dropped_archive());
+        assert_eq!(inject_stale_archive(100), 100);
+
+        let config = FaultInjectionConfig {
+            seed: 42,
+            drop_archives_percent: 50,
+            corrupt_archives_percent: 100,
+            stale_archives_percent: 100,
+            stale_archive_offset: 10,
+            ..Default::default()
+        };
+
+        set_fault_injection(config.clone());
+        let first = (0..32)
+            .map(|_| inject_dropped_archive())
+            .collect::<Vec<_>>();
+        assert!(first.contains(&true) && first.contains(&false));
+
+        let mut chunk = vec![0u8; 16];
+        inject_corrupted_chunk(&mut chunk);
+        assert_eq!(chunk.iter().filter(|&&byte| byte == 0xff).count(), 1);
+
+        assert_eq!(inject_stale_archive(100), 90);
+        assert_eq!(inject_stale_archive(5), 0);
+
+        // Same seed produces the same faults
+        set_fault_injection(config);
+        let second = (0..32)
+            .map(|_| inject_dropped_archive())
+            .collect::<Vec<_>>();
+        assert_eq!(first, second);
+        assert_eq!(
+            fault_injection_stats()
+                .dropped_archives
+                .load(Ordering::Acquire),
+            second.iter().filter(|&&dropped| dropped).count() as u64
+        );
+
+        reset_fault_injection();
+        assert!(!inject_dropped_archive());
+    }
+}
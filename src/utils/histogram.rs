@@ -0,0 +1,119 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Upper bounds of the histogram buckets in microseconds
+pub const HISTOGRAM_BUCKETS_US: [u64; 14] = [
+    100, 500, 1_000, 2_500, 5_000, 10_000, 25_000, 50_000, 100_000, 250_000, 500_000, 1_000_000,
+    5_000_000, 10_000_000,
+];
+
+/// Lock-free duration histogram with fixed buckets
+#[derive(Debug, Default)]
+pub struct Histogram {
+    /// Bucket counters. The last one is for the values above the largest bound
+    buckets: [AtomicU64; HISTOGRAM_BUCKETS_US.len() + 1],
+    count: AtomicU64,
+    sum_us: AtomicU64,
+}
+
+impl Histogram {
+    pub fn observe(&self, duration: Duration) {
+        let value_us = std::cmp::min(duration.as_micros(), u64::MAX as u128) as u64;
+
+        let index = HISTOGRAM_BUCKETS_US
+            .iter()
+            .position(|&bound| value_us <= bound)
+            .unwrap_or(HISTOGRAM_BUCKETS_US.len());
+
+        self.buckets[index].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_us.fetch_add(value_us, Ordering::Relaxed);
+    }
+
+    /// Observes the time elapsed since `started_at`
+    pub fn observe_since(&self, started_at: Instant) {
+        self.observe(started_at.elapsed());
+    }
+
+    pub fn snapshot(&self) -> HistogramSnapshot {
+        HistogramSnapshot {
+            buckets: self
+                .buckets
+                .iter()
+                .map(|counter| counter.load(Ordering::Relaxed))
+                .collect(),
+            count: self.count.load(Ordering::Relaxed),
+            sum_us: self.sum_us.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize)]
+pub struct HistogramSnapshot {
+    /// Non-cumulative bucket counters for each bound in [`HISTOGRAM_BUCKETS_US`]
+    /// and an additional one for larger values
+    pub buckets: Vec<u64>,
+    pub count: u64,
+    pub sum_us: u64,
+}
+
+impl HistogramSnapshot {
+    /// Returns an upper bound (in microseconds) of the bucket with the specified quantile,
+    /// or `None` if the quantile is in the overflow bucket or there were no observations
+    pub fn quantile_upper_bound_us(&self, quantile: f64) -> Option<u64> {
+        if self.count == 0 {
+            return None;
+        }
+
+        let target = (self.count as f64 * quantile.clamp(0.0, 1.0)).ceil() as u64;
+
+        let mut total = 0;
+        for (i, count) in self.buckets.iter().enumerate() {
+            total += count;
+            if total >= target.max(1) {
+                return HISTOGRAM_BUCKETS_US.get(i).copied();
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_are_placed_into_correct_buckets() {
+        let histogram = Histogram::default();
+        histogram.observe(Duration::from_micros(50));
+        histogram.observe(Duration::from_micros(100));
+        histogram.observe(Duration::from_millis(3));
+        histogram.observe(Duration::from_secs(60));
+
+        let snapshot = histogram.snapshot();
+        assert_eq!(snapshot.buckets.len(), HISTOGRAM_BUCKETS_US.len() + 1);
+        assert_eq!(snapshot.buckets[0], 2);
+        assert_eq!(snapshot.buckets[4], 1);
+        assert_eq!(snapshot.buckets[HISTOGRAM_BUCKETS_US.len()], 1);
+        assert_eq!(snapshot.count, 4);
+        assert_eq!(snapshot.sum_us, 50 + 100 + 3_000 + 60_000_000);
+    }
+
+    #[test]
+    fn quantiles() {
+        let histogram = Histogram::default();
+        assert_eq!(histogram.snapshot().quantile_upper_bound_us(0.5), None);
+
+        for _ in 0..9 {
+            histogram.observe(Duration::from_micros(200));
+        }
+        histogram.observe(Duration::from_secs(1));
+
+        let snapshot = histogram.snapshot();
+        assert_eq!(snapshot.quantile_upper_bound_us(0.0), Some(500));
+        assert_eq!(snapshot.quantile_upper_bound_us(0.5), Some(500));
+        assert_eq!(snapshot.quantile_upper_bound_us(0.9), Some(500));
+        assert_eq!(snapshot.quantile_upper_bound_us(0.99), Some(1_000_000));
+    }
+}
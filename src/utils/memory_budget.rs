@@ -0,0 +1,137 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Shared accounting of the memory held by the in-flight sync data.
+///
+/// Each pool reserves memory before holding the data and decides what to do
+/// when the budget is exhausted (spill to disk, skip caching, etc.).
+#[derive(Clone)]
+pub struct MemoryBudget {
+    state: Arc<MemoryBudgetState>,
+}
+
+impl MemoryBudget {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            state: Arc::new(MemoryBudgetState {
+                capacity,
+                used: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    /// Max number of bytes which can be reserved without overcommit
+    pub fn capacity(&self) -> usize {
+        self.state.capacity
+    }
+
+    /// Number of currently reserved bytes
+    pub fn used(&self) -> usize {
+        self.state.used.load(Ordering::Acquire)
+    }
+
+    /// Creates an empty reservation which can be grown later
+    pub fn reservation(&self) -> MemoryReservation {
+        MemoryReservation {
+            state: self.state.clone(),
+            bytes: 0,
+        }
+    }
+
+    /// Reserves `bytes` if they fit into the budget
+    pub fn try_acquire(&self, bytes: usize) -> Option<MemoryReservation> {
+        let mut reservation = self.reservation();
+        reservation.try_grow(bytes).then(|| reservation)
+    }
+
+    /// Reserves `bytes` even if the budget is exceeded.
+    ///
+    /// NOTE: must only be used for the data which is required to make
+    /// progress, other pools will shed their load until it is released
+    pub fn force_acquire(&self, bytes: usize) -> MemoryReservation {
+        self.state.used.fetch_add(bytes, Ordering::AcqRel);
+        MemoryReservation {
+            state: self.state.clone(),
+            bytes,
+        }
+    }
+}
+
+struct MemoryBudgetState {
+    capacity: usize,
+    used: AtomicUsize,
+}
+
+/// Memory reserved in the [`MemoryBudget`]. Released on drop
+pub struct MemoryReservation {
+    state: Arc<MemoryBudgetState>,
+    bytes: usize,
+}
+
+impl MemoryReservation {
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// Reserves `additional` bytes if they fit into the budget
+    pub fn try_grow(&mut self, additional: usize) -> bool {
+        let capacity = self.state.capacity;
+        let result = self
+            .state
+            .used
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| {
+                used.checked_add(additional)
+                    .filter(|&used| used <= capacity)
+            });
+
+        if result.is_ok() {
+            self.bytes += additional;
+        }
+        result.is_ok()
+    }
+
+    /// Returns all reserved bytes to the budget
+    pub fn release(&mut self) {
+        if self.bytes > 0 {
+            self.state.used.fetch_sub(self.bytes, Ordering::AcqRel);
+            self.bytes = 0;
+        }
+    }
+}
+
+impl Drop for MemoryReservation {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reservations_are_capped() {
+        let budget = MemoryBudget::new(100);
+
+        let mut first = budget.try_acquire(60).unwrap();
+        assert!(budget.try_acquire(50).is_none());
+        assert!(!first.try_grow(50));
+        assert!(first.try_grow(40));
+        assert_eq!(first.bytes(), 100);
+        assert_eq!(budget.used(), 100);
+
+        // Forced reservation exceeds the budget
+        let forced = budget.force_acquire(30);
+        assert_eq!(budget.used(), 130);
+
+        first.release();
+        assert_eq!(budget.used(), 30);
+        assert!(budget.try_acquire(80).is_none());
+
+        drop(forced);
+        drop(first);
+        assert_eq!(budget.used(), 0);
+        assert!(budget.try_acquire(100).is_some());
+        assert_eq!(budget.used(), 0);
+    }
+}
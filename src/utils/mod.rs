@@ -3,7 +3,11 @@ use std::collections::{HashMap, HashSet};
 pub use archive_package::*;
 pub use block::*;
 pub use block_proof::*;
+#[cfg(feature = "fault-injection")]
+pub use fault_injection::*;
+pub use histogram::*;
 pub use mapped_file::*;
+pub use memory_budget::*;
 pub use operations_pool::*;
 pub use package_entry_id::*;
 pub use progress_bar::*;
@@ -12,11 +16,16 @@ pub use shard_state_cache::*;
 pub use stored_value::*;
 pub use top_blocks::*;
 pub use with_archive_data::*;
+pub use worker_pool::*;
 
 mod archive_package;
 mod block;
 mod block_proof;
+#[cfg(feature = "fault-injection")]
+mod fault_injection;
+mod histogram;
 mod mapped_file;
+mod memory_budget;
 mod operations_pool;
 mod package_entry_id;
 mod progress_bar;
@@ -25,6 +34,7 @@ mod shard_state_cache;
 mod stored_value;
 mod top_blocks;
 mod with_archive_data;
+mod worker_pool;
 
 pub(crate) type FastHashSet<K> = HashSet<K, FastHasherState>;
 pub(crate) type FastHashMap<K, V> = HashMap<K, V, FastHasherState>;
@@ -1,6 +1,8 @@
 use std::sync::Arc;
 use std::time::Duration;
 
+use super::block::BlockIdExtDisplay;
+use super::memory_budget::*;
 use super::shard_state::ShardStateStuff;
 use super::top_blocks::*;
 use super::FastDashMap;
@@ -11,24 +13,38 @@ use crate::config::ShardStateCacheOptions;
 /// [`ShardStateStuff`]
 pub struct ShardStateCache {
     ttl: Option<Duration>,
+    prewarm: bool,
+    state_size_estimate: usize,
+    memory_budget: MemoryBudget,
     map: Option<ShardStatesMap>,
 }
 
 impl ShardStateCache {
     /// Creates cache object. If `config` is `None`, cache is disabled
-    pub fn new(config: Option<ShardStateCacheOptions>) -> Self {
+    pub fn new(config: Option<ShardStateCacheOptions>, memory_budget: MemoryBudget) -> Self {
         match config.map(|config| {
             let ttl = Duration::from_secs(config.ttl_sec);
-            (ttl, ShardStatesMap::default())
+            (
+                ttl,
+                config.prewarm,
+                config.state_size_estimate,
+                ShardStatesMap::default(),
+            )
         }) {
             // Cache is enabled and should be cleared every TTL interval
-            Some((ttl, map)) => Self {
+            Some((ttl, prewarm, state_size_estimate, map)) => Self {
                 ttl: Some(ttl),
+                prewarm,
+                state_size_estimate,
+                memory_budget,
                 map: Some(map),
             },
             // Cache is disabled
             None => Self {
                 ttl: None,
+                prewarm: false,
+                state_size_estimate: 0,
+                memory_budget,
                 map: None,
             },
         }
@@ -38,6 +54,11 @@ impl ShardStateCache {
         self.ttl
     }
 
+    /// Whether the cache is enabled and should be filled on startup
+    pub fn prewarm(&self) -> bool {
+        self.prewarm
+    }
+
     /// Retrieves a reference to the value stored under key, or None if the key doesn't exist
     /// or the cache is disabled.
     ///
@@ -45,19 +66,41 @@ impl ShardStateCache {
     pub fn get(&self, block_id: &ton_block::BlockIdExt) -> Option<Arc<ShardStateStuff>> {
         if let Some(map) = &self.map {
             let entry = map.get(block_id)?;
-            Some(entry.value().clone())
+            Some(entry.value().state.clone())
         } else {
             None
         }
     }
 
     /// Inserts a key-value pair into the cache (if enabled).
+    ///
+    /// The state is not cached if the memory budget is exhausted
     pub fn set<F>(&self, block_id: &ton_block::BlockIdExt, factory: F)
     where
         F: FnOnce() -> Arc<ShardStateStuff>,
     {
         if let Some(map) = &self.map {
-            map.insert(block_id.clone(), factory());
+            if map.contains_key(block_id) {
+                return;
+            }
+
+            match self.memory_budget.try_acquire(self.state_size_estimate) {
+                Some(memory) => {
+                    map.insert(
+                        block_id.clone(),
+                        CachedState {
+                            state: factory(),
+                            _memory: memory,
+                        },
+                    );
+                }
+                None => {
+                    tracing::debug!(
+                        block_id = %block_id.display(),
+                        "memory budget exhausted, state is not cached"
+                    );
+                }
+            }
         }
     }
 
@@ -94,4 +137,10 @@ impl ShardStateCache {
     }
 }
 
-type ShardStatesMap = FastDashMap<ton_block::BlockIdExt, Arc<ShardStateStuff>>;
+struct CachedState {
+    state: Arc<ShardStateStuff>,
+    /// Released when the state is removed from the cache
+    _memory: MemoryReservation,
+}
+
+type ShardStatesMap = FastDashMap<ton_block::BlockIdExt, CachedState>;
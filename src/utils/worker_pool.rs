@@ -0,0 +1,121 @@
+use std::panic::AssertUnwindSafe;
+use std::sync::{mpsc, Arc};
+
+use anyhow::Result;
+use parking_lot::Mutex;
+use tokio::sync::oneshot;
+
+/// Fixed set of OS threads for CPU-heavy jobs.
+///
+/// Unlike `spawn_blocking`, the number of threads is bounded,
+/// so heavy jobs can't starve the runtime and each other
+pub struct WorkerPool {
+    name: &'static str,
+    thread_count: usize,
+    jobs_tx: Mutex<mpsc::Sender<Job>>,
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+
+impl WorkerPool {
+    pub fn new(name: &'static str, thread_count: usize) -> Result<Self> {
+        let thread_count = std::cmp::max(thread_count, 1);
+
+        let (jobs_tx, jobs_rx) = mpsc::channel::<Job>();
+        let jobs_rx = Arc::new(Mutex::new(jobs_rx));
+
+        for i in 0..thread_count {
+            let jobs_rx = jobs_rx.clone();
+            std::thread::Builder::new()
+                .name(format!("{name}-{i}"))
+                .spawn(move || loop {
+                    // NOTE: lock is released before the job is executed
+                    let job = match jobs_rx.lock().recv() {
+                        Ok(job) => job,
+                        // Pool was dropped
+                        Err(_) => break,
+                    };
+                    job();
+                })?;
+        }
+
+        Ok(Self {
+            name,
+            thread_count,
+            jobs_tx: Mutex::new(jobs_tx),
+        })
+    }
+
+    pub fn thread_count(&self) -> usize {
+        self.thread_count
+    }
+
+    /// Executes the closure on one of the pool threads
+    pub async fn run<F, R>(&self, f: F) -> Result<R>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let (result_tx, result_rx) = oneshot::channel();
+
+        let job: Job = Box::new(move || {
+            // Keep the thread alive if the job panics
+            let result = std::panic::catch_unwind(AssertUnwindSafe(f));
+            result_tx.send(result).ok();
+        });
+
+        self.jobs_tx
+            .lock()
+            .send(job)
+            .map_err(|_| WorkerPoolError::Closed(self.name))?;
+
+        match result_rx.await {
+            Ok(Ok(result)) => Ok(result),
+            Ok(Err(_)) => Err(WorkerPoolError::JobPanicked(self.name).into()),
+            Err(_) => Err(WorkerPoolError::Closed(self.name).into()),
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+enum WorkerPoolError {
+    #[error("{0}: worker pool is closed")]
+    Closed(&'static str),
+    #[error("{0}: job panicked")]
+    JobPanicked(&'static str),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn runs_jobs() {
+        let pool = WorkerPool::new("test", 2).unwrap();
+        assert_eq!(pool.thread_count(), 2);
+
+        let results =
+            futures_util::future::join_all((0..16).map(|i| pool.run(move || i * 2))).await;
+        for (i, result) in results.into_iter().enumerate() {
+            assert_eq!(result.unwrap(), i * 2);
+        }
+
+        // Jobs are executed on the pool threads
+        let name = pool
+            .run(|| std::thread::current().name().map(ToOwned::to_owned))
+            .await
+            .unwrap();
+        assert!(matches!(name, Some(name) if name.starts_with("test-")));
+    }
+
+    #[tokio::test]
+    async fn survives_panics() {
+        let pool = WorkerPool::new("test", 1).unwrap();
+
+        assert!(pool
+            .run(|| -> u32 { panic!("job panicked") })
+            .await
+            .is_err());
+        assert_eq!(pool.run(|| 123).await.unwrap(), 123);
+    }
+}
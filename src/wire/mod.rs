@@ -0,0 +1,492 @@
+//! Protobuf encoders for the payloads emitted by the indexer.
+//!
+//! Schema: [`SCHEMA`] (`ton_indexer.v1.proto`). All payloads are wrapped
+//! into the `Envelope` message with the schema version.
+
+use anyhow::Result;
+use ton_block::{Deserializable, HashmapAugType, HashmapType, Serializable};
+
+use crate::engine::AccountChange;
+
+/// Protobuf schema of the encoded payloads
+pub const SCHEMA: &str = include_str!("ton_indexer.v1.proto");
+
+/// Version of the [`SCHEMA`]
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Encodes block info into the `Envelope` message.
+///
+/// Transactions are included only if `with_transactions` is set
+pub fn encode_block(
+    block_id: &ton_block::BlockIdExt,
+    block: &ton_block::Block,
+    with_transactions: bool,
+) -> Result<Vec<u8>> {
+    let info = block.read_info()?;
+
+    let mut transactions = Vec::new();
+    if with_transactions {
+        let workchain = block_id.shard_id.workchain_id();
+        block
+            .read_extra()?
+            .read_account_blocks()?
+            .iterate_objects(|account_block| {
+                account_block
+                    .transactions()
+                    .iterate_objects(|transaction| {
+                        let cell = transaction.0.serialize()?;
+                        let mut writer = ProtoWriter::default();
+                        write_transaction(
+                            &mut writer,
+                            workchain,
+                            &cell.repr_hash(),
+                            &transaction.0,
+                            None,
+                        )?;
+                        transactions.push(writer.into_inner());
+                        Ok(true)
+                    })?;
+                Ok(true)
+            })?;
+    }
+
+    let mut block_writer = ProtoWriter::default();
+    block_writer.message(1, |w| write_block_id(w, block_id));
+    block_writer.uint32(2, info.gen_utime().as_u32());
+    block_writer.uint64(3, info.start_lt());
+    block_writer.uint64(4, info.end_lt());
+    block_writer.bool(5, info.key_block());
+    block_writer.uint32(6, info.min_ref_mc_seqno());
+    for transaction in &transactions {
+        block_writer.bytes(7, transaction);
+    }
+
+    Ok(encode_envelope(
+        EnvelopePayload::Block,
+        &block_writer.into_inner(),
+    ))
+}
+
+/// Encodes transaction into the `Envelope` message.
+///
+/// Transaction BOC is included only if `boc` is specified
+pub fn encode_transaction(
+    workchain: i32,
+    hash: &ton_types::UInt256,
+    transaction: &ton_block::Transaction,
+    boc: Option<&[u8]>,
+) -> Result<Vec<u8>> {
+    let mut writer = ProtoWriter::default();
+    write_transaction(&mut writer, workchain, hash, transaction, boc)?;
+    Ok(encode_envelope(
+        EnvelopePayload::Transaction,
+        &writer.into_inner(),
+    ))
+}
+
+/// Encodes account change into the `Envelope` message
+pub fn encode_account_change(change: &AccountChange) -> Vec<u8> {
+    let mut writer = ProtoWriter::default();
+    writer.uint32(1, change.mc_seq_no);
+    writer.message(2, |w| write_block_id(w, &change.block_id));
+    writer.bytes(3, change.account.as_slice());
+    writer.bytes(4, change.old_hash.as_slice());
+    writer.bytes(5, change.new_hash.as_slice());
+    writer.uint32(6, change.transaction_count as u32);
+    encode_envelope(EnvelopePayload::AccountChange, &writer.into_inner())
+}
+
+#[derive(Copy, Clone)]
+enum EnvelopePayload {
+    Block = 2,
+    Transaction = 3,
+    AccountChange = 4,
+}
+
+fn encode_envelope(payload: EnvelopePayload, data: &[u8]) -> Vec<u8> {
+    let mut writer = ProtoWriter::with_capacity(data.len() + 16);
+    writer.uint32(1, SCHEMA_VERSION);
+    // NOTE: `oneof` fields are always written, even if empty
+    writer.key(payload as u32, WireType::Len);
+    writer.varint(data.len() as u64);
+    writer.buffer.extend_from_slice(data);
+    writer.into_inner()
+}
+
+fn write_block_id(writer: &mut ProtoWriter, block_id: &ton_block::BlockIdExt) {
+    writer.int32(1, block_id.shard_id.workchain_id());
+    writer.fixed64(2, block_id.shard_id.shard_prefix_with_tag());
+    writer.uint32(3, block_id.seq_no);
+    writer.bytes(4, block_id.root_hash.as_slice());
+    writer.bytes(5, block_id.file_hash.as_slice());
+}
+
+fn write_transaction(
+    writer: &mut ProtoWriter,
+    workchain: i32,
+    hash: &ton_types::UInt256,
+    transaction: &ton_block::Transaction,
+    boc: Option<&[u8]>,
+) -> Result<()> {
+    fn account_status(status: &ton_block::AccountStatus) -> u32 {
+        match status {
+            ton_block::AccountStatus::AccStateUninit => 0,
+            ton_block::AccountStatus::AccStateFrozen => 1,
+            ton_block::AccountStatus::AccStateActive => 2,
+            ton_block::AccountStatus::AccStateNonexist => 3,
+        }
+    }
+
+    writer.bytes(1, hash.as_slice());
+    writer.int32(2, workchain);
+    writer.bytes(3, &transaction.account_addr.get_bytestring(0));
+    writer.uint64(4, transaction.lt);
+    writer.uint32(5, transaction.now);
+    writer.bytes(6, transaction.prev_trans_hash.as_slice());
+    writer.uint64(7, transaction.prev_trans_lt);
+    writer.uint32(8, transaction.outmsg_cnt as u32);
+    writer.uint32(9, account_status(&transaction.orig_status));
+    writer.uint32(10, account_status(&transaction.end_status));
+    writer.string(11, &transaction.total_fees.grams.as_u128().to_string());
+    if let Some(boc) = boc {
+        writer.bytes(12, boc);
+    }
+    if let Some(cell) = transaction.in_msg_cell() {
+        let message = ton_block::Message::construct_from_cell(cell.clone())?;
+        writer.message(13, |w| write_message(w, &cell.repr_hash(), &message));
+    }
+    transaction.out_msgs.iterate_slices(|mut slice| {
+        let cell = slice.checked_drain_reference()?;
+        let message = ton_block::Message::construct_from_cell(cell.clone())?;
+        writer.message(14, |w| write_message(w, &cell.repr_hash(), &message));
+        Ok(true)
+    })?;
+    Ok(())
+}
+
+fn write_message(
+    writer: &mut ProtoWriter,
+    hash: &ton_types::UInt256,
+    message: &ton_block::Message,
+) {
+    writer.bytes(1, hash.as_slice());
+    match message.header() {
+        ton_block::CommonMsgInfo::IntMsgInfo(header) => {
+            writer.uint32(2, 0);
+            writer.string(3, &header.src.to_string());
+            writer.string(4, &header.dst.to_string());
+            writer.string(5, &header.value.grams.as_u128().to_string());
+            writer.uint64(6, header.created_lt);
+            writer.bool(7, header.bounce);
+            writer.bool(8, header.bounced);
+        }
+        ton_block::CommonMsgInfo::ExtInMsgInfo(header) => {
+            writer.uint32(2, 1);
+            writer.string(4, &header.dst.to_string());
+        }
+        ton_block::CommonMsgInfo::ExtOutMsgInfo(header) => {
+            writer.uint32(2, 2);
+            writer.string(3, &header.src.to_string());
+            writer.uint64(6, header.created_lt);
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+enum WireType {
+    Varint = 0,
+    I64 = 1,
+    Len = 2,
+}
+
+/// Minimal protobuf writer.
+///
+/// Fields with default values are omitted, as in proto3
+#[derive(Default)]
+struct ProtoWriter {
+    buffer: Vec<u8>,
+}
+
+impl ProtoWriter {
+    fn with_capacity(capacity: usize) -> Self {
+        Self {
+            buffer: Vec::with_capacity(capacity),
+        }
+    }
+
+    fn into_inner(self) -> Vec<u8> {
+        self.buffer
+    }
+
+    fn uint32(&mut self, field: u32, value: u32) {
+        self.uint64(field, value as u64);
+    }
+
+    fn uint64(&mut self, field: u32, value: u64) {
+        if value != 0 {
+            self.key(field, WireType::Varint);
+            self.varint(value);
+        }
+    }
+
+    fn int32(&mut self, field: u32, value: i32) {
+        // NOTE: negative values are sign-extended to 64 bits
+        self.uint64(field, value as i64 as u64);
+    }
+
+    fn bool(&mut self, field: u32, value: bool) {
+        self.uint64(field, value as u64);
+    }
+
+    fn fixed64(&mut self, field: u32, value: u64) {
+        if value != 0 {
+            self.key(field, WireType::I64);
+            self.buffer.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    fn bytes(&mut self, field: u32, value: &[u8]) {
+        if !value.is_empty() {
+            self.key(field, WireType::Len);
+            self.varint(value.len() as u64);
+            self.buffer.extend_from_slice(value);
+        }
+    }
+
+    fn string(&mut self, field: u32, value: &str) {
+        self.bytes(field, value.as_bytes());
+    }
+
+    fn message<F>(&mut self, field: u32, f: F)
+    where
+        F: FnOnce(&mut ProtoWriter),
+    {
+        let mut nested = ProtoWriter::default();
+        f(&mut nested);
+
+        self.key(field, WireType::Len);
+        self.varint(nested.buffer.len() as u64);
+        self.buffer.extend_from_slice(&nested.buffer);
+    }
+
+    fn key(&mut self, field: u32, wire_type: WireType) {
+        self.varint(((field as u64) << 3) | wire_type as u64);
+    }
+
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.buffer.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        self.buffer.push(value as u8);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scalar_fields() {
+        let mut writer = ProtoWriter::default();
+        writer.uint32(1, 150);
+        assert_eq!(writer.into_inner(), [0x08, 0x96, 0x01]);
+
+        let mut writer = ProtoWriter::default();
+        writer.int32(1, -1);
+        assert_eq!(
+            writer.into_inner(),
+            [0x08, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01]
+        );
+
+        let mut writer = ProtoWriter::default();
+        writer.fixed64(2, 0x8000000000000000);
+        assert_eq!(writer.into_inner(), [0x11, 0, 0, 0, 0, 0, 0, 0, 0x80]);
+
+        let mut writer = ProtoWriter::default();
+        writer.string(2, "testing");
+        assert_eq!(
+            writer.into_inner(),
+            [0x12, 0x07, 0x74, 0x65, 0x73, 0x74, 0x69, 0x6e, 0x67]
+        );
+
+        // Default values are omitted
+        let mut writer = ProtoWriter::default();
+        writer.uint64(1, 0);
+        writer.bool(2, false);
+        writer.bytes(3, &[]);
+        assert!(writer.into_inner().is_empty());
+    }
+
+    #[test]
+    fn nested_messages() {
+        let mut writer = ProtoWriter::default();
+        writer.message(3, |w| w.uint32(1, 150));
+        assert_eq!(writer.into_inner(), [0x1a, 0x03, 0x08, 0x96, 0x01]);
+
+        let envelope = encode_envelope(EnvelopePayload::Block, &[]);
+        assert_eq!(envelope, [0x08, SCHEMA_VERSION as u8, 0x12, 0x00]);
+    }
+
+    #[test]
+    fn account_change() {
+        let change = AccountChange {
+            mc_seq_no: 1,
+            block_id: ton_block::BlockIdExt {
+                shard_id: ton_block::ShardIdent::masterchain(),
+                seq_no: 2,
+                root_hash: ton_types::UInt256::from([1u8; 32]),
+                file_hash: ton_types::UInt256::from([2u8; 32]),
+            },
+            account: ton_types::UInt256::from([3u8; 32]),
+            old_hash: ton_types::UInt256::from([4u8; 32]),
+            new_hash: ton_types::UInt256::from([5u8; 32]),
+            transaction_count: 1,
+        };
+
+        let data = encode_account_change(&change);
+        // Envelope header
+        assert_eq!(&data[..3], [0x08, 0x01, 0x22]);
+        // Payload starts with `mc_seqno`
+        assert_eq!(&data[5..7], [0x08, 0x01]);
+        assert!(SCHEMA.contains("package ton_indexer.v1;"));
+    }
+
+    /// Mirror of the schema for decoding with `prost`
+    mod proto {
+        #[derive(Clone, PartialEq, prost::Message)]
+        pub struct Envelope {
+            #[prost(uint32, tag = "1")]
+            pub version: u32,
+            #[prost(message, optional, tag = "3")]
+            pub transaction: Option<Transaction>,
+        }
+
+        #[derive(Clone, PartialEq, prost::Message)]
+        pub struct Transaction {
+            #[prost(bytes = "vec", tag = "1")]
+            pub hash: Vec<u8>,
+            #[prost(int32, tag = "2")]
+            pub workchain: i32,
+            #[prost(bytes = "vec", tag = "3")]
+            pub account: Vec<u8>,
+            #[prost(uint64, tag = "4")]
+            pub lt: u64,
+            #[prost(uint32, tag = "5")]
+            pub now: u32,
+            #[prost(uint32, tag = "8")]
+            pub out_msg_count: u32,
+            #[prost(int32, tag = "10")]
+            pub end_status: i32,
+            #[prost(string, tag = "11")]
+            pub total_fees: String,
+            #[prost(bytes = "vec", tag = "12")]
+            pub boc: Vec<u8>,
+            #[prost(message, optional, tag = "13")]
+            pub in_msg: Option<Message>,
+            #[prost(message, repeated, tag = "14")]
+            pub out_msgs: Vec<Message>,
+        }
+
+        #[derive(Clone, PartialEq, prost::Message)]
+        pub struct Message {
+            #[prost(bytes = "vec", tag = "1")]
+            pub hash: Vec<u8>,
+            #[prost(int32, tag = "2")]
+            pub r#type: i32,
+            #[prost(string, tag = "3")]
+            pub src: String,
+            #[prost(string, tag = "4")]
+            pub dst: String,
+            #[prost(string, tag = "5")]
+            pub value: String,
+            #[prost(uint64, tag = "6")]
+            pub created_lt: u64,
+            #[prost(bool, tag = "7")]
+            pub bounce: bool,
+            #[prost(bool, tag = "8")]
+            pub bounced: bool,
+        }
+    }
+
+    #[test]
+    fn transaction_round_trip() {
+        use prost::Message;
+
+        let address = |byte: u8| {
+            ton_block::MsgAddressInt::with_standart(
+                None,
+                0,
+                ton_types::UInt256::from([byte; 32]).into(),
+            )
+            .unwrap()
+        };
+
+        let account = ton_types::UInt256::from([1u8; 32]);
+        let mut transaction = ton_block::Transaction::with_address_and_status(
+            account.clone().into(),
+            ton_block::AccountStatus::AccStateActive,
+        );
+        transaction.lt = 1000;
+        transaction.now = 1600000000;
+        transaction.end_status = ton_block::AccountStatus::AccStateActive;
+
+        let mut in_msg_header = ton_block::InternalMessageHeader::with_addresses(
+            address(2),
+            address(1),
+            ton_block::CurrencyCollection::with_grams(1_000_000_000),
+        );
+        in_msg_header.created_lt = 900;
+        in_msg_header.bounce = true;
+        let in_msg = ton_block::Message::with_int_header(in_msg_header);
+        transaction.write_in_msg(Some(&in_msg)).unwrap();
+
+        let out_msg = ton_block::Message::with_ext_out_header(ton_block::ExtOutMessageHeader {
+            src: address(1),
+            created_lt: 1001,
+            ..Default::default()
+        });
+        transaction.add_out_message(&out_msg).unwrap();
+
+        let cell = transaction.serialize().unwrap();
+        let data = encode_transaction(0, &cell.repr_hash(), &transaction, Some(&[0xb5])).unwrap();
+
+        let envelope = proto::Envelope::decode(data.as_slice()).unwrap();
+        assert_eq!(envelope.version, SCHEMA_VERSION);
+
+        let decoded = envelope.transaction.unwrap();
+        assert_eq!(decoded.hash, cell.repr_hash().as_slice());
+        assert_eq!(decoded.workchain, 0);
+        assert_eq!(decoded.account, account.as_slice());
+        assert_eq!(decoded.lt, 1000);
+        assert_eq!(decoded.now, 1600000000);
+        assert_eq!(decoded.out_msg_count, 1);
+        assert_eq!(decoded.end_status, 2);
+        assert_eq!(decoded.total_fees, "0");
+        assert_eq!(decoded.boc, [0xb5]);
+
+        let decoded_in_msg = decoded.in_msg.unwrap();
+        assert_eq!(
+            decoded_in_msg.hash,
+            in_msg.serialize().unwrap().repr_hash().as_slice()
+        );
+        assert_eq!(decoded_in_msg.r#type, 0);
+        assert_eq!(decoded_in_msg.src, address(2).to_string());
+        assert_eq!(decoded_in_msg.dst, address(1).to_string());
+        assert_eq!(decoded_in_msg.value, "1000000000");
+        assert_eq!(decoded_in_msg.created_lt, 900);
+        assert!(decoded_in_msg.bounce);
+        assert!(!decoded_in_msg.bounced);
+
+        assert_eq!(decoded.out_msgs.len(), 1);
+        let decoded_out_msg = &decoded.out_msgs[0];
+        assert_eq!(
+            decoded_out_msg.hash,
+            out_msg.serialize().unwrap().repr_hash().as_slice()
+        );
+        assert_eq!(decoded_out_msg.r#type, 2);
+        assert_eq!(decoded_out_msg.src, address(1).to_string());
+        assert!(decoded_out_msg.dst.is_empty());
+        assert_eq!(decoded_out_msg.created_lt, 1001);
+    }
+}